@@ -1,7 +1,7 @@
 import { useCallback, useEffect, useRef, useState } from 'react'
 import Editor, { type OnMount } from '@monaco-editor/react'
 import type { editor } from 'monaco-editor'
-import { branchyLanguage, branchyLanguageId } from './branchyLanguage'
+import { branchyLanguage, branchyLanguageId, registerBranchyProviders } from './branchyLanguage'
 
 let monacoGlobal: typeof import('monaco-editor') | null = null
 
@@ -12,6 +12,8 @@ type TraceSpan = {
   start_column: number
   end_line: number
   end_column: number
+  /** Set (non-zero) for spans in included files. */
+  file?: number
 }
 
 type RunOk = { result: string; trace?: TraceSpan[] }
@@ -24,6 +26,16 @@ type RunErr = {
 }
 
 type FormatOk = { formatted: string }
+type ShareCreated = { id: string; seed: number }
+type Diagnostic = { severity: 'error' | 'warning'; message: string; span?: TraceSpan; file?: string }
+type AnalysisSymbol = { kind: 'function' | 'event'; name: string; span?: TraceSpan; file?: string }
+type Analysis = {
+  diagnostics: Diagnostic[]
+  symbols: AnalysisSymbol[]
+  outputs: number | null
+  builtins: string[]
+}
+type SharedResult = { source: string; input?: string; seed: number; result?: string } & Partial<RunErr>
 type FormatErr = RunErr
 
 const defaultSource = `[
@@ -43,6 +55,9 @@ export default function App() {
   >(null)
   const [loading, setLoading] = useState(false)
   const [formatLoading, setFormatLoading] = useState(false)
+  const [shareLoading, setShareLoading] = useState(false)
+  const [shareLink, setShareLink] = useState('')
+  const [analysis, setAnalysis] = useState<Analysis | null>(null)
   const editorRef = useRef<editor.IStandaloneCodeEditor | null>(null)
   const decorationIdsRef = useRef<string[]>([])
 
@@ -53,6 +68,75 @@ export default function App() {
       .catch(() => setExamples([]))
   }, [])
 
+  useEffect(() => {
+    const timer = setTimeout(() => {
+      fetch('/api/analyze', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ source }),
+      })
+        .then((r) => (r.ok ? (r.json() as Promise<Analysis>) : null))
+        .then(setAnalysis)
+        .catch(() => setAnalysis(null))
+    }, 400)
+    return () => clearTimeout(timer)
+  }, [source])
+
+  useEffect(() => {
+    const model = editorRef.current?.getModel()
+    const monaco = monacoGlobal
+    if (!model || !monaco) return
+    const markers = (analysis?.diagnostics ?? [])
+      .filter((d) => d.span && !d.file)
+      .map((d) => ({
+        severity:
+          d.severity === 'error' ? monaco.MarkerSeverity.Error : monaco.MarkerSeverity.Warning,
+        message: d.message,
+        startLineNumber: d.span!.start_line,
+        startColumn: d.span!.start_column,
+        endLineNumber: d.span!.end_line,
+        endColumn: d.span!.end_column,
+      }))
+    monaco.editor.setModelMarkers(model, 'branchy', markers)
+  }, [analysis])
+
+  function revealSymbol(symbol: AnalysisSymbol) {
+    const ed = editorRef.current
+    if (!ed || !symbol.span) return
+    ed.revealLineInCenter(symbol.span.start_line)
+    ed.setPosition({ lineNumber: symbol.span.start_line, column: symbol.span.start_column })
+    ed.focus()
+  }
+
+  useEffect(() => {
+    const id = new URLSearchParams(window.location.search).get('share')
+    if (!id) return
+    fetch(`/api/share/${encodeURIComponent(id)}`)
+      .then(async (r) => {
+        const data = (await r.json()) as SharedResult
+        if (!r.ok) {
+          setResult({ error: data.error ?? r.statusText })
+          return
+        }
+        setSource(data.source)
+        setInput(data.input ?? '')
+        setSeedInput(String(data.seed))
+        setShareLink(window.location.href)
+        setResult(
+          data.error != null
+            ? {
+                error: data.error,
+                line: data.line,
+                column: data.column,
+                end_line: data.end_line,
+                end_column: data.end_column,
+              }
+            : { ok: data.result ?? '' }
+        )
+      })
+      .catch((e) => setResult({ error: e instanceof Error ? e.message : String(e) }))
+  }, [])
+
   const applyDecorations = useCallback(
     (trace: TraceSpan[] | undefined, errorSpan: RunErr | undefined) => {
       const ed = editorRef.current
@@ -81,7 +165,7 @@ export default function App() {
         return
       }
       if (!trace?.length) return
-      const decorations: editor.IModelDeltaDecoration[] = trace.map((s) => ({
+      const decorations: editor.IModelDeltaDecoration[] = trace.filter((s) => !s.file).map((s) => ({
         range: new monaco.Range(
           s.start_line,
           s.start_column,
@@ -124,6 +208,7 @@ export default function App() {
     try {
       monaco.languages.register({ id: branchyLanguageId })
       monaco.languages.setMonarchTokensProvider(branchyLanguageId, branchyLanguage)
+      registerBranchyProviders(monaco)
     } catch {
       // already registered
     }
@@ -165,6 +250,36 @@ export default function App() {
     }
   }
 
+  async function handleShare() {
+    setShareLoading(true)
+    setShareLink('')
+    try {
+      const body: { source: string; input?: string; seed?: number } = { source }
+      if (input.trim()) body.input = input.trim()
+      const seedNum = seedInput.trim() ? parseInt(seedInput.trim(), 10) : undefined
+      if (seedNum !== undefined && !Number.isNaN(seedNum) && seedNum >= 0) body.seed = seedNum
+      const res = await fetch('/api/share', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body),
+      })
+      const data = (await res.json()) as ShareCreated | RunErr
+      if (!res.ok || !('id' in data)) {
+        setResult({ error: 'error' in data ? data.error : res.statusText })
+        return
+      }
+      const url = new URL(window.location.href)
+      url.search = `?share=${data.id}`
+      setSeedInput(String(data.seed))
+      setShareLink(url.toString())
+      navigator.clipboard?.writeText(url.toString()).catch(() => {})
+    } catch (e) {
+      setResult({ error: e instanceof Error ? e.message : String(e) })
+    } finally {
+      setShareLoading(false)
+    }
+  }
+
   async function handleFormat() {
     setFormatLoading(true)
     setResult(null)
@@ -237,6 +352,26 @@ export default function App() {
           />
         </div>
       </div>
+      {analysis && (
+        <div className="field analysis">
+          <span>
+            Вариантов вывода: {analysis.outputs ?? '∞'}
+            {analysis.builtins.length > 0 && ` · встроенные: ${analysis.builtins.join(', ')}`}
+          </span>
+          {analysis.symbols
+            .filter((s) => !s.file)
+            .map((s) => (
+              <button
+                key={`${s.kind}:${s.name}`}
+                type="button"
+                className="symbol-btn"
+                onClick={() => revealSymbol(s)}
+              >
+                {s.name}
+              </button>
+            ))}
+        </div>
+      )}
       <div className="field">
         <label htmlFor="input">Вход для события (необязательно)</label>
         <input
@@ -271,7 +406,21 @@ export default function App() {
         >
           {formatLoading ? 'Форматирую…' : 'Форматировать'}
         </button>
+        <button
+          onClick={handleShare}
+          disabled={shareLoading}
+          className="format-btn"
+          type="button"
+        >
+          {shareLoading ? 'Сохраняю…' : 'Поделиться'}
+        </button>
       </div>
+      {shareLink && (
+        <div className="field">
+          <label htmlFor="share-link">Ссылка на запуск</label>
+          <input id="share-link" type="text" readOnly value={shareLink} />
+        </div>
+      )}
       <div className="result-slot" aria-live="polite">
         {loading && !result && (
           <div className="result result-loading">Выполняю…</div>
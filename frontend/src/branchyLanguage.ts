@@ -1,4 +1,6 @@
-import type { languages } from 'monaco-editor'
+import type { editor, IRange, languages, Position } from 'monaco-editor'
+
+type Monaco = typeof import('monaco-editor')
 
 export const branchyLanguage: languages.IMonarchLanguage = {
   defaultToken: '',
@@ -32,3 +34,82 @@ export const branchyLanguage: languages.IMonarchLanguage = {
 }
 
 export const branchyLanguageId = 'branchy'
+
+type Span = { start_line: number; start_column: number; end_line: number; end_column: number }
+type Completion = {
+  label: string
+  kind: 'builtin' | 'function' | 'event' | 'param' | 'block_param'
+  detail: string
+  documentation?: string
+}
+type Completions = { span: Span; items: Completion[] }
+type Hover = { span: Span; signature: string; documentation?: string }
+
+function toRange(span: Span): IRange {
+  return {
+    startLineNumber: span.start_line,
+    startColumn: span.start_column,
+    endLineNumber: span.end_line,
+    endColumn: span.end_column,
+  }
+}
+
+/** POST the model's source and the cursor position to `path`; null on any failure. */
+async function atCursor<T>(
+  path: string,
+  model: editor.ITextModel,
+  position: Position,
+): Promise<T | null> {
+  try {
+    const res = await fetch(path, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({
+        source: model.getValue(),
+        line: position.lineNumber,
+        column: position.column,
+      }),
+    })
+    return res.ok ? ((await res.json()) as T) : null
+  } catch {
+    return null
+  }
+}
+
+/** Completion and hover from the backend (`/api/complete`, `/api/hover`). */
+export function registerBranchyProviders(monaco: Monaco) {
+  const kinds = {
+    builtin: monaco.languages.CompletionItemKind.Function,
+    function: monaco.languages.CompletionItemKind.Method,
+    event: monaco.languages.CompletionItemKind.Event,
+    param: monaco.languages.CompletionItemKind.Variable,
+    block_param: monaco.languages.CompletionItemKind.Field,
+  }
+  monaco.languages.registerCompletionItemProvider(branchyLanguageId, {
+    triggerCharacters: ['!', ':', '?', '@'],
+    async provideCompletionItems(model, position) {
+      const data = await atCursor<Completions>('/api/complete', model, position)
+      if (!data) return { suggestions: [] }
+      const range = toRange(data.span)
+      return {
+        suggestions: data.items.map((c) => ({
+          label: c.label,
+          kind: kinds[c.kind],
+          detail: c.detail,
+          documentation: c.documentation,
+          insertText: c.label,
+          range,
+        })),
+      }
+    },
+  })
+  monaco.languages.registerHoverProvider(branchyLanguageId, {
+    async provideHover(model, position) {
+      const data = await atCursor<Hover | null>('/api/hover', model, position)
+      if (!data) return null
+      const contents = [{ value: '```\n' + data.signature + '\n```' }]
+      if (data.documentation) contents.push({ value: data.documentation })
+      return { range: toRange(data.span), contents }
+    },
+  })
+}
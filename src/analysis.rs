@@ -0,0 +1,591 @@
+//! Static analysis for editors: diagnostics, symbols, the call graph, output counts and the
+//! builtins a program calls, all without running it.
+//!
+//! Output counts are upper bounds: the number of distinct choice paths, some of which may print
+//! the same string. They are `None` when unbounded or unknown (recursion, a repeat count computed
+//! at run time) and saturate at `u64::MAX`.
+
+use crate::ast::{BinOp, CharBlockCount, EventMatcher, FunctionDef, Literal, Node, Program};
+use crate::ast::{SourceError, Span};
+use crate::builtins::BuiltinFn;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::BuildHasher;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+  /// The program fails to load, or fails whenever this code runs.
+  Error,
+  Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Diagnostic {
+  pub severity: Severity,
+  pub message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub span: Option<Span>,
+  /// Included file the span refers to (omitted for the entry source).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+}
+
+impl From<SourceError> for Diagnostic {
+  fn from(e: SourceError) -> Self {
+    Self {
+      severity: Severity::Error,
+      message: e.message,
+      span: e.span,
+      file: e.file,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+  Function,
+  Event,
+}
+
+/// A function or event, for an outline.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Symbol {
+  pub kind: SymbolKind,
+  /// `!name` for functions, the matcher label (`@name`, `"text"`, `~"regex"`) for events.
+  pub name: String,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub params: Vec<String>,
+  /// Span of the body.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub span: Option<Span>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  /// Outputs of the body, counting each param as one value.
+  pub outputs: Option<u64>,
+}
+
+/// `from` calls the function `to` at `calls` places.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct CallEdge {
+  /// `main`, `!name` or an event label.
+  pub from: String,
+  /// `!name`.
+  pub to: String,
+  pub calls: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Analysis {
+  pub diagnostics: Vec<Diagnostic>,
+  /// Functions, then events, in definition order.
+  pub symbols: Vec<Symbol>,
+  /// Calls of user functions, sorted by caller and callee.
+  pub calls: Vec<CallEdge>,
+  /// Outputs of a run without input.
+  pub outputs: Option<u64>,
+  /// Builtins the program calls, sorted.
+  pub builtins: Vec<String>,
+}
+
+impl Analysis {
+  /// Analysis of a program that failed to load: just its error.
+  pub fn failed(error: SourceError) -> Self {
+    Self {
+      diagnostics: vec![error.into()],
+      symbols: Vec::new(),
+      calls: Vec::new(),
+      outputs: None,
+      builtins: Vec::new(),
+    }
+  }
+}
+
+/// Analyze a (resolved) program; `builtins` decides which `!name(...)` calls are builtins.
+pub fn analyze<S: BuildHasher>(
+  program: &Program,
+  builtins: &HashMap<String, BuiltinFn, S>,
+) -> Analysis {
+  let builtins: HashSet<&str> = builtins.keys().map(String::as_str).collect();
+  // The interpreter calls the first definition of a name.
+  let mut functions = HashMap::new();
+  for f in &program.functions {
+    functions.entry(f.name.as_str()).or_insert(f);
+  }
+  let mut walker = Walker {
+    builtins: &builtins,
+    functions: &functions,
+    owner: String::new(),
+    file: None,
+    diagnostics: Vec::new(),
+    calls: BTreeMap::new(),
+    used_builtins: BTreeSet::new(),
+  };
+  for f in &program.functions {
+    walker.owner = format!("!{}", f.name);
+    walker.file = f.file.as_deref();
+    walker.walk(&f.body);
+  }
+  for e in &program.events {
+    walker.owner = e.matcher.label();
+    walker.file = e.file.as_deref();
+    if let EventMatcher::ByRegex(pattern) = &e.matcher {
+      if regex::Regex::new(pattern).is_err() {
+        walker.warn(
+          format!("invalid regex {pattern:?}: the event never matches"),
+          e.body.span(),
+        );
+      }
+    }
+    walker.walk(&e.body);
+  }
+  walker.owner = "main".to_string();
+  walker.file = None;
+  walker.walk(&program.main);
+  walker.unused_functions(program);
+
+  let mut counter = Counter {
+    builtins: &builtins,
+    functions: &functions,
+    memo: HashMap::new(),
+    calls: HashMap::new(),
+    active: HashSet::new(),
+  };
+  let mut symbols = Vec::new();
+  for f in &program.functions {
+    symbols.push(Symbol {
+      kind: SymbolKind::Function,
+      name: format!("!{}", f.name),
+      params: f.params.clone(),
+      span: f.body.span(),
+      file: f.file.clone(),
+      outputs: counter.function(&f.name),
+    });
+  }
+  for e in &program.events {
+    symbols.push(Symbol {
+      kind: SymbolKind::Event,
+      name: e.matcher.label(),
+      params: Vec::new(),
+      span: e.body.span(),
+      file: e.file.clone(),
+      outputs: counter.count(&e.body, None),
+    });
+  }
+  let outputs = counter.main(&program.main);
+  Analysis {
+    diagnostics: walker.diagnostics,
+    symbols,
+    calls: walker
+      .calls
+      .into_iter()
+      .map(|((from, to), calls)| CallEdge { from, to, calls })
+      .collect(),
+    outputs,
+    builtins: walker.used_builtins.into_iter().collect(),
+  }
+}
+
+/// Collects diagnostics, call edges and builtins, one definition at a time.
+struct Walker<'a> {
+  builtins: &'a HashSet<&'a str>,
+  functions: &'a HashMap<&'a str, &'a FunctionDef>,
+  /// Definition being walked: `main`, `!name` or an event label.
+  owner: String,
+  file: Option<&'a str>,
+  diagnostics: Vec<Diagnostic>,
+  /// Call sites by (caller, callee).
+  calls: BTreeMap<(String, String), usize>,
+  used_builtins: BTreeSet<String>,
+}
+
+impl Walker<'_> {
+  fn push(&mut self, severity: Severity, message: String, span: Option<Span>) {
+    self.diagnostics.push(Diagnostic {
+      severity,
+      message,
+      span,
+      file: self.file.map(str::to_string),
+    });
+  }
+
+  fn error(&mut self, message: String, span: Option<Span>) {
+    self.push(Severity::Error, message, span);
+  }
+
+  fn warn(&mut self, message: String, span: Option<Span>) {
+    self.push(Severity::Warning, message, span);
+  }
+
+  fn call(&mut self, name: &str) {
+    *self
+      .calls
+      .entry((self.owner.clone(), format!("!{name}")))
+      .or_default() += 1;
+  }
+
+  fn walk(&mut self, node: &Node) {
+    match node {
+      Node::Branch { children, span } => {
+        if children.is_empty() {
+          self.error("empty branch".to_string(), *span);
+        }
+        for c in children {
+          self.walk(c);
+        }
+      }
+      Node::InlineCall { options, span, .. } => {
+        if options.is_empty() {
+          self.error("empty inline options".to_string(), *span);
+        }
+        for o in options {
+          self.walk(o);
+        }
+      }
+      Node::BinaryOp {
+        op, left, right, ..
+      } => {
+        if *op == BinOp::Star {
+          if let Node::Leaf {
+            lit: lit @ (Literal::Ident(_) | Literal::Str(_)),
+            span,
+          } = &**right
+          {
+            if lit.to_string_value().parse::<i64>().is_err() {
+              self.error(
+                "repeat count must be integer or range (e.g. 1..3)".to_string(),
+                *span,
+              );
+            }
+          }
+        }
+        self.walk(left);
+        self.walk(right);
+      }
+      Node::FuncCall { name, args, span } => {
+        if self.builtins.contains(name.as_str()) {
+          self.used_builtins.insert(name.clone());
+        } else if let Some(f) = self.functions.get(name.as_str()) {
+          self.call(name);
+          if f.params.len() != args.len() {
+            self.error(
+              format!(
+                "function {name} expects {} arguments, got {}",
+                f.params.len(),
+                args.len()
+              ),
+              *span,
+            );
+          }
+        } else {
+          self.error(format!("unknown function: {name}"), *span);
+        }
+        for a in args {
+          self.walk(a);
+        }
+      }
+      Node::Call {
+        name,
+        params,
+        block,
+        span,
+        ..
+      } => {
+        let bindings = block.iter().flat_map(|b| b.bindings.iter());
+        if self.functions.contains_key(name.as_str()) {
+          self.call(name);
+        } else {
+          let unused: Vec<String> = bindings
+            .clone()
+            .filter(|(k, _)| !params.contains(k))
+            .map(|(k, _)| format!(":{k}"))
+            .collect();
+          if !unused.is_empty() {
+            self.error(
+              format!(
+                "call '{name}' has block parameter(s) ({}) but no function definition; block \
+                 parameters must be used in a template",
+                unused.join(", ")
+              ),
+              *span,
+            );
+          }
+        }
+        for (_, v) in bindings {
+          self.walk(v);
+        }
+      }
+      Node::Leaf { .. }
+      | Node::SpreadParam { .. }
+      | Node::SpreadInclude { .. }
+      | Node::SpreadData { .. }
+      | Node::CharBlock { .. } => {}
+    }
+  }
+
+  /// Warn about functions of the entry source that neither main nor an event reaches.
+  fn unused_functions(&mut self, program: &Program) {
+    let mut reached = HashSet::new();
+    let mut queue = vec!["main".to_string()];
+    queue.extend(program.events.iter().map(|e| e.matcher.label()));
+    while let Some(from) = queue.pop() {
+      for (caller, callee) in self.calls.keys() {
+        if *caller == from && reached.insert(callee.clone()) {
+          queue.push(callee.clone());
+        }
+      }
+    }
+    self.file = None;
+    for f in program.functions.iter().filter(|f| f.file.is_none()) {
+      let name = format!("!{}", f.name);
+      if !reached.contains(&name) {
+        self.warn(format!("function {name} is never called"), f.body.span());
+      }
+    }
+  }
+}
+
+/// Counts outputs; function bodies and template calls are counted once and memoized.
+struct Counter<'a> {
+  builtins: &'a HashSet<&'a str>,
+  functions: &'a HashMap<&'a str, &'a FunctionDef>,
+  memo: HashMap<&'a str, Option<u64>>,
+  /// Outputs of template calls by the address of their `Node::Call`. A call's count depends
+  /// only on its own block, so a chain of templates each calling the next is counted in linear
+  /// rather than exponential time.
+  calls: HashMap<*const Node, Option<u64>>,
+  /// Functions being counted, to stop at recursion.
+  active: HashSet<&'a str>,
+}
+
+impl<'a> Counter<'a> {
+  /// Outputs of a run of `main` without input.
+  fn main(&mut self, main: &Node) -> Option<u64> {
+    match main {
+      // Like the interpreter: a main of several branches prints one choice of each.
+      Node::Branch { children, .. }
+        if children.len() > 1 && children.iter().all(|c| matches!(c, Node::Branch { .. })) =>
+      {
+        self.product(children.iter())
+      }
+      main => self.count(main, None),
+    }
+  }
+
+  /// Outputs of the function `name` called without a block.
+  fn function(&mut self, name: &str) -> Option<u64> {
+    let f = *self.functions.get(name)?;
+    if let Some(count) = self.memo.get(f.name.as_str()) {
+      return *count;
+    }
+    let count = self.body(f, None);
+    self.memo.insert(&f.name, count);
+    count
+  }
+
+  /// Outputs of the body of `f`; None when `f` is already being counted (recursion).
+  fn body(&mut self, f: &'a FunctionDef, block: Option<&HashMap<&str, &Node>>) -> Option<u64> {
+    if !self.active.insert(&f.name) {
+      return None;
+    }
+    let count = self.count(&f.body, block);
+    self.active.remove(f.name.as_str());
+    count
+  }
+
+  /// Outputs of `node`; `block` holds the bindings `...:name` expands to inside a template.
+  fn count(&mut self, node: &Node, block: Option<&HashMap<&str, &Node>>) -> Option<u64> {
+    match node {
+      Node::Branch { children, .. } => {
+        let mut total = 0u64;
+        for c in children {
+          let count = match c {
+            Node::SpreadParam { param, .. } => match block?.get(param.as_str())? {
+              Node::Branch { children, .. } => self.sum(children, block)?,
+              other => self.count(other, block)?,
+            },
+            c => self.count(c, block)?,
+          };
+          total = total.saturating_add(count);
+        }
+        Some(total)
+      }
+      Node::Leaf {
+        lit: Literal::OptionalParam(_),
+        ..
+      } => Some(2),
+      Node::Leaf { .. } => Some(1),
+      Node::CharBlock { ranges, count, .. } => Some(char_block(ranges, count)),
+      Node::BinaryOp {
+        op: BinOp::Plus,
+        left,
+        right,
+        ..
+      } => Some(
+        self
+          .count(left, None)?
+          .saturating_mul(self.count(right, None)?),
+      ),
+      Node::BinaryOp {
+        op: BinOp::Star,
+        left,
+        right,
+        ..
+      } => {
+        let each = self.count(left, None)?;
+        match &**right {
+          Node::Leaf {
+            lit: Literal::Range(lo, hi),
+            ..
+          } => Some(powers(each, (*lo).max(0), (*hi).max(0))),
+          Node::Leaf {
+            lit: lit @ (Literal::Num(_) | Literal::Ident(_) | Literal::Str(_)),
+            ..
+          } => Some(power(
+            each,
+            lit.to_string_value().parse::<i64>().ok()?.max(0),
+          )),
+          _ => None,
+        }
+      }
+      Node::InlineCall { options, .. } => self.sum(options, None),
+      Node::FuncCall { name, args, .. } => {
+        let args = self.product(args.iter())?;
+        if self.builtins.contains(name.as_str()) {
+          return Some(args);
+        }
+        Some(args.saturating_mul(self.function(name)?))
+      }
+      Node::Call { .. } => {
+        let key = std::ptr::from_ref(node);
+        if let Some(count) = self.calls.get(&key) {
+          return *count;
+        }
+        let count = self.call(node);
+        self.calls.insert(key, count);
+        count
+      }
+      Node::SpreadParam { .. } | Node::SpreadInclude { .. } | Node::SpreadData { .. } => None,
+    }
+  }
+
+  /// Outputs of a template call: its body with the call's block, times the printed bindings.
+  fn call(&mut self, node: &Node) -> Option<u64> {
+    let Node::Call {
+      name,
+      params,
+      optional_params,
+      block,
+      ..
+    } = node
+    else {
+      return None;
+    };
+    let bindings: Vec<_> = block.iter().flat_map(|b| b.bindings.iter()).collect();
+    let f = self.functions.get(name.as_str()).copied();
+    // A bound value only adds outputs where it is printed: passed on as a param or read
+    // as `:name` by the template.
+    let printed = bindings
+      .iter()
+      .filter(|(k, _)| params.contains(k) || f.is_some_and(|f| reads(&f.body, k)))
+      .map(|(_, v)| v);
+    let values = self.product(printed)?;
+    let Some(f) = f else {
+      let optional = params
+        .iter()
+        .filter(|p| optional_params.contains(*p))
+        .count();
+      return Some(values.saturating_mul(power(2, i64::try_from(optional).unwrap_or(i64::MAX))));
+    };
+    let block: HashMap<&str, &Node> = bindings.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    Some(values.saturating_mul(self.body(f, Some(&block))?))
+  }
+
+  fn sum(&mut self, nodes: &[Node], block: Option<&HashMap<&str, &Node>>) -> Option<u64> {
+    nodes.iter().try_fold(0u64, |acc, n| {
+      Some(acc.saturating_add(self.count(n, block)?))
+    })
+  }
+
+  fn product<'n>(&mut self, nodes: impl Iterator<Item = &'n Node>) -> Option<u64> {
+    let mut total = 1u64;
+    for n in nodes {
+      total = total.saturating_mul(self.count(n, None)?);
+    }
+    Some(total)
+  }
+}
+
+/// Whether `node` reads the param `name` (as `:name`, `:?name` or a template call param).
+fn reads(node: &Node, name: &str) -> bool {
+  match node {
+    Node::Leaf {
+      lit: Literal::Param(p) | Literal::OptionalParam(p),
+      ..
+    } => p == name,
+    Node::Branch { children, .. } => children.iter().any(|c| reads(c, name)),
+    Node::InlineCall { options, .. } => options.iter().any(|o| reads(o, name)),
+    Node::FuncCall { args, .. } => args.iter().any(|a| reads(a, name)),
+    Node::BinaryOp { left, right, .. } => reads(left, name) || reads(right, name),
+    Node::Call { params, block, .. } => {
+      params.iter().any(|p| p == name)
+        || block
+          .iter()
+          .flat_map(|b| b.bindings.iter())
+          .any(|(_, v)| reads(v, name))
+    }
+    Node::Leaf { .. }
+    | Node::SpreadParam { .. }
+    | Node::SpreadInclude { .. }
+    | Node::SpreadData { .. }
+    | Node::CharBlock { .. } => false,
+  }
+}
+
+/// Outputs of a char block: strings of `count` characters from `ranges`.
+fn char_block(ranges: &[(char, char)], count: &CharBlockCount) -> u64 {
+  let chars = ranges
+    .iter()
+    .map(|&(lo, hi)| (lo..=hi).count() as u64)
+    .sum::<u64>();
+  if chars == 0 {
+    return 1;
+  }
+  match *count {
+    CharBlockCount::One => chars,
+    CharBlockCount::Fixed(n) => power(chars, n.max(0)),
+    CharBlockCount::Range(lo, hi) => powers(chars, lo.max(0), hi.max(0)),
+  }
+}
+
+/// `base^exp`, saturating.
+fn power(base: u64, exp: i64) -> u64 {
+  u32::try_from(exp)
+    .ok()
+    .and_then(|e| base.checked_pow(e))
+    .unwrap_or(if base <= 1 { base } else { u64::MAX })
+}
+
+/// `base^lo + … + base^hi`, saturating (a repeat or char block with a count range).
+fn powers(base: u64, lo: i64, hi: i64) -> u64 {
+  if hi < lo {
+    // The count is always 0: one, empty, output.
+    return 1;
+  }
+  match base {
+    0 => u64::from(lo == 0),
+    1 => u64::try_from(hi - lo).map_or(u64::MAX, |n| n.saturating_add(1)),
+    _ => {
+      let mut total = 0u64;
+      for exp in lo..=hi {
+        total = total.saturating_add(power(base, exp));
+        if total == u64::MAX {
+          break;
+        }
+      }
+      total
+    }
+  }
+}
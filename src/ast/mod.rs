@@ -4,6 +4,6 @@ mod node;
 mod program;
 mod span;
 
-pub use node::{BinOp, CallBlock, CharBlockCount, Literal, Node};
-pub use program::{Event, EventMatcher, FunctionDef, Program};
-pub use span::{SourceError, Span, span_from_offsets};
+pub use node::{BinOp, CallBlock, CharBlockCount, DataFormat, Literal, Node};
+pub use program::{Event, EventMatcher, FunctionDef, Import, Include, Program};
+pub use span::{span_from_offsets, SourceError, Span};
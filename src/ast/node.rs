@@ -1,4 +1,4 @@
-//! AST node types: Node, Literal, BinOp, CallBlock, CharBlockCount.
+//! AST node types: Node, Literal, BinOp, CallBlock, CharBlockCount, DataFormat.
 
 use serde::{Deserialize, Serialize};
 
@@ -66,6 +66,92 @@ pub enum Node {
     #[serde(skip_serializing_if = "Option::is_none")]
     span: Option<Span>,
   },
+  /// `...lines "f"`, `...csv "f" column c`, `...json "f" "a.b"`: one leaf per record of a data
+  /// file, expanded at load time like `SpreadInclude`.
+  SpreadData {
+    path: String,
+    format: DataFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Span>,
+  },
+}
+
+impl Node {
+  /// Source span of this node, if recorded by the parser.
+  pub const fn span(&self) -> Option<Span> {
+    match self {
+      Self::Branch { span, .. }
+      | Self::Leaf { span, .. }
+      | Self::BinaryOp { span, .. }
+      | Self::Call { span, .. }
+      | Self::InlineCall { span, .. }
+      | Self::FuncCall { span, .. }
+      | Self::SpreadParam { span, .. }
+      | Self::SpreadInclude { span, .. }
+      | Self::CharBlock { span, .. }
+      | Self::SpreadData { span, .. } => *span,
+    }
+  }
+
+  /// Mark the spans of this node and all nodes below it as lying in source file `file`.
+  pub fn set_file(&mut self, file: u32) {
+    match self {
+      Self::Branch { children, span } => {
+        for c in children {
+          c.set_file(file);
+        }
+        set_span_file(span, file);
+      }
+      Self::BinaryOp {
+        left, right, span, ..
+      } => {
+        left.set_file(file);
+        right.set_file(file);
+        set_span_file(span, file);
+      }
+      Self::Call { block, span, .. } => {
+        for (_, v) in block.iter_mut().flat_map(|b| b.bindings.iter_mut()) {
+          v.set_file(file);
+        }
+        set_span_file(span, file);
+      }
+      Self::InlineCall {
+        options: nodes,
+        span,
+        ..
+      }
+      | Self::FuncCall {
+        args: nodes, span, ..
+      } => {
+        for n in nodes {
+          n.set_file(file);
+        }
+        set_span_file(span, file);
+      }
+      Self::Leaf { span, .. }
+      | Self::SpreadParam { span, .. }
+      | Self::SpreadInclude { span, .. }
+      | Self::CharBlock { span, .. }
+      | Self::SpreadData { span, .. } => set_span_file(span, file),
+    }
+  }
+}
+
+const fn set_span_file(span: &mut Option<Span>, file: u32) {
+  if let Some(s) = span {
+    s.file = file;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataFormat {
+  /// Non-empty lines of a text file.
+  Lines,
+  /// Values of a column (by header name) of a CSV file.
+  Csv { column: String },
+  /// Strings, numbers and booleans at a dotted path of a JSON file (arrays on the way are
+  /// flattened); the whole document when `path` is empty.
+  Json { path: String },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -7,13 +7,40 @@ use super::node::Node;
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Program {
   #[serde(default)]
-  pub includes: Vec<String>,
+  pub includes: Vec<Include>,
   pub functions: Vec<FunctionDef>,
   #[serde(default)]
   pub events: Vec<Event>,
   pub main: Node,
 }
 
+/// `include "path";`, `include "path" as ns;` or `from "path" import a, b;`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Include {
+  pub path: String,
+  pub import: Import,
+}
+
+impl Include {
+  /// Plain `include "path";`.
+  pub fn all(path: impl Into<String>) -> Self {
+    Self {
+      path: path.into(),
+      import: Import::All,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Import {
+  /// Every function and event, unqualified.
+  All,
+  /// Every function as `ns.name` (`include "path" as ns;`); events are not imported.
+  Namespace(String),
+  /// Only the listed functions, unqualified (`from "path" import a, b;`).
+  Names(Vec<String>),
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EventMatcher {
   ByName(String),
@@ -21,10 +48,24 @@ pub enum EventMatcher {
   ByRegex(String),
 }
 
+impl EventMatcher {
+  /// Short label for reports: `@name`, `"text"` or `~"regex"`.
+  pub fn label(&self) -> String {
+    match self {
+      Self::ByName(name) => format!("@{name}"),
+      Self::ByStr(s) => format!("{s:?}"),
+      Self::ByRegex(re) => format!("~{re:?}"),
+    }
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Event {
   pub matcher: EventMatcher,
   pub body: Node,
+  /// File the event was included from (set by `resolve_includes`; `None`: the entry source).
+  #[serde(default)]
+  pub file: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -32,4 +73,7 @@ pub struct FunctionDef {
   pub name: String,
   pub params: Vec<String>,
   pub body: Node,
+  /// File the function was included from (set by `resolve_includes`; `None`: the entry source).
+  #[serde(default)]
+  pub file: Option<String>,
 }
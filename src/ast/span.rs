@@ -2,14 +2,46 @@
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use utoipa::ToSchema;
 
 /// Source span for trace (Monaco: 1-based line, 1-based column; end exclusive for column).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct Span {
   pub start_line: u32,
   pub start_column: u32,
   pub end_line: u32,
   pub end_column: u32,
+  /// Source file: 0 for the entry source; `resolve_includes` numbers included files from 1.
+  #[serde(default)]
+  pub file: u32,
+}
+
+impl Span {
+  /// Source text covered by this span (columns are byte offsets within the line). None when
+  /// the span is outside `source` or has a line or column of 0.
+  pub fn text<'a>(&self, source: &'a str) -> Option<&'a str> {
+    let offset = |line: u32, column: u32| -> Option<usize> {
+      let column = (column as usize).checked_sub(1)?;
+      let line_start = match (line as usize).checked_sub(2) {
+        None if line == 1 => 0,
+        None => return None,
+        Some(n) => source.match_indices('\n').nth(n)?.0 + 1,
+      };
+      Some(line_start + column)
+    };
+    let start = offset(self.start_line, self.start_column)?;
+    let end = offset(self.end_line, self.end_column)?;
+    source.get(start..end)
+  }
+
+  /// True if `other` lies entirely within this span (in the same file).
+  pub fn contains(&self, other: &Self) -> bool {
+    if self.file != other.file {
+      return false;
+    }
+    (self.start_line, self.start_column) <= (other.start_line, other.start_column)
+      && (other.end_line, other.end_column) <= (self.end_line, self.end_column)
+  }
 }
 
 /// Error with optional source location (line, column).
@@ -18,6 +50,9 @@ pub struct SourceError {
   pub message: String,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub span: Option<Span>,
+  /// Included file the span refers to (`None`: the entry source).
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
 }
 
 impl SourceError {
@@ -25,10 +60,22 @@ impl SourceError {
     self.span = span;
     self
   }
+
+  /// Attribute the error to `file` unless an inner frame already did.
+  #[must_use]
+  pub fn in_file(mut self, file: Option<&str>) -> Self {
+    if self.file.is_none() {
+      self.file = file.map(str::to_string);
+    }
+    self
+  }
 }
 
 impl fmt::Display for SourceError {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(file) = &self.file {
+      write!(f, "{file}: ")?;
+    }
     if let Some(s) = &self.span {
       write!(
         f,
@@ -66,5 +113,6 @@ pub fn span_from_offsets(source: &str, start: usize, end: usize) -> Span {
     start_column: (start - line_index[start_line] + 1) as u32,
     end_line: (end_line + 1) as u32,
     end_column: (end - line_index[end_line] + 1) as u32,
+    file: 0,
   }
 }
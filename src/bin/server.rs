@@ -1,27 +1,113 @@
 use branchy::{
   default_registry,
-  server::{create_app, AppState},
+  server::{
+    create_app_with_origins, hash_key, load_examples, AppState, Auth, ParseCache, ProgramStore,
+    ServerConfig, ShareStore, Throttle, USAGE,
+  },
 };
 use std::sync::Arc;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if args.iter().any(|a| a == "-h" || a == "--help") {
+    print!("{USAGE}");
+    return;
+  }
+  if let [flag, key] = args.as_slice() {
+    if flag == "--hash-key" {
+      println!("{}", hash_key(key));
+      return;
+    }
+  }
+  let config = match ServerConfig::load(&args, |name| std::env::var(name).ok()) {
+    Ok(config) => config,
+    Err(e) => {
+      eprintln!("branchy-server: {e}");
+      std::process::exit(2);
+    }
+  };
+
   tracing_subscriber::registry()
-    .with(tracing_subscriber::EnvFilter::new(
-      std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-    ))
+    .with(tracing_subscriber::EnvFilter::new(&config.log))
     .with(tracing_subscriber::fmt::layer())
     .init();
 
+  // Named programs (/programs) are stored here, one directory per program.
+  let store = ProgramStore::open(&config.store_dir).expect("open program store");
+  tracing::info!("program store in {}", config.store_dir.display());
+  // Shared runs (/share), one file per run.
+  let shares = ShareStore::open(&config.share_dir)
+    .expect("open share store")
+    .with_max_runs(config.max_shares);
+  tracing::info!("shared runs in {}", config.share_dir.display());
+  let examples = load_examples(&config.examples_dir);
+  tracing::info!(
+    "{} examples from {}",
+    examples.len(),
+    config.examples_dir.display()
+  );
+
+  let auth = config.keys_file.as_ref().map_or_else(Auth::open, |path| {
+    Auth::load(path).unwrap_or_else(|e| {
+      eprintln!("branchy-server: {e}");
+      std::process::exit(2);
+    })
+  });
+  if auth.enabled() {
+    tracing::info!("API keys required ({} configured)", auth.len());
+  } else {
+    tracing::info!("API is open (no keys file)");
+  }
+
   let state = AppState {
     builtins: Arc::new(default_registry()),
+    store: Arc::new(store),
+    cache: Arc::new(ParseCache::new(
+      config.cache.max_entries,
+      config.cache.max_bytes,
+    )),
+    limits: config.limits.into(),
+    throttle: Arc::new(Throttle::new(config.rate_limits.clone())),
+    examples: Arc::new(examples),
+    metrics: Arc::default(),
+    auth: Arc::new(auth),
+    shares: Arc::new(shares),
   };
 
-  let app = create_app(state);
+  let app = create_app_with_origins(state, &config.cors_origins);
 
-  let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 3000));
-  tracing::info!("listening on http://{}", addr);
-  let listener = tokio::net::TcpListener::bind(addr).await.expect("bind");
-  axum::serve(listener, app).await.expect("serve");
+  tracing::info!("listening on http://{}", config.bind);
+  let listener = tokio::net::TcpListener::bind(config.bind)
+    .await
+    .expect("bind");
+  // Peer addresses are needed for per-IP rate limits.
+  let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+  axum::serve(listener, app)
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .expect("serve");
+  tracing::info!("stopped");
+}
+
+/// Ctrl+C, or SIGTERM on Unix (`docker stop`). In-flight requests finish before exit.
+async fn shutdown_signal() {
+  let ctrl_c = async {
+    tokio::signal::ctrl_c().await.expect("Ctrl+C handler");
+  };
+  #[cfg(unix)]
+  let terminate = async {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+      .expect("SIGTERM handler")
+      .recv()
+      .await;
+  };
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+  tokio::select! {
+    () = ctrl_c => {},
+    () = terminate => {},
+  }
+  tracing::info!("shutting down");
 }
@@ -3,7 +3,10 @@ use std::io::Read;
 
 const MAGIC: &[u8] = b"BRCH";
 /// Bumped to 2 when Node enum got span fields (incompatible with v1).
-const VERSION: u16 = 2;
+/// Bumped to 3 when includes became `Include { path, import }` (namespaced/selective imports).
+/// Bumped to 4 when functions and events got the `file` they were included from.
+/// Bumped to 5 when spans got the id of their `file`.
+const VERSION: u16 = 5;
 
 pub fn serialize_program(program: &Program) -> Result<Vec<u8>, String> {
   let payload = bincode::serialize(program).map_err(|e| e.to_string())?;
@@ -47,6 +50,15 @@ mod tests {
     assert_eq!(p.events.len(), p2.events.len());
   }
 
+  #[test]
+  fn serialize_deserialize_imports() {
+    let src =
+      "include \"a.branchy\";\ninclude \"b.branchy\" as ui;\nfrom \"c\" import f, g;\n[ !ui.f(); ]";
+    let p = parse_program(src).unwrap();
+    let p2 = deserialize_program(&serialize_program(&p).unwrap()).unwrap();
+    assert_eq!(p, p2);
+  }
+
   #[test]
   fn deserialize_invalid_magic() {
     let bytes = b"XXXX\x02\x00";
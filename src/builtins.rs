@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 pub type BuiltinFn = fn(&[String]) -> Result<String, String>;
 
+/// A builtin that stops building its output past a length.
+type LimitedFn = fn(&[String], usize) -> Result<String, String>;
+
 pub fn default_registry() -> HashMap<String, BuiltinFn> {
   let mut m = HashMap::new();
   m.insert("upper".into(), upper as BuiltinFn);
@@ -15,6 +18,30 @@ pub fn default_registry() -> HashMap<String, BuiltinFn> {
   m
 }
 
+/// Signature and description of a builtin of [`default_registry`], for editors.
+pub fn doc(name: &str) -> Option<(&'static str, &'static str)> {
+  Some(match name {
+    "upper" => ("!upper(s)", "`s` in upper case."),
+    "lower" => ("!lower(s)", "`s` in lower case."),
+    "trim" => ("!trim(s)", "`s` without leading and trailing whitespace."),
+    "concat" => (
+      "!concat(a, b, ...)",
+      "The arguments joined together (at least 2).",
+    ),
+    "join" => (
+      "!join(sep, a, ...)",
+      "The arguments after `sep` joined with `sep`.",
+    ),
+    "len" => ("!len(s)", "Length of `s` in bytes."),
+    "replace" => (
+      "!replace(s, from, to)",
+      "`s` with every `from` replaced by `to`.",
+    ),
+    "split" => ("!split(s, sep)", "The part of `s` before the first `sep`."),
+    _ => return None,
+  })
+}
+
 pub fn upper(args: &[String]) -> Result<String, String> {
   exact_args(1, args)?;
   Ok(args[0].to_uppercase())
@@ -31,18 +58,26 @@ pub fn trim(args: &[String]) -> Result<String, String> {
 }
 
 pub fn concat(args: &[String]) -> Result<String, String> {
+  concat_limited(args, usize::MAX)
+}
+
+fn concat_limited(args: &[String], limit: usize) -> Result<String, String> {
   if args.len() < 2 {
     return Err("concat expects at least 2 arguments".into());
   }
-  Ok(args.join(""))
+  Ok(build(args.iter().map(String::as_str), limit))
 }
 
 pub fn join(args: &[String]) -> Result<String, String> {
+  join_limited(args, usize::MAX)
+}
+
+fn join_limited(args: &[String], limit: usize) -> Result<String, String> {
   if args.len() < 2 {
     return Err("join(sep, ...) expects at least sep and one part".into());
   }
-  let sep = &args[0];
-  Ok(args[1..].join(sep))
+  let parts = args[1..].iter().map(String::as_str);
+  Ok(build(separated(parts, &args[0]), limit))
 }
 
 pub fn len(args: &[String]) -> Result<String, String> {
@@ -51,8 +86,13 @@ pub fn len(args: &[String]) -> Result<String, String> {
 }
 
 pub fn replace(args: &[String]) -> Result<String, String> {
+  replace_limited(args, usize::MAX)
+}
+
+fn replace_limited(args: &[String], limit: usize) -> Result<String, String> {
   exact_args(3, args)?;
-  Ok(args[0].replace(&args[1], &args[2]))
+  // What `str::replace` builds: the parts between matches, separated by `to`.
+  Ok(build(separated(args[0].split(&args[1]), &args[2]), limit))
 }
 
 pub fn split_one(args: &[String]) -> Result<String, String> {
@@ -62,6 +102,51 @@ pub fn split_one(args: &[String]) -> Result<String, String> {
   Ok(parts.get(idx).map(|s| s.to_string()).unwrap_or_default())
 }
 
+/// Output of `builtin` for `args`, cut once it is longer than `limit` bytes.
+///
+/// `concat`, `join` and `replace` can return much more than they are given, so they stop
+/// building their output there, like a repeat does; other builtins are called as they are.
+pub fn call_with_limit(
+  builtin: BuiltinFn,
+  args: &[String],
+  limit: usize,
+) -> Result<String, String> {
+  let limited: [(BuiltinFn, LimitedFn); 3] = [
+    (concat, concat_limited),
+    (join, join_limited),
+    (replace, replace_limited),
+  ];
+  match limited
+    .iter()
+    .find(|(f, _)| std::ptr::fn_addr_eq(*f, builtin))
+  {
+    Some((_, f)) => f(args, limit),
+    None => builtin(args),
+  }
+}
+
+/// `parts` with `sep` between each two.
+fn separated<'a>(
+  parts: impl Iterator<Item = &'a str>,
+  sep: &'a str,
+) -> impl Iterator<Item = &'a str> {
+  parts
+    .enumerate()
+    .flat_map(move |(i, part)| [if i == 0 { "" } else { sep }, part])
+}
+
+/// `parts` joined, stopping after the first part that takes the output over `limit` bytes.
+fn build<'a>(parts: impl Iterator<Item = &'a str>, limit: usize) -> String {
+  let mut out = String::new();
+  for part in parts {
+    out.push_str(part);
+    if out.len() > limit {
+      break;
+    }
+  }
+  out
+}
+
 fn exact_args(n: usize, args: &[String]) -> Result<(), String> {
   if args.len() != n {
     Err(format!("expected {} arguments, got {}", n, args.len()))
@@ -115,6 +200,29 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_call_with_limit() {
+    let args: Vec<String> = ["a".repeat(10), "a".into(), "bcd".into()].into();
+    assert_eq!(
+      call_with_limit(replace, &args, 100).unwrap(),
+      "bcd".repeat(10)
+    );
+    assert_eq!(call_with_limit(replace, &args, 5).unwrap(), "bcdbcd");
+    let args: Vec<String> = ["-".into(), "ab".into(), "cd".into(), "ef".into()].into();
+    assert_eq!(call_with_limit(join, &args, 3).unwrap(), "ab-cd");
+    assert_eq!(call_with_limit(concat, &args[1..], 3).unwrap(), "abcd");
+    assert_eq!(call_with_limit(upper, &args[1..2], 0).unwrap(), "AB");
+    assert!(call_with_limit(replace, &args[..2], 3).is_err());
+  }
+
+  #[test]
+  fn test_replace_empty_pattern() {
+    assert_eq!(
+      replace(&["ab".into(), String::new(), "-".into()]).unwrap(),
+      "-a-b-"
+    );
+  }
+
   #[test]
   fn test_split_one() {
     assert_eq!(split_one(&["a,b,c".into(), ",".into()]).unwrap(), "a");
@@ -0,0 +1,275 @@
+//! Branch coverage: which alternatives, functions and events were reached across many runs.
+//!
+//! Hits are derived from the interpreter trace: a point counts as reached in a run if any traced
+//! span lies within its own span. Only the entry file is covered: functions, events and
+//! alternatives pulled in by `include` or `...include` lie in other files and get no points.
+
+use crate::ast::{Node, Program, Span};
+use serde::Serialize;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PointKind {
+  /// Child of a branch `[ a; b; ]`.
+  Alternative,
+  /// Option of an inline call `name <a|b>`.
+  InlineOption,
+  Function,
+  Event,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CoveragePoint {
+  pub kind: PointKind,
+  /// Enclosing definition: `main`, `!name` or the event matcher.
+  pub owner: String,
+  /// Id of the parent branch or inline call (lcov block number); unique per program.
+  pub group: usize,
+  /// Position among siblings (0-based) and number of siblings; 0 of 1 for functions and events.
+  pub index: usize,
+  pub of: usize,
+  pub span: Span,
+  /// Number of runs in which this point was reached at least once.
+  pub hits: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Coverage {
+  pub runs: u64,
+  pub errors: u64,
+  pub points: Vec<CoveragePoint>,
+}
+
+impl Coverage {
+  /// Collect the coverage points of the entry file of a (resolved) program with zero hits.
+  pub fn new(program: &Program) -> Self {
+    let mut c = Collector::default();
+    for f in program.functions.iter().filter(|f| f.file.is_none()) {
+      let owner = format!("!{}", f.name);
+      c.definition(PointKind::Function, &owner, &f.body);
+      c.walk(&f.body, &owner);
+    }
+    for e in program.events.iter().filter(|e| e.file.is_none()) {
+      let owner = e.matcher.label();
+      c.definition(PointKind::Event, &owner, &e.body);
+      c.walk(&e.body, &owner);
+    }
+    c.walk(&program.main, "main");
+    Self {
+      runs: 0,
+      errors: 0,
+      points: c.points,
+    }
+  }
+
+  /// Record the trace of one successful run.
+  pub fn record(&mut self, trace: &[Span]) {
+    self.runs += 1;
+    for p in &mut self.points {
+      if trace.iter().any(|s| p.span.contains(s)) {
+        p.hits += 1;
+      }
+    }
+  }
+
+  /// Record a run that failed with an error (no trace is available).
+  pub const fn record_error(&mut self) {
+    self.runs += 1;
+    self.errors += 1;
+  }
+
+  /// Branch alternatives and inline options that were never chosen.
+  pub fn never_chosen(&self) -> impl Iterator<Item = &CoveragePoint> {
+    self
+      .points
+      .iter()
+      .filter(|p| p.hits == 0 && matches!(p.kind, PointKind::Alternative | PointKind::InlineOption))
+  }
+
+  /// (hit, total) for branch alternatives and inline options.
+  pub fn branch_summary(&self) -> (usize, usize) {
+    self.summary(|k| matches!(k, PointKind::Alternative | PointKind::InlineOption))
+  }
+
+  /// (hit, total) for functions.
+  pub fn function_summary(&self) -> (usize, usize) {
+    self.summary(|k| k == PointKind::Function)
+  }
+
+  fn summary(&self, want: impl Fn(PointKind) -> bool) -> (usize, usize) {
+    let selected = self.points.iter().filter(|p| want(p.kind));
+    let (mut hit, mut total) = (0, 0);
+    for p in selected {
+      total += 1;
+      if p.hits > 0 {
+        hit += 1;
+      }
+    }
+    (hit, total)
+  }
+
+  /// Render an lcov tracefile (`genhtml`-compatible) for a single source file.
+  pub fn to_lcov(&self, source_file: &str) -> String {
+    let mut out = format!("TN:\nSF:{source_file}\n");
+    let functions: Vec<_> = self
+      .points
+      .iter()
+      .filter(|p| p.kind == PointKind::Function)
+      .collect();
+    for p in &functions {
+      let _ = writeln!(out, "FN:{},{}", p.span.start_line, p.owner);
+    }
+    for p in &functions {
+      let _ = writeln!(out, "FNDA:{},{}", p.hits, p.owner);
+    }
+    let (fn_hit, fn_total) = self.function_summary();
+    let _ = writeln!(out, "FNF:{fn_total}\nFNH:{fn_hit}");
+    for p in self
+      .points
+      .iter()
+      .filter(|p| matches!(p.kind, PointKind::Alternative | PointKind::InlineOption))
+    {
+      let _ = writeln!(
+        out,
+        "BRDA:{},{},{},{}",
+        p.span.start_line, p.group, p.index, p.hits
+      );
+    }
+    let (br_hit, br_total) = self.branch_summary();
+    let _ = writeln!(out, "BRF:{br_total}\nBRH:{br_hit}");
+    let lines = self.line_hits();
+    for (line, hits) in &lines {
+      let _ = writeln!(out, "DA:{line},{hits}");
+    }
+    let lines_hit = lines.iter().filter(|(_, h)| *h > 0).count();
+    let _ = writeln!(out, "LF:{}\nLH:{lines_hit}", lines.len());
+    out.push_str("end_of_record\n");
+    out
+  }
+
+  /// Source with a hit column per line: the highest hit count of points starting on the line,
+  /// or `#####` if any alternative starting there was never chosen.
+  pub fn annotate(&self, source: &str) -> String {
+    let mut out = String::new();
+    for (line, text) in (1u32..).zip(source.lines()) {
+      let on_line: Vec<_> = self
+        .points
+        .iter()
+        .filter(|p| p.span.start_line == line)
+        .collect();
+      let marker = if on_line.is_empty() {
+        String::new()
+      } else if on_line
+        .iter()
+        .any(|p| p.hits == 0 && p.kind != PointKind::Function && p.kind != PointKind::Event)
+      {
+        "#####".into()
+      } else {
+        on_line
+          .iter()
+          .map(|p| p.hits)
+          .max()
+          .unwrap_or(0)
+          .to_string()
+      };
+      let _ = writeln!(out, "{marker:>9} | {text}");
+    }
+    out
+  }
+
+  /// (line, hits) for every line where a point starts, sorted by line.
+  fn line_hits(&self) -> Vec<(u32, u64)> {
+    let mut lines: std::collections::BTreeMap<u32, u64> = std::collections::BTreeMap::new();
+    for p in &self.points {
+      let e = lines.entry(p.span.start_line).or_insert(0);
+      *e = (*e).max(p.hits);
+    }
+    lines.into_iter().collect()
+  }
+}
+
+#[derive(Default)]
+struct Collector {
+  points: Vec<CoveragePoint>,
+  groups: usize,
+}
+
+impl Collector {
+  fn definition(&mut self, kind: PointKind, owner: &str, body: &Node) {
+    if let Some(span) = body.span() {
+      let group = self.next_group();
+      self.points.push(CoveragePoint {
+        kind,
+        owner: owner.to_string(),
+        group,
+        index: 0,
+        of: 1,
+        span,
+        hits: 0,
+      });
+    }
+  }
+
+  const fn next_group(&mut self) -> usize {
+    self.groups += 1;
+    self.groups - 1
+  }
+
+  fn choices(&mut self, kind: PointKind, owner: &str, nodes: &[Node]) {
+    let group = self.next_group();
+    let nodes: Vec<_> = nodes
+      .iter()
+      .filter(|n| !matches!(n, Node::SpreadParam { .. }))
+      .collect();
+    for (index, n) in nodes.iter().enumerate() {
+      if let Some(span) = n.span().filter(|s| s.file == 0) {
+        self.points.push(CoveragePoint {
+          kind,
+          owner: owner.to_string(),
+          group,
+          index,
+          of: nodes.len(),
+          span,
+          hits: 0,
+        });
+      }
+    }
+  }
+
+  fn walk(&mut self, node: &Node, owner: &str) {
+    match node {
+      Node::Branch { children, .. } => {
+        self.choices(PointKind::Alternative, owner, children);
+        for c in children {
+          self.walk(c, owner);
+        }
+      }
+      Node::InlineCall { options, .. } => {
+        self.choices(PointKind::InlineOption, owner, options);
+        for o in options {
+          self.walk(o, owner);
+        }
+      }
+      Node::BinaryOp { left, right, .. } => {
+        self.walk(left, owner);
+        self.walk(right, owner);
+      }
+      Node::Call { block, .. } => {
+        for (_, v) in block.iter().flat_map(|b| b.bindings.iter()) {
+          self.walk(v, owner);
+        }
+      }
+      Node::FuncCall { args, .. } => {
+        for a in args {
+          self.walk(a, owner);
+        }
+      }
+      Node::Leaf { .. }
+      | Node::SpreadParam { .. }
+      | Node::SpreadInclude { .. }
+      | Node::SpreadData { .. }
+      | Node::CharBlock { .. } => {}
+    }
+  }
+}
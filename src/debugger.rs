@@ -0,0 +1,319 @@
+//! Step debugger for `branchy debug`, built on the interpreter's [`EvalHook`].
+//!
+//! Commands are read line by line from any iterator and output goes to any writer, so the
+//! debugger can be driven from stdin or from a script in tests.
+
+use crate::ast::{Node, SourceError, Span};
+use crate::interpreter::{EvalHook, HookContext};
+use std::io::Write;
+
+pub const HELP: &str = "\
+  s, step          step into the next node
+  n, next          step over the current node
+  o, out           run until the current function or branch returns
+  c, continue      run until the next breakpoint
+  p, env           show parameters
+  block            show block bindings of the enclosing template call
+  bt               show the call stack
+  choose N         force option N (0-based) of the current branch or inline call;
+                   choose alone lists the options
+  b LINE|NAME      add a breakpoint on a line or function
+  d LINE|NAME      remove a breakpoint
+  l, list          show source around the current node
+  q, quit          abort evaluation
+  h, help          this text";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+  Line(u32),
+  /// User function or template name (without `!`).
+  Function(String),
+}
+
+impl Breakpoint {
+  /// `12` is a line breakpoint; `name` or `!name` is a function breakpoint.
+  pub fn parse(arg: &str) -> Self {
+    arg.parse().map_or_else(
+      |_| Self::Function(arg.trim_start_matches('!').to_string()),
+      Self::Line,
+    )
+  }
+
+  fn hit(&self, node: &Node, line: Option<u32>, last_line: Option<u32>) -> bool {
+    match self {
+      Self::Line(l) => line == Some(*l) && last_line != Some(*l),
+      Self::Function(f) => {
+        matches!(node, Node::Call { name, .. } | Node::FuncCall { name, .. } if name == f)
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+  Continue,
+  StepIn,
+  /// Pause at the next node at this depth or shallower.
+  StepOver(usize),
+  /// Pause at the next node shallower than this depth.
+  StepOut(usize),
+}
+
+pub struct Debugger<I: Iterator<Item = String>, W: Write> {
+  commands: I,
+  out: W,
+  source: Option<String>,
+  breakpoints: Vec<Breakpoint>,
+  mode: Mode,
+  /// Forced option for the branch or inline call at this depth: (depth, index).
+  forced: Option<(usize, usize)>,
+  /// Depths of paused nodes whose result is still to be printed.
+  watching: Vec<usize>,
+  last_line: Option<u32>,
+}
+
+impl<I: Iterator<Item = String>, W: Write> Debugger<I, W> {
+  /// Without breakpoints the debugger pauses at the first node.
+  pub const fn new(
+    commands: I,
+    out: W,
+    source: Option<String>,
+    breakpoints: Vec<Breakpoint>,
+  ) -> Self {
+    let mode = if breakpoints.is_empty() {
+      Mode::StepIn
+    } else {
+      Mode::Continue
+    };
+    Self {
+      commands,
+      out,
+      source,
+      breakpoints,
+      mode,
+      forced: None,
+      watching: Vec::new(),
+      last_line: None,
+    }
+  }
+
+  pub fn into_output(self) -> W {
+    self.out
+  }
+
+  fn should_pause(&self, ctx: &HookContext<'_>, line: Option<u32>) -> bool {
+    let stepped = match self.mode {
+      Mode::Continue => false,
+      Mode::StepIn => true,
+      Mode::StepOver(d) => ctx.depth <= d,
+      Mode::StepOut(d) => ctx.depth < d,
+    };
+    stepped
+      || self
+        .breakpoints
+        .iter()
+        .any(|b| b.hit(ctx.node, line, self.last_line))
+  }
+
+  fn snippet(&self, node: &Node) -> String {
+    let text = node
+      .span()
+      .zip(self.source.as_deref())
+      .and_then(|(s, src)| s.text(src))
+      .and_then(|t| t.lines().next())
+      .map(str::trim);
+    match text {
+      Some(t) if t.chars().count() > 60 => format!("{}...", t.chars().take(60).collect::<String>()),
+      Some(t) => t.to_string(),
+      None => node_kind(node).to_string(),
+    }
+  }
+
+  fn show_location(&mut self, ctx: &HookContext<'_>) {
+    let at = ctx.node.span().map_or_else(
+      || "?".to_string(),
+      |s| format!("{}:{}", s.start_line, s.start_column),
+    );
+    let frame = ctx
+      .frames
+      .last()
+      .map_or_else(|| "main".to_string(), |f| format!("!{f}"));
+    let snippet = self.snippet(ctx.node);
+    let _ = writeln!(self.out, "{at} in {frame} (depth {}): {snippet}", ctx.depth);
+  }
+
+  fn list(&mut self, span: Option<Span>) {
+    let (Some(src), Some(span)) = (self.source.as_deref(), span) else {
+      let _ = writeln!(self.out, "no source available");
+      return;
+    };
+    let from = span.start_line.saturating_sub(2).max(1);
+    for (n, text) in (1u32..).zip(src.lines()) {
+      if (from..=span.start_line + 2).contains(&n) {
+        let marker = if n == span.start_line { '>' } else { ' ' };
+        let _ = writeln!(self.out, "{marker}{n:>4} | {text}");
+      }
+    }
+  }
+
+  fn show_env(&mut self, ctx: &HookContext<'_>) {
+    let mut vars: Vec<_> = ctx.env.iter().collect();
+    vars.sort();
+    if vars.is_empty() {
+      let _ = writeln!(self.out, "(no parameters)");
+    }
+    for (k, v) in vars {
+      let _ = writeln!(self.out, ":{k} = {v:?}");
+    }
+  }
+
+  fn show_block(&mut self, ctx: &HookContext<'_>) {
+    let Some(block) = ctx.block else {
+      let _ = writeln!(self.out, "(no block)");
+      return;
+    };
+    let mut names: Vec<_> = block.keys().collect();
+    names.sort();
+    for k in names {
+      let snippet = self.snippet(&block[k]);
+      let _ = writeln!(self.out, "{k} = {snippet}");
+    }
+  }
+
+  /// Force option `arg` of the current branch (after expanding `...:param` spreads) or inline
+  /// call; lists the options when `arg` is not one of them.
+  fn force(&mut self, ctx: &HookContext<'_>, arg: &str) {
+    let Some(options) = ctx.options() else {
+      let _ = writeln!(self.out, "not at a branch or inline call");
+      return;
+    };
+    let count = options.len();
+    match arg.parse::<usize>() {
+      Ok(i) if i < count => {
+        self.forced = Some((ctx.depth, i));
+        let snippet = self.snippet(&options[i]);
+        let _ = writeln!(self.out, "will choose option {i} of {count}: {snippet}");
+      }
+      _ => {
+        let _ = writeln!(self.out, "choose N: N must be in 0..{count}");
+        for (i, o) in options.iter().enumerate() {
+          let snippet = self.snippet(o);
+          let _ = writeln!(self.out, "  {i}: {snippet}");
+        }
+      }
+    }
+  }
+
+  /// Read commands until one resumes evaluation. `Err` aborts (quit).
+  fn prompt(&mut self, ctx: &HookContext<'_>) -> Result<(), String> {
+    self.show_location(ctx);
+    loop {
+      let _ = write!(self.out, "(debug) ");
+      let _ = self.out.flush();
+      let Some(line) = self.commands.next() else {
+        self.mode = Mode::Continue;
+        return Ok(());
+      };
+      let line = line.trim();
+      let (cmd, arg) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(c, a)| (c, a.trim()));
+      match cmd {
+        "" => {}
+        "s" | "step" => {
+          self.mode = Mode::StepIn;
+          return Ok(());
+        }
+        "n" | "next" => {
+          self.mode = Mode::StepOver(ctx.depth);
+          return Ok(());
+        }
+        "o" | "out" => {
+          self.mode = Mode::StepOut(ctx.depth);
+          return Ok(());
+        }
+        "c" | "continue" => {
+          self.mode = Mode::Continue;
+          return Ok(());
+        }
+        "p" | "env" => self.show_env(ctx),
+        "block" => self.show_block(ctx),
+        "bt" => {
+          let _ = writeln!(self.out, "main");
+          for (i, f) in ctx.frames.iter().enumerate() {
+            let _ = writeln!(self.out, "{}!{f}", "  ".repeat(i + 1));
+          }
+        }
+        "choose" => self.force(ctx, arg),
+        "b" | "break" if !arg.is_empty() => {
+          self.breakpoints.push(Breakpoint::parse(arg));
+          let _ = writeln!(self.out, "breakpoint added: {arg}");
+        }
+        "d" | "delete" if !arg.is_empty() => {
+          let bp = Breakpoint::parse(arg);
+          self.breakpoints.retain(|b| *b != bp);
+          let _ = writeln!(self.out, "breakpoint removed: {arg}");
+        }
+        "l" | "list" => self.list(ctx.node.span()),
+        "q" | "quit" => return Err("debugger: quit".into()),
+        "h" | "help" => {
+          let _ = writeln!(self.out, "{HELP}");
+        }
+        _ => {
+          let _ = writeln!(self.out, "unknown command: {line} (try help)");
+        }
+      }
+    }
+  }
+}
+
+impl<I: Iterator<Item = String>, W: Write> EvalHook for Debugger<I, W> {
+  fn before(&mut self, ctx: &HookContext<'_>) -> Result<(), String> {
+    let line = ctx.node.span().map(|s| s.start_line);
+    let pause = self.should_pause(ctx, line);
+    if line.is_some() {
+      self.last_line = line;
+    }
+    if !pause {
+      return Ok(());
+    }
+    self.watching.push(ctx.depth);
+    self.prompt(ctx)
+  }
+
+  fn after(&mut self, ctx: &HookContext<'_>, result: Result<&str, &SourceError>) {
+    if self.watching.last() != Some(&ctx.depth) {
+      return;
+    }
+    self.watching.pop();
+    let _ = match result {
+      Ok(s) => writeln!(self.out, "=> {s:?}"),
+      Err(e) => writeln!(self.out, "=> error: {e}"),
+    };
+  }
+
+  fn choose(&mut self, _span: Option<Span>, depth: usize, chosen: usize, count: usize) -> usize {
+    match self.forced {
+      Some((d, i)) if d == depth && i < count => {
+        self.forced = None;
+        i
+      }
+      _ => chosen,
+    }
+  }
+}
+
+const fn node_kind(node: &Node) -> &'static str {
+  match node {
+    Node::Branch { .. } => "branch",
+    Node::Leaf { .. } => "leaf",
+    Node::BinaryOp { .. } => "operator",
+    Node::Call { .. } => "call",
+    Node::InlineCall { .. } => "inline call",
+    Node::FuncCall { .. } => "function call",
+    Node::SpreadParam { .. } => "spread",
+    Node::SpreadInclude { .. } => "include spread",
+    Node::SpreadData { .. } => "data spread",
+    Node::CharBlock { .. } => "char block",
+  }
+}
@@ -0,0 +1,430 @@
+//! Completion and hover for editors, from the spans the parser records.
+//!
+//! Positions are 1-based lines and columns of the entry source, columns in bytes as in
+//! [`Span`]. Only definitions of the entry source count as the scope of a position; functions
+//! and events of included files are suggested by name.
+
+use crate::ast::{span_from_offsets, EventMatcher, FunctionDef, Literal, Node, Program, Span};
+use crate::builtins::{self, BuiltinFn};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::hash::BuildHasher;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+  Builtin,
+  Function,
+  Event,
+  /// Positional param of a function.
+  Param,
+  /// Param a function reads without declaring it, bound by the block of its calls.
+  BlockParam,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Completion {
+  /// Name to insert, without its sigil (`!`, `:`, `@`).
+  pub label: String,
+  pub kind: CompletionKind,
+  /// Signature, e.g. `!greet(:who)`.
+  pub detail: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub documentation: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Completions {
+  /// The partial name before the cursor, which a completion replaces.
+  pub span: Span,
+  pub items: Vec<Completion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Hover {
+  /// The name under the cursor.
+  pub span: Span,
+  pub signature: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub documentation: Option<String>,
+}
+
+/// What a name typed at the cursor can be, by the sigil before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+  /// `!name`: a builtin or user function.
+  Function,
+  /// `@name`: an event.
+  Event,
+  /// `:name` or `:?name`.
+  Param,
+  /// `...:name`.
+  Spread,
+  /// A bare name: a function called as a template (`name :p { ... }`).
+  Template,
+}
+
+/// The partial name at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prefix {
+  pub context: Context,
+  pub word: String,
+  pub span: Span,
+  /// Byte range of the sigil and the word.
+  start: usize,
+  end: usize,
+}
+
+impl Prefix {
+  /// The partial name at `line`, `column`; None when the position is outside `source`.
+  pub fn at(source: &str, line: u32, column: u32) -> Option<Self> {
+    let end = offset(source, line, column)?;
+    let start = source[..end]
+      .char_indices()
+      .rev()
+      .take_while(|&(_, c)| c.is_alphanumeric() || c == '_' || c == '.' || !c.is_ascii())
+      .last()
+      .map_or(end, |(i, _)| i);
+    let before = &source[..start];
+    let (context, sigil) = if before.ends_with("...:") {
+      (Context::Spread, 4)
+    } else if before.ends_with(":?") {
+      (Context::Param, 2)
+    } else if before.ends_with(':') {
+      (Context::Param, 1)
+    } else if before.ends_with('!') {
+      (Context::Function, 1)
+    } else if before.ends_with('@') {
+      (Context::Event, 1)
+    } else {
+      (Context::Template, 0)
+    };
+    Some(Self {
+      context,
+      word: source[start..end].to_string(),
+      span: span_from_offsets(source, start, end),
+      start: start - sigil,
+      end,
+    })
+  }
+
+  /// `source` without the sigil and partial name, which usually leaves a program that parses
+  /// while a name is being typed.
+  pub fn strip(&self, source: &str) -> String {
+    [&source[..self.start], &source[self.end..]].concat()
+  }
+}
+
+/// Byte offset of `line`, `column`, clamped to the end of the line.
+fn offset(source: &str, line: u32, column: u32) -> Option<usize> {
+  let start = match line {
+    0 => return None,
+    1 => 0,
+    _ => source.match_indices('\n').nth(line as usize - 2)?.0 + 1,
+  };
+  let end = source[start..]
+    .find('\n')
+    .map_or(source.len(), |i| start + i);
+  let mut at = (start + (column.max(1) as usize - 1)).min(end);
+  while !source.is_char_boundary(at) {
+    at -= 1;
+  }
+  Some(at)
+}
+
+/// Completions of `prefix`, with names in scope from `program` (the entry source as loaded, or
+/// None when it does not load even without the prefix). Matching ignores ASCII case.
+pub fn complete<S: BuildHasher>(
+  prefix: &Prefix,
+  program: Option<&Program>,
+  builtins: &HashMap<String, BuiltinFn, S>,
+) -> Completions {
+  let at = (prefix.span.end_line, prefix.span.end_column);
+  let functions = || first_definitions(program.map_or(&[][..], |p| &p.functions));
+  let mut items = Vec::new();
+  match prefix.context {
+    Context::Function => {
+      items.extend(functions().into_iter().map(function_completion));
+      let mut names: Vec<&String> = builtins.keys().collect();
+      names.sort();
+      items.extend(names.into_iter().map(|name| {
+        let (detail, documentation) = builtin_doc(name);
+        Completion {
+          label: name.clone(),
+          kind: CompletionKind::Builtin,
+          detail,
+          documentation,
+        }
+      }));
+    }
+    Context::Template => items.extend(functions().into_iter().map(function_completion)),
+    Context::Event => {
+      for e in program.iter().flat_map(|p| &p.events) {
+        if let EventMatcher::ByName(name) = &e.matcher {
+          items.push(Completion {
+            label: name.clone(),
+            kind: CompletionKind::Event,
+            detail: format!("@{name}"),
+            documentation: e.file.as_deref().map(defined_in),
+          });
+        }
+      }
+    }
+    Context::Param | Context::Spread => {
+      if let Some(program) = program {
+        items = params_in_scope(program, at);
+      }
+    }
+  }
+  let word = prefix.word.to_ascii_lowercase();
+  let mut seen = BTreeSet::new();
+  items.retain(|c| c.label.to_ascii_lowercase().starts_with(&word) && seen.insert(c.label.clone()));
+  Completions {
+    span: prefix.span,
+    items,
+  }
+}
+
+/// Signature and description of the function called at `line`, `column` (the cursor on its
+/// name), in a definition of the entry source.
+pub fn hover<S: BuildHasher>(
+  program: &Program,
+  line: u32,
+  column: u32,
+  builtins: &HashMap<String, BuiltinFn, S>,
+) -> Option<Hover> {
+  let at = (line, column);
+  let (call, span) = entry_nodes(program).find_map(|n| call_name_at(n, at))?;
+  let name = match call {
+    // Builtins are called as `!name(...)` only, and come before functions of the same name.
+    Node::FuncCall { name, .. } if builtins.contains_key(name) => {
+      let (signature, documentation) = builtin_doc(name);
+      return Some(Hover {
+        span,
+        signature,
+        documentation,
+      });
+    }
+    Node::FuncCall { name, .. } | Node::Call { name, .. } => name,
+    _ => return None,
+  };
+  let f = program.functions.iter().find(|f| f.name == *name)?;
+  Some(Hover {
+    span,
+    signature: signature(f),
+    documentation: f.file.as_deref().map(defined_in),
+  })
+}
+
+/// Bodies of the definitions of the entry source, then its main branch.
+fn entry_nodes(program: &Program) -> impl Iterator<Item = &Node> {
+  let functions = program.functions.iter().filter(|f| f.file.is_none());
+  let events = program.events.iter().filter(|e| e.file.is_none());
+  functions
+    .map(|f| &f.body)
+    .chain(events.map(|e| &e.body))
+    .chain(std::iter::once(&program.main))
+}
+
+/// The innermost call whose name is at `at`, and the span of the name.
+fn call_name_at(node: &Node, at: (u32, u32)) -> Option<(&Node, Span)> {
+  if let Some(inner) = children(node).into_iter().find_map(|c| call_name_at(c, at)) {
+    return Some(inner);
+  }
+  let (name, span, skip) = match node {
+    Node::FuncCall { name, span, .. } => (name, (*span)?, 1),
+    Node::Call { name, span, .. } => (name, (*span)?, 0),
+    _ => return None,
+  };
+  let start = span.start_column + skip;
+  let end = start + u32::try_from(name.len()).ok()?;
+  (at.0 == span.start_line && (start..end).contains(&at.1)).then_some((
+    node,
+    Span {
+      start_line: span.start_line,
+      start_column: start,
+      end_line: span.start_line,
+      end_column: end,
+      file: span.file,
+    },
+  ))
+}
+
+/// Params a name typed at `at` can refer to: inside the params or block of a template call,
+/// those of the called template; in a function body, its params and block params; elsewhere,
+/// the params read in the same definition (given as `vars` of a run).
+fn params_in_scope(program: &Program, at: (u32, u32)) -> Vec<Completion> {
+  let functions = first_definitions(&program.functions);
+  if let Some(Node::Call { name, params, .. }) = entry_nodes(program).find_map(|n| call_at(n, at)) {
+    return functions.iter().find(|f| f.name == *name).map_or_else(
+      || {
+        params
+          .iter()
+          .map(|p| param(p, CompletionKind::Param, format!("param of {name}")))
+          .collect()
+      },
+      |f| function_params(f),
+    );
+  }
+  let inside = |node: &Node| node.span().is_some_and(|s| contains(s, at));
+  if let Some(f) = program
+    .functions
+    .iter()
+    .find(|f| f.file.is_none() && inside(&f.body))
+  {
+    return function_params(f);
+  }
+  let body = program
+    .events
+    .iter()
+    .filter(|e| e.file.is_none())
+    .map(|e| &e.body)
+    .find(|b| inside(b))
+    .unwrap_or(&program.main);
+  let mut read = BTreeSet::new();
+  params_read(body, &mut read);
+  read
+    .into_iter()
+    .map(|p| param(p, CompletionKind::Param, "param of the run".to_string()))
+    .collect()
+}
+
+/// The innermost template call around `at`, unless `at` is inside the value of a binding.
+fn call_at(node: &Node, at: (u32, u32)) -> Option<&Node> {
+  match node {
+    Node::Call { block, span, .. } => {
+      if !span.is_some_and(|s| contains(s, at)) {
+        return None;
+      }
+      let bindings = block.iter().flat_map(|b| b.bindings.iter());
+      for (_, value) in bindings {
+        if value.span().is_some_and(|s| contains(s, at)) {
+          return call_at(value, at);
+        }
+      }
+      Some(node)
+    }
+    _ => children(node).into_iter().find_map(|c| call_at(c, at)),
+  }
+}
+
+/// Positional params of `f`, then the block params its body reads.
+fn function_params(f: &FunctionDef) -> Vec<Completion> {
+  let mut read = BTreeSet::new();
+  params_read(&f.body, &mut read);
+  let positional = f
+    .params
+    .iter()
+    .map(|p| param(p, CompletionKind::Param, format!("param of !{}", f.name)));
+  let block = read
+    .into_iter()
+    .filter(|p| !f.params.iter().any(|q| q == p))
+    .map(|p| {
+      param(
+        p,
+        CompletionKind::BlockParam,
+        format!("block param of !{}", f.name),
+      )
+    });
+  positional.chain(block).collect()
+}
+
+fn param(name: &str, kind: CompletionKind, documentation: String) -> Completion {
+  Completion {
+    label: name.to_string(),
+    kind,
+    detail: format!(":{name}"),
+    documentation: Some(documentation),
+  }
+}
+
+fn function_completion(f: &FunctionDef) -> Completion {
+  Completion {
+    label: f.name.clone(),
+    kind: CompletionKind::Function,
+    detail: signature(f),
+    documentation: f.file.as_deref().map(defined_in),
+  }
+}
+
+/// The definition of each name the interpreter calls: the first one.
+fn first_definitions(functions: &[FunctionDef]) -> Vec<&FunctionDef> {
+  let mut seen = BTreeSet::new();
+  functions
+    .iter()
+    .filter(|f| seen.insert(f.name.as_str()))
+    .collect()
+}
+
+/// `!name(:a, :b)`, followed by `{ :c; }` when the body reads block params.
+fn signature(f: &FunctionDef) -> String {
+  let params: Vec<String> = f.params.iter().map(|p| format!(":{p}")).collect();
+  let mut out = format!("!{}({})", f.name, params.join(", "));
+  let mut read = BTreeSet::new();
+  params_read(&f.body, &mut read);
+  let block: Vec<String> = read
+    .into_iter()
+    .filter(|p| !f.params.iter().any(|q| q == p))
+    .map(|p| format!(":{p};"))
+    .collect();
+  if !block.is_empty() {
+    let _ = write!(out, " {{ {} }}", block.join(" "));
+  }
+  out
+}
+
+/// Signature and description of a builtin; registries other than the default one may have
+/// builtins without a description.
+fn builtin_doc(name: &str) -> (String, Option<String>) {
+  builtins::doc(name).map_or_else(
+    || (format!("!{name}(...)"), None),
+    |(signature, description)| (signature.to_string(), Some(description.to_string())),
+  )
+}
+
+fn defined_in(file: &str) -> String {
+  format!("Defined in {file}.")
+}
+
+/// Names `node` reads as `:name`, `:?name`, `...:name` or template call params.
+fn params_read<'a>(node: &'a Node, out: &mut BTreeSet<&'a str>) {
+  match node {
+    Node::Leaf {
+      lit: Literal::Param(p) | Literal::OptionalParam(p),
+      ..
+    }
+    | Node::SpreadParam { param: p, .. } => {
+      out.insert(p);
+    }
+    Node::Call { params, .. } => out.extend(params.iter().map(String::as_str)),
+    _ => {}
+  }
+  for c in children(node) {
+    params_read(c, out);
+  }
+}
+
+fn children(node: &Node) -> Vec<&Node> {
+  match node {
+    Node::Branch { children, .. } => children.iter().collect(),
+    Node::InlineCall { options, .. } => options.iter().collect(),
+    Node::FuncCall { args, .. } => args.iter().collect(),
+    Node::BinaryOp { left, right, .. } => vec![left, right],
+    Node::Call { block, .. } => block
+      .iter()
+      .flat_map(|b| b.bindings.iter().map(|(_, v)| v))
+      .collect(),
+    Node::Leaf { .. }
+    | Node::SpreadParam { .. }
+    | Node::SpreadInclude { .. }
+    | Node::SpreadData { .. }
+    | Node::CharBlock { .. } => Vec::new(),
+  }
+}
+
+/// Whether `at` is in `span`, its end included (a cursor right after a name is on it).
+fn contains(span: Span, at: (u32, u32)) -> bool {
+  (span.start_line, span.start_column) <= at && at <= (span.end_line, span.end_column)
+}
@@ -1,7 +1,8 @@
 //! Format (unparse) Branchy AST to canonical source text.
 
 use crate::ast::{
-  BinOp, CallBlock, CharBlockCount, Event, EventMatcher, FunctionDef, Literal, Node, Program,
+  BinOp, CallBlock, CharBlockCount, DataFormat, Event, EventMatcher, FunctionDef, Import, Include,
+  Literal, Node, Program,
 };
 
 /// Formatting options (Prettier-like).
@@ -89,12 +90,11 @@ fn char_block_to_string(ranges: &[(char, char)], count: &CharBlockCount) -> Stri
 pub fn format_program(program: &Program, options: &FormatOptions) -> String {
   let mut out = String::new();
 
-  for (i, path) in program.includes.iter().enumerate() {
+  for (i, include) in program.includes.iter().enumerate() {
     if i > 0 && options.newline_after_include {
       out.push('\n');
     }
-    out.push_str("include ");
-    out.push_str(&escape_string(path));
+    out.push_str(&format_include(include));
     out.push_str(";\n");
   }
 
@@ -108,7 +108,9 @@ pub fn format_program(program: &Program, options: &FormatOptions) -> String {
     out.push('\n');
   }
 
-  if options.newline_before_main && (!program.includes.is_empty() || !program.functions.is_empty() || !program.events.is_empty()) {
+  if options.newline_before_main
+    && (!program.includes.is_empty() || !program.functions.is_empty() || !program.events.is_empty())
+  {
     out.push('\n');
   }
 
@@ -117,17 +119,50 @@ pub fn format_program(program: &Program, options: &FormatOptions) -> String {
   out
 }
 
+fn format_include(include: &Include) -> String {
+  let path = escape_string(&include.path);
+  match &include.import {
+    Import::All => format!("include {path}"),
+    Import::Namespace(ns) => format!("include {path} as {ns}"),
+    Import::Names(names) => format!("from {path} import {}", names.join(", ")),
+  }
+}
+
+fn format_data_spread(path: &str, format: &DataFormat) -> String {
+  let path = escape_string(path);
+  match format {
+    DataFormat::Lines => format!("...lines {path}"),
+    DataFormat::Csv { column } => format!("...csv {path} column {}", word_or_string(column)),
+    DataFormat::Json { path: p } if p.is_empty() => format!("...json {path}"),
+    DataFormat::Json { path: p } => format!("...json {path} {}", word_or_string(p)),
+  }
+}
+
+/// `s` as a bare word if it lexes as one identifier (dots allowed between parts), else quoted.
+fn word_or_string(s: &str) -> String {
+  let word = s.split('.').all(|part| {
+    part
+      .chars()
+      .next()
+      .is_some_and(|c| c.is_alphabetic() || c == '_' || !c.is_ascii())
+      && part
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || !c.is_ascii())
+  });
+  if word && s != "include" {
+    s.to_string()
+  } else {
+    escape_string(s)
+  }
+}
+
 fn format_event(event: &Event, options: &FormatOptions) -> String {
   let matcher = match &event.matcher {
     EventMatcher::ByName(name) => format!("@{}", name),
     EventMatcher::ByStr(s) => escape_string(s),
     EventMatcher::ByRegex(pat) => format!("~{}", escape_string(pat)),
   };
-  format!(
-    "{} = {};\n",
-    matcher,
-    format_node(&event.body, options, 0)
-  )
+  format!("{} = {};\n", matcher, format_node(&event.body, options, 0))
 }
 
 fn format_function_def(f: &FunctionDef, options: &FormatOptions) -> String {
@@ -145,7 +180,9 @@ fn format_node(node: &Node, options: &FormatOptions, depth: usize) -> String {
   match node {
     Node::Branch { children, .. } => format_branch(children, options, depth),
     Node::Leaf { lit, .. } => format_literal(lit),
-    Node::BinaryOp { op, left, right, .. } => {
+    Node::BinaryOp {
+      op, left, right, ..
+    } => {
       let sep = if options.spaces_around_binary {
         match op {
           BinOp::Plus => " + ",
@@ -173,7 +210,11 @@ fn format_node(node: &Node, options: &FormatOptions, depth: usize) -> String {
     } => {
       let mut s = name.clone();
       for p in params {
-        let prefix = if optional_params.contains(p) { ":?" } else { ":" };
+        let prefix = if optional_params.contains(p) {
+          ":?"
+        } else {
+          ":"
+        };
         s.push_str(&format!(" {}{}", prefix, p));
       }
       if let Some(blk) = block {
@@ -185,16 +226,27 @@ fn format_node(node: &Node, options: &FormatOptions, depth: usize) -> String {
       }
       s
     }
-    Node::InlineCall { name, options: opts, .. } => {
-      let parts: Vec<String> = opts.iter().map(|n| format_node(n, options, depth)).collect();
+    Node::InlineCall {
+      name,
+      options: opts,
+      ..
+    } => {
+      let parts: Vec<String> = opts
+        .iter()
+        .map(|n| format_node(n, options, depth))
+        .collect();
       format!("{} <{}>", name, parts.join("|"))
     }
     Node::FuncCall { name, args, .. } => {
-      let args_str: Vec<String> = args.iter().map(|a| format_node(a, options, depth)).collect();
+      let args_str: Vec<String> = args
+        .iter()
+        .map(|a| format_node(a, options, depth))
+        .collect();
       format!("!{}({})", name, args_str.join(", "))
     }
     Node::SpreadParam { param, .. } => format!("...:{}", param),
     Node::SpreadInclude { path, .. } => format!("...include {}", escape_string(path)),
+    Node::SpreadData { path, format, .. } => format_data_spread(path, format),
     Node::CharBlock { ranges, count, .. } => char_block_to_string(ranges, count),
   }
 }
@@ -234,7 +286,11 @@ fn format_call_block(block: &CallBlock, options: &FormatOptions, depth: usize) -
 }
 
 fn branch_single_line_len(children: &[Node], options: &FormatOptions) -> usize {
-  let sep_len = if options.semicolon_between_elements { 2 } else { 1 };
+  let sep_len = if options.semicolon_between_elements {
+    2
+  } else {
+    1
+  };
   let trail = if options.trailing_semicolon { 1 } else { 0 };
   let mut len = 2; // [ ]
   for (i, c) in children.iter().enumerate() {
@@ -251,20 +307,47 @@ fn node_approx_len(node: &Node, options: &FormatOptions) -> usize {
     Node::Branch { children, .. } => branch_single_line_len(children, options),
     Node::Leaf { lit, .. } => literal_approx_len(lit),
     Node::BinaryOp { left, right, .. } => {
-      node_approx_len(left, options) + node_approx_len(right, options) + if options.spaces_around_binary { 5 } else { 1 }
+      node_approx_len(left, options)
+        + node_approx_len(right, options)
+        + if options.spaces_around_binary { 5 } else { 1 }
     }
-    Node::Call { name, params, block, .. } => {
-      name.len() + params.iter().map(|p| p.len() + 2).sum::<usize>()
-        + block.as_ref().map(|b| 10 + b.bindings.len() * 5).unwrap_or(0)
+    Node::Call {
+      name,
+      params,
+      block,
+      ..
+    } => {
+      name.len()
+        + params.iter().map(|p| p.len() + 2).sum::<usize>()
+        + block
+          .as_ref()
+          .map(|b| 10 + b.bindings.len() * 5)
+          .unwrap_or(0)
     }
-    Node::InlineCall { name, options: opts, .. } => {
-      name.len() + 2 + opts.iter().map(|n| node_approx_len(n, options)).sum::<usize>() + opts.len().saturating_sub(1)
+    Node::InlineCall {
+      name,
+      options: opts,
+      ..
+    } => {
+      name.len()
+        + 2
+        + opts
+          .iter()
+          .map(|n| node_approx_len(n, options))
+          .sum::<usize>()
+        + opts.len().saturating_sub(1)
     }
     Node::FuncCall { name, args, .. } => {
-      name.len() + 2 + args.iter().map(|a| node_approx_len(a, options)).sum::<usize>()
+      name.len()
+        + 2
+        + args
+          .iter()
+          .map(|a| node_approx_len(a, options))
+          .sum::<usize>()
     }
     Node::SpreadParam { param, .. } => 4 + param.len(),
     Node::SpreadInclude { path, .. } => 12 + path.len(),
+    Node::SpreadData { path, format, .. } => format_data_spread(path, format).len(),
     Node::CharBlock { ranges, count, .. } => char_block_to_string(ranges, count).len(),
   }
 }
@@ -284,8 +367,7 @@ fn format_branch(children: &[Node], options: &FormatOptions, depth: usize) -> St
     BracketStyle::SingleLine => true,
     BracketStyle::MultiLine => false,
     BracketStyle::Auto => {
-      children.len() <= 1
-        || branch_single_line_len(children, options) <= options.max_line_length
+      children.len() <= 1 || branch_single_line_len(children, options) <= options.max_line_length
     }
   };
 
@@ -356,6 +438,35 @@ mod tests {
     assert_eq!(p.events.len(), p2.events.len());
   }
 
+  #[test]
+  fn format_imports_roundtrip() {
+    let src = "include \"a.branchy\"; include \"ui\" as ui;\nfrom \"lib\" import greet, !tag;\n[ !ui.title(x); ui.card :_ { :_ = [ y; z ]; }; ]";
+    let p = parse_program(src).unwrap();
+    let out = format_program(&p, &FormatOptions::default());
+    assert!(
+      out.starts_with(
+        "include \"a.branchy\";\ninclude \"ui\" as ui;\nfrom \"lib\" import greet, tag;\n"
+      ),
+      "{}",
+      out
+    );
+    assert_eq!(parse_program(&out).unwrap().includes, p.includes);
+    roundtrip_and_idempotent(src);
+  }
+
+  #[test]
+  fn format_data_spreads_roundtrip() {
+    let src = "[ ...lines \"c.txt\"; ...csv \"p.csv\" column \"first name\"; ...json \"d.json\" a.b; ...json \"l.json\"; ]";
+    let out = format_program(&parse_program(src).unwrap(), &FormatOptions::default());
+    assert!(
+      out.contains("...csv \"p.csv\" column \"first name\""),
+      "{}",
+      out
+    );
+    assert!(out.contains("...json \"d.json\" a.b"), "{}", out);
+    roundtrip_and_idempotent(src);
+  }
+
   #[test]
   fn format_idempotent_hello() {
     roundtrip_and_idempotent(r#"[ hello; world; 123; ]"#);
@@ -420,9 +531,11 @@ mod tests {
 
   #[test]
   fn format_idempotent_include() {
-    roundtrip_and_idempotent(r#"include "lib.branchy";
+    roundtrip_and_idempotent(
+      r#"include "lib.branchy";
 
-[ a; b; ]"#);
+[ a; b; ]"#,
+    );
   }
 
   #[test]
@@ -432,7 +545,11 @@ mod tests {
     assert!(escaped.starts_with('"') && escaped.ends_with('"'));
     let p = parse_program(&format!("[ {}; ]", escaped)).unwrap();
     if let Node::Branch { children, .. } = &p.main {
-      if let Some(Node::Leaf { lit: Literal::Str(back), .. }) = children.first() {
+      if let Some(Node::Leaf {
+        lit: Literal::Str(back),
+        ..
+      }) = children.first()
+      {
         assert_eq!(back, s);
         return;
       }
@@ -1,6 +1,8 @@
 use crate::ast::{Node, Program, SourceError};
 use crate::builtins::BuiltinFn;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::time::Instant;
 
 use super::eval_branch;
 use super::eval_call;
@@ -8,27 +10,91 @@ use super::eval_func;
 use super::eval_inline;
 use super::eval_leaf;
 use super::eval_op;
+use super::hook::{EvalHook, HookContext};
+use super::profile::{Profile, ProfileKind};
 use super::{err_span_impl, node_span};
 
+/// Builtins by name, whatever the hasher of the caller's map.
+pub(super) trait Builtins {
+  fn builtin(&self, name: &str) -> Option<&BuiltinFn>;
+}
+
+impl<S: BuildHasher> Builtins for HashMap<String, BuiltinFn, S> {
+  fn builtin(&self, name: &str) -> Option<&BuiltinFn> {
+    self.get(name)
+  }
+}
+
 pub(super) struct EvalState<'a, 'b, R: rand::RngCore> {
   pub program: &'a Program,
-  pub builtins: &'a HashMap<String, BuiltinFn>,
+  pub builtins: &'a dyn Builtins,
   pub rng: &'b mut R,
   pub trace: &'b mut Vec<crate::ast::Span>,
+  pub profile: Option<&'b mut Profile>,
+  pub hook: Option<&'b mut dyn EvalHook>,
+  /// Current node depth (only tracked when a hook is installed).
+  pub depth: usize,
+  /// Functions/templates being evaluated, outermost first.
+  pub frames: Vec<&'a str>,
+  /// Output length past which repeats, concatenations and builtins stop early (the hook's
+  /// [`EvalHook::output_limit`]).
+  pub output_cap: usize,
 }
 
 impl<'a, 'b, R: rand::RngCore> EvalState<'a, 'b, R> {
   pub fn new(
     program: &'a Program,
-    builtins: &'a HashMap<String, BuiltinFn>,
+    builtins: &'a dyn Builtins,
     rng: &'b mut R,
     trace: &'b mut Vec<crate::ast::Span>,
+    profile: Option<&'b mut Profile>,
+    hook: Option<&'b mut dyn EvalHook>,
   ) -> Self {
+    let output_cap = hook
+      .as_deref()
+      .and_then(EvalHook::output_limit)
+      .unwrap_or(usize::MAX);
     Self {
       program,
       builtins,
       rng,
       trace,
+      profile,
+      hook,
+      depth: 0,
+      frames: Vec::new(),
+      output_cap,
+    }
+  }
+
+  /// Randomly pick one of `items`; an installed hook may override the choice.
+  pub fn choose<'n>(
+    &mut self,
+    span: Option<crate::ast::Span>,
+    items: &'n [Node],
+  ) -> Option<&'n Node> {
+    use rand::seq::SliceRandom;
+    let picked = items.choose(self.rng)?;
+    let Some(hook) = self.hook.as_deref_mut() else {
+      return Some(picked);
+    };
+    let chosen = items
+      .iter()
+      .position(|n| std::ptr::eq(n, picked))
+      .unwrap_or(0);
+    let i = hook.choose(span, self.depth.saturating_sub(1), chosen, items.len());
+    items.get(i).or(Some(picked))
+  }
+
+  /// Start timing a profiled section (None when profiling is off).
+  pub fn prof_start(&self) -> Option<Instant> {
+    self.profile.as_ref().map(|_| Instant::now())
+  }
+
+  /// Finish a section started with `prof_start`.
+  pub fn prof_end(&mut self, start: Option<Instant>, kind: ProfileKind, name: &str, items: u64) {
+    if let (Some(p), Some(t)) = (self.profile.as_deref_mut(), start) {
+      p.record(kind, name, t.elapsed(), items);
     }
   }
 
@@ -41,18 +107,64 @@ impl<'a, 'b, R: rand::RngCore> EvalState<'a, 'b, R> {
     node: &Node,
     block_nodes: Option<&HashMap<String, Node>>,
     env: &mut HashMap<String, String>,
+  ) -> Result<String, SourceError> {
+    let Some(hook) = self.hook.as_deref_mut() else {
+      return self.eval_node(node, block_nodes, env);
+    };
+    let ctx = HookContext {
+      node,
+      depth: self.depth,
+      env,
+      block: block_nodes,
+      frames: &self.frames,
+    };
+    hook
+      .before(&ctx)
+      .map_err(|m| err_span_impl(m, node_span(node)))?;
+    self.depth += 1;
+    let out = self.eval_node(node, block_nodes, env);
+    self.depth -= 1;
+    if let Some(hook) = self.hook.as_deref_mut() {
+      let ctx = HookContext {
+        node,
+        depth: self.depth,
+        env,
+        block: block_nodes,
+        frames: &self.frames,
+      };
+      hook.after(&ctx, out.as_deref());
+    }
+    out
+  }
+
+  fn eval_node(
+    &mut self,
+    node: &Node,
+    block_nodes: Option<&HashMap<String, Node>>,
+    env: &mut HashMap<String, String>,
   ) -> Result<String, SourceError> {
     match node {
-      Node::Branch { children, .. } => eval_branch::eval_branch(self, children, block_nodes, env),
-      Node::SpreadParam { .. } | Node::SpreadInclude { .. } => Err(err_span_impl(
-        "spread should be expanded (SpreadInclude at load, SpreadParam in branch)",
-        node_span(node),
-      )),
-      Node::CharBlock { ranges, count, span } => eval_leaf::eval_char_block(self, ranges, count, *span),
-      Node::Leaf { lit, span } => eval_leaf::eval_leaf(self, lit, *span, env),
-      Node::BinaryOp { op, left, right, span } => {
-        eval_op::eval_binary_op(self, op.clone(), left, right, *span, env)
+      Node::Branch { children, span } => {
+        eval_branch::eval_branch(self, children, *span, block_nodes, env)
+      }
+      Node::SpreadParam { .. } | Node::SpreadInclude { .. } | Node::SpreadData { .. } => {
+        Err(err_span_impl(
+          "spread should be expanded (SpreadInclude/SpreadData at load, SpreadParam in branch)",
+          node_span(node),
+        ))
       }
+      Node::CharBlock {
+        ranges,
+        count,
+        span,
+      } => eval_leaf::eval_char_block(self, ranges, count, *span),
+      Node::Leaf { lit, span } => eval_leaf::eval_leaf(self, lit, *span, env),
+      Node::BinaryOp {
+        op,
+        left,
+        right,
+        span,
+      } => eval_op::eval_binary_op(self, op.clone(), left, right, *span, env),
       Node::Call {
         name,
         params,
@@ -1,19 +1,29 @@
 use crate::ast::{Node, SourceError};
-use rand::seq::SliceRandom;
 use std::collections::HashMap;
 
 use super::eval::EvalState;
-use super::{expand_branch_spreads, err_span_impl};
+use super::profile::ProfileKind;
+use super::{err_span_impl, expand_branch_spreads, span_label};
 
 pub(super) fn eval_branch<R: rand::RngCore>(
   state: &mut EvalState<'_, '_, R>,
   children: &[Node],
+  span: Option<crate::ast::Span>,
   block_nodes: Option<&HashMap<String, Node>>,
   env: &mut HashMap<String, String>,
 ) -> Result<String, SourceError> {
+  let t = state.prof_start();
   let expanded = expand_branch_spreads(children, block_nodes)?;
-  let child = expanded
-    .choose(state.rng)
+  if t.is_some() {
+    state.prof_end(
+      t,
+      ProfileKind::Spread,
+      &span_label(span),
+      expanded.len() as u64,
+    );
+  }
+  let child = state
+    .choose(span, &expanded)
     .ok_or_else(|| err_span_impl("empty branch", None))?;
   state.eval(child, block_nodes, env)
 }
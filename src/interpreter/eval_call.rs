@@ -1,8 +1,9 @@
 use crate::ast::{CallBlock, SourceError};
 use std::collections::HashMap;
 
-use super::eval::EvalState;
 use super::err_span_impl;
+use super::eval::EvalState;
+use super::profile::ProfileKind;
 
 pub(super) fn eval_call<R: rand::RngCore>(
   state: &mut EvalState<'_, '_, R>,
@@ -13,6 +14,7 @@ pub(super) fn eval_call<R: rand::RngCore>(
   call_span: Option<crate::ast::Span>,
   env: &mut HashMap<String, String>,
 ) -> Result<String, SourceError> {
+  super::push_span(state.trace, call_span);
   let mut call_env = env.clone();
   let block_nodes_for_body = block.map(|b| {
     b.bindings
@@ -42,8 +44,14 @@ pub(super) fn eval_call<R: rand::RngCore>(
         fn_env.insert(var.clone(), s.clone());
       }
     }
-    let result = state.eval(&fd.body, block_nodes_for_body.as_ref(), &mut fn_env)?;
-    return Ok(result);
+    let t = state.prof_start();
+    state.frames.push(&fd.name);
+    let result = state
+      .eval(&fd.body, block_nodes_for_body.as_ref(), &mut fn_env)
+      .map_err(|e| e.in_file(fd.file.as_deref()));
+    state.frames.pop();
+    state.prof_end(t, ProfileKind::Function, name, 0);
+    return result;
   }
   if let Some(blk) = block {
     let param_set: std::collections::HashSet<_> = params.iter().collect();
@@ -76,10 +84,12 @@ pub(super) fn eval_call<R: rand::RngCore>(
       }
     })
     .collect();
-  Ok([name]
-    .iter()
-    .chain(parts.iter())
-    .cloned()
-    .collect::<Vec<_>>()
-    .join(" "))
+  Ok(
+    [name]
+      .iter()
+      .chain(parts.iter())
+      .cloned()
+      .collect::<Vec<_>>()
+      .join(" "),
+  )
 }
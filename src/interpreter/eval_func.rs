@@ -1,8 +1,9 @@
 use crate::ast::{Node, SourceError};
 use std::collections::HashMap;
 
-use super::eval::EvalState;
 use super::err_span_impl;
+use super::eval::EvalState;
+use super::profile::ProfileKind;
 
 pub(super) fn eval_func_call<R: rand::RngCore>(
   state: &mut EvalState<'_, '_, R>,
@@ -11,12 +12,18 @@ pub(super) fn eval_func_call<R: rand::RngCore>(
   fc_span: Option<crate::ast::Span>,
   env: &mut HashMap<String, String>,
 ) -> Result<String, SourceError> {
-  if let Some(builtin) = state.builtins.get(name) {
+  super::push_span(state.trace, fc_span);
+  if let Some(builtin) = state.builtins.builtin(name) {
     let evaled: Vec<String> = args
       .iter()
       .map(|a| state.eval(a, None, env))
       .collect::<Result<Vec<_>, _>>()?;
-    return builtin(&evaled).map_err(|e| err_span_impl(e, fc_span));
+    let t = state.prof_start();
+    // Over the cap: the hook stops the run once it sees this output.
+    let out = crate::builtins::call_with_limit(*builtin, &evaled, state.output_cap)
+      .map_err(|e| err_span_impl(e, fc_span));
+    state.prof_end(t, ProfileKind::Builtin, name, 0);
+    return out;
   }
   let fd = state
     .program
@@ -40,5 +47,12 @@ pub(super) fn eval_func_call<R: rand::RngCore>(
     let s = state.eval(&args[i], None, env)?;
     fn_env.insert(p.clone(), s);
   }
-  state.eval(&fd.body, None, &mut fn_env)
+  let t = state.prof_start();
+  state.frames.push(&fd.name);
+  let out = state
+    .eval(&fd.body, None, &mut fn_env)
+    .map_err(|e| e.in_file(fd.file.as_deref()));
+  state.frames.pop();
+  state.prof_end(t, ProfileKind::Function, name, 0);
+  out
 }
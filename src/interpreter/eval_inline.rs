@@ -1,5 +1,4 @@
 use crate::ast::SourceError;
-use rand::seq::SliceRandom;
 
 use super::eval::EvalState;
 use super::{err_span_impl, node_span};
@@ -11,8 +10,8 @@ pub(super) fn eval_inline_call<R: rand::RngCore>(
   ic_span: Option<crate::ast::Span>,
   env: &mut std::collections::HashMap<String, String>,
 ) -> Result<String, SourceError> {
-  let opt = options
-    .choose(state.rng)
+  let opt = state
+    .choose(ic_span, options)
     .ok_or_else(|| err_span_impl("empty inline options", ic_span))?;
   super::push_span(state.trace, node_span(opt));
   let s = state.eval(opt, None, env)?;
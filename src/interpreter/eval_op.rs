@@ -1,19 +1,23 @@
 use crate::ast::{BinOp, Literal, Node, SourceError};
 
 use super::eval::EvalState;
-use super::{err_span_impl, node_span};
+use super::profile::ProfileKind;
+use super::{err_span_impl, node_span, span_label};
 
 pub(super) fn eval_binary_op<R: rand::RngCore>(
   state: &mut EvalState<'_, '_, R>,
   op: BinOp,
   left: &Node,
   right: &Node,
-  _span: Option<crate::ast::Span>,
+  span: Option<crate::ast::Span>,
   env: &mut std::collections::HashMap<String, String>,
 ) -> Result<String, SourceError> {
   match op {
     BinOp::Plus => {
       let a = state.eval(left, None, env)?;
+      if a.len() > state.output_cap {
+        return Ok(a);
+      }
       let b = state.eval(right, None, env)?;
       Ok(format!("{}{}", a, b))
     }
@@ -43,12 +47,32 @@ pub(super) fn eval_binary_op<R: rand::RngCore>(
             .max(0) as usize
         }
       };
+      let t = state.prof_start();
       let mut out = String::new();
-      for _ in 0..n {
-        let a = state.eval(left, None, env)?;
-        out.push_str(&a);
+      // Recorded in the profile also when an iteration fails.
+      let result = repeat(state, left, n, env, &mut out);
+      if t.is_some() {
+        state.prof_end(t, ProfileKind::Repeat, &span_label(span), n as u64);
       }
-      Ok(out)
+      result.map(|()| out)
+    }
+  }
+}
+
+/// Append `n` evaluations of `node` to `out`.
+fn repeat<R: rand::RngCore>(
+  state: &mut EvalState<'_, '_, R>,
+  node: &Node,
+  n: usize,
+  env: &mut std::collections::HashMap<String, String>,
+  out: &mut String,
+) -> Result<(), SourceError> {
+  for _ in 0..n {
+    out.push_str(&state.eval(node, None, env)?);
+    // Over the cap: the hook stops the run once it sees this output.
+    if out.len() > state.output_cap {
+      break;
     }
   }
+  Ok(())
 }
@@ -5,6 +5,7 @@ pub fn err_span(message: impl Into<String>, span: Option<Span>) -> SourceError {
   SourceError {
     message: message.into(),
     span,
+    file: None,
   }
 }
 
@@ -38,6 +39,14 @@ pub fn push_span(trace: &mut Vec<Span>, span: Option<Span>) {
   }
 }
 
+/// `line:column` of a span for profile entries; `?` when unknown.
+pub fn span_label(span: Option<Span>) -> String {
+  span.map_or_else(
+    || "?".into(),
+    |s| format!("{}:{}", s.start_line, s.start_column),
+  )
+}
+
 pub fn node_span(node: &Node) -> Option<Span> {
   match node {
     Node::Branch { span, .. } => *span,
@@ -48,6 +57,7 @@ pub fn node_span(node: &Node) -> Option<Span> {
     Node::FuncCall { span, .. } => *span,
     Node::SpreadParam { span, .. } => *span,
     Node::SpreadInclude { span, .. } => *span,
+    Node::SpreadData { span, .. } => *span,
     Node::CharBlock { span, .. } => *span,
   }
 }
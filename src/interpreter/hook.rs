@@ -0,0 +1,55 @@
+//! Debugging hooks: observe each node before and after evaluation and steer branch choices.
+
+use crate::ast::{Node, SourceError, Span};
+use std::collections::HashMap;
+
+/// What a hook sees about the node being evaluated.
+pub struct HookContext<'a> {
+  pub node: &'a Node,
+  /// Nesting depth of the node (main branch is 0; function bodies are deeper than their calls).
+  pub depth: usize,
+  /// Evaluated parameters visible to the node.
+  pub env: &'a HashMap<String, String>,
+  /// Unevaluated block bindings of the enclosing template call, if any.
+  pub block: Option<&'a HashMap<String, Node>>,
+  /// Names of the functions/templates being evaluated, outermost first.
+  pub frames: &'a [&'a str],
+}
+
+impl HookContext<'_> {
+  /// Options a branch or inline call at this node picks from, as the interpreter sees them:
+  /// branch children with `...:param` spreads replaced by the block's nodes. `None` for other
+  /// nodes, or when a spread has no binding.
+  pub fn options(&self) -> Option<Vec<Node>> {
+    match self.node {
+      Node::Branch { children, .. } => super::expand_branch_spreads(children, self.block).ok(),
+      Node::InlineCall { options, .. } => Some(options.clone()),
+      _ => None,
+    }
+  }
+}
+
+/// Callbacks invoked by the interpreter when a hook is installed (see `interpret_with_hook`).
+/// All methods have no-op defaults.
+pub trait EvalHook {
+  /// Before a node is evaluated. An error aborts evaluation with that message.
+  fn before(&mut self, _ctx: &HookContext<'_>) -> Result<(), String> {
+    Ok(())
+  }
+
+  /// After a node is evaluated, with its output or error.
+  fn after(&mut self, _ctx: &HookContext<'_>, _result: Result<&str, &SourceError>) {}
+
+  /// Longest output a node should build. Repeats, concatenations and the builtins `concat`,
+  /// `join` and `replace` stop as soon as their output is longer and return it as is, so
+  /// [`after`](Self::after) sees it; `None` for no limit.
+  fn output_limit(&self) -> Option<usize> {
+    None
+  }
+
+  /// When a branch or inline call of `count` options has randomly picked `chosen`
+  /// (`depth` is the depth of that branch or inline call). Returns the index to use.
+  fn choose(&mut self, _span: Option<Span>, _depth: usize, chosen: usize, _count: usize) -> usize {
+    chosen
+  }
+}
@@ -3,6 +3,7 @@
 use crate::ast::{EventMatcher, Node, Program, SourceError, Span};
 use crate::builtins::BuiltinFn;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
 
 mod eval;
 mod eval_branch;
@@ -12,9 +13,13 @@ mod eval_inline;
 mod eval_leaf;
 mod eval_op;
 mod helpers;
+mod hook;
+mod profile;
 
 use eval::EvalState;
 use helpers::err_span;
+pub use hook::{EvalHook, HookContext};
+pub use profile::{Profile, ProfileEntry, ProfileKind};
 
 /// Result of interpretation: output string and optional trace of source spans that were used.
 pub fn interpret(
@@ -23,14 +28,85 @@ pub fn interpret(
   rng: &mut impl rand::RngCore,
   input: Option<&str>,
 ) -> Result<(String, Vec<Span>), SourceError> {
-  let mut env = HashMap::new();
+  interpret_with(program, builtins, rng, input, &HashMap::new(), None, None)
+}
+
+/// Like [`interpret`], additionally recording call counts and timings into `profile`.
+pub fn interpret_profiled<S: BuildHasher>(
+  program: &Program,
+  builtins: &HashMap<String, BuiltinFn, S>,
+  rng: &mut impl rand::RngCore,
+  input: Option<&str>,
+  profile: &mut Profile,
+) -> Result<(String, Vec<Span>), SourceError> {
+  let start = std::time::Instant::now();
+  let out = interpret_with(
+    program,
+    builtins,
+    rng,
+    input,
+    &HashMap::new(),
+    Some(&mut *profile),
+    None,
+  );
+  profile.finish(start.elapsed());
+  out
+}
+
+/// Like [`interpret`], calling `hook` around every node (used by `branchy debug`).
+pub fn interpret_with_hook<S: BuildHasher>(
+  program: &Program,
+  builtins: &HashMap<String, BuiltinFn, S>,
+  rng: &mut impl rand::RngCore,
+  input: Option<&str>,
+  hook: &mut dyn EvalHook,
+) -> Result<(String, Vec<Span>), SourceError> {
+  interpret_with(
+    program,
+    builtins,
+    rng,
+    input,
+    &HashMap::new(),
+    None,
+    Some(hook),
+  )
+}
+
+/// Like [`interpret`], with `vars` as the initial params of the main branch and events
+/// (`:name` reads `vars["name"]`).
+pub fn interpret_with_vars(
+  program: &Program,
+  builtins: &HashMap<String, BuiltinFn>,
+  rng: &mut impl rand::RngCore,
+  input: Option<&str>,
+  vars: &HashMap<String, String>,
+) -> Result<(String, Vec<Span>), SourceError> {
+  interpret_with(program, builtins, rng, input, vars, None, None)
+}
+
+/// Shared implementation of the `interpret*` functions; `profile` gets call counts and
+/// timings (the caller finishes it), `hook` is called around every node.
+pub(crate) fn interpret_with<S: BuildHasher>(
+  program: &Program,
+  builtins: &HashMap<String, BuiltinFn, S>,
+  rng: &mut impl rand::RngCore,
+  input: Option<&str>,
+  vars: &HashMap<String, String>,
+  profile: Option<&mut Profile>,
+  hook: Option<&mut dyn EvalHook>,
+) -> Result<(String, Vec<Span>), SourceError> {
+  let mut env = vars.clone();
   let mut trace = Vec::new();
-  let mut state = EvalState::new(program, builtins, rng, &mut trace);
+  let hook = hook.map(|h| h as &mut dyn EvalHook);
+  let mut state = EvalState::new(program, builtins, rng, &mut trace, profile, hook);
   let out = if let Some(s) = input {
     if !program.events.is_empty() {
       for event in &program.events {
         if event_matches(&event.matcher, s) {
-          let out = state.eval(&event.body, None, &mut env)?;
+          let t = state.prof_start();
+          let out = state.eval(&event.body, None, &mut env);
+          state.prof_end(t, ProfileKind::Event, &event.matcher.label(), 0);
+          let out = out.map_err(|e| e.in_file(event.file.as_deref()))?;
           return Ok((out, trace));
         }
       }
@@ -72,4 +148,4 @@ fn event_matches(matcher: &EventMatcher, input: &str) -> bool {
   }
 }
 
-pub(super) use helpers::{expand_branch_spreads, err_span_impl, node_span, push_span};
+pub(super) use helpers::{err_span_impl, expand_branch_spreads, node_span, push_span, span_label};
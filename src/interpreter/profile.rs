@@ -0,0 +1,119 @@
+//! Opt-in evaluation profiler: call counts and wall time per function, builtin, event,
+//! branch spread expansion and repeat loop.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileKind {
+  /// User function or template body (`!f(...)` or `name :p { ... }`).
+  Function,
+  Builtin,
+  Event,
+  /// `expand_branch_spreads` for a branch; `items` counts cloned child nodes.
+  Spread,
+  /// `expr * n` loop; `items` counts iterations.
+  Repeat,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProfileEntry {
+  pub kind: ProfileKind,
+  /// Function/builtin name, event matcher, or `line:column` for spreads and repeats.
+  pub name: String,
+  pub calls: u64,
+  /// Inclusive wall time in nanoseconds (nested calls are counted in their callers too).
+  pub total_ns: u64,
+  pub items: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Profile {
+  /// Wall time of the whole interpretation in nanoseconds.
+  pub total_ns: u64,
+  /// Entries sorted by `total_ns`, highest first (see [`Profile::finish`]).
+  pub entries: Vec<ProfileEntry>,
+  #[serde(skip)]
+  index: HashMap<(ProfileKind, String), usize>,
+}
+
+impl Profile {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub(super) fn record(&mut self, kind: ProfileKind, name: &str, elapsed: Duration, items: u64) {
+    let i = if let Some(&i) = self.index.get(&(kind, name.to_string())) {
+      i
+    } else {
+      self.entries.push(ProfileEntry {
+        kind,
+        name: name.to_string(),
+        calls: 0,
+        total_ns: 0,
+        items: 0,
+      });
+      self
+        .index
+        .insert((kind, name.to_string()), self.entries.len() - 1);
+      self.entries.len() - 1
+    };
+    let e = &mut self.entries[i];
+    e.calls += 1;
+    e.total_ns += u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+    e.items += items;
+  }
+
+  /// Sort entries by total time (highest first). Called after the run with its duration.
+  pub(crate) fn finish(&mut self, elapsed: Duration) {
+    self.total_ns += u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+    self.entries.sort_by(|a, b| {
+      b.total_ns
+        .cmp(&a.total_ns)
+        .then_with(|| a.name.cmp(&b.name))
+    });
+    self.index = self
+      .entries
+      .iter()
+      .enumerate()
+      .map(|(i, e)| ((e.kind, e.name.clone()), i))
+      .collect();
+  }
+
+  /// Human-readable table, one line per entry.
+  pub fn report(&self) -> String {
+    let mut out = format!("total: {:.3} ms\n", ms(self.total_ns));
+    let _ = writeln!(
+      out,
+      "{:<9} {:<24} {:>9} {:>11} {:>10} {:>9}",
+      "kind", "name", "calls", "total ms", "avg us", "items"
+    );
+    for e in &self.entries {
+      let avg_us = e
+        .total_ns
+        .checked_div(e.calls)
+        .map_or(0.0, |ns| ms(ns) * 1000.0);
+      let kind = format!("{:?}", e.kind).to_lowercase();
+      let _ = writeln!(
+        out,
+        "{:<9} {:<24} {:>9} {:>11.3} {:>10.2} {:>9}",
+        kind,
+        e.name,
+        e.calls,
+        ms(e.total_ns),
+        avg_us,
+        e.items
+      );
+    }
+    out
+  }
+}
+
+#[allow(clippy::cast_precision_loss)] // display only
+fn ms(ns: u64) -> f64 {
+  ns as f64 / 1_000_000.0
+}
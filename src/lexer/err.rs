@@ -9,5 +9,6 @@ pub(super) fn err_at(
   SourceError {
     message: message.into(),
     span: Some(span_from_offsets(input, start, end)),
+    file: None,
   }
 }
@@ -124,7 +124,10 @@ fn read_quoted(it: &mut Peekable<Chars>, end: char) -> Result<String, String> {
           'r' => s.push('\r'),
           '\\' => s.push('\\'),
           _ if n == end => s.push(n),
-          _ => { s.push(c); s.push(n); }
+          _ => {
+            s.push(c);
+            s.push(n);
+          }
         }
       }
     } else if c == end {
@@ -159,7 +162,8 @@ fn read_ident(it: &mut Peekable<Chars>) -> Result<String, String> {
 fn read_ident_from(first: char, it: &mut Peekable<Chars>) -> String {
   let mut s = String::from(first);
   while let Some(&c) = it.peek() {
-    if is_ident_cont(c) {
+    let qualified = c == '.' && it.clone().nth(1).is_some_and(is_ident_start);
+    if is_ident_cont(c) || qualified {
       s.push(it.next().unwrap());
     } else {
       break;
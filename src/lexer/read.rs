@@ -2,7 +2,7 @@ use crate::ast::SourceError;
 
 use super::cursor::OffsetCursor;
 use super::err::err_at;
-use super::ident::is_ident_cont;
+use super::ident::{is_ident_cont, is_ident_start};
 
 pub(super) fn read_quoted_offset(
   cur: &mut OffsetCursor<'_>,
@@ -23,7 +23,10 @@ pub(super) fn read_quoted_offset(
           'r' => s.push('\r'),
           '\\' => s.push('\\'),
           _ if n == end => s.push(n),
-          _ => { s.push(c); s.push(n); }
+          _ => {
+            s.push(c);
+            s.push(n);
+          }
         }
       }
     } else if c == end {
@@ -62,7 +65,13 @@ pub(super) fn read_ident_from_offset(
   let mut s = String::from(first);
   let mut last_end = start + first.len_utf8();
   while let Some((off, c)) = cur.peek() {
-    if is_ident_cont(c) {
+    // `ns.name`: a dot directly followed by an identifier continues a qualified name.
+    let qualified = c == '.'
+      && cur.s[cur.pos + 1..]
+        .chars()
+        .next()
+        .is_some_and(is_ident_start);
+    if is_ident_cont(c) || qualified {
       cur.next();
       s.push(c);
       last_end = off + c.len_utf8();
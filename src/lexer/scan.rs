@@ -8,11 +8,7 @@ use super::scan_rest;
 use super::token::Token;
 
 pub fn tokenize_with_offsets(input: &str) -> Result<Vec<(Token, usize, usize)>, SourceError> {
-  fn rec(
-    input: &str,
-    start: usize,
-    end: usize,
-  ) -> Result<Vec<(Token, usize, usize)>, SourceError> {
+  fn rec(input: &str, start: usize, end: usize) -> Result<Vec<(Token, usize, usize)>, SourceError> {
     tokenize_range_rec(input, start, end, &rec)
   }
   rec(input, 0, input.len())
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
-  use super::super::{tokenize, tokenize_with_offsets};
   use super::super::Token;
+  use super::super::{tokenize, tokenize_with_offsets};
 
   #[test]
   fn tokenize_spread_param_top_level() {
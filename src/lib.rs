@@ -1,18 +1,30 @@
+pub mod analysis;
 pub mod ast;
 pub mod binformat;
 pub mod builtins;
+pub mod coverage;
+pub mod debugger;
+pub mod editor;
 pub mod formatter;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
+pub mod repl;
 pub mod resolve;
 pub mod server;
+pub mod stdlib;
+pub mod watch;
 
 pub use ast::{Literal, Node, Program, SourceError, Span};
 pub use binformat::{deserialize_program, serialize_program};
 pub use builtins::default_registry;
 pub use formatter::{format_program, FormatOptions};
-pub use interpreter::interpret;
+pub use interpreter::{
+  interpret, interpret_profiled, interpret_with_hook, interpret_with_vars, EvalHook, Profile,
+};
 pub use lexer::tokenize;
-pub use parser::parse_program;
-pub use resolve::resolve_includes;
+pub use parser::{parse_fragment, parse_program};
+pub use resolve::{
+  resolve_includes, resolve_includes_detailed, resolve_includes_with, ArchiveResolver, FsResolver,
+  MemoryResolver, ResolveError, SearchPath, SourceResolver,
+};
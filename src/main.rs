@@ -1,29 +1,46 @@
-//! CLI entry: run | compile.
+//! CLI entry: run | compile | fmt | check | repl | coverage | debug.
 
+use branchy::debugger::Breakpoint;
 use std::env;
+use std::path::PathBuf;
 
 mod run;
 
+/// Printed when no command is given.
+const USAGE: &str = "\
+Usage: branchy run <file.branchy|file.branchyc> [input] [--seed N] [--profile] [--watch]
+       branchy compile <file.branchy> -o <file.branchyc>
+       branchy fmt [path] [-w|--write] [-c|--check] [--watch]
+       branchy check <file> [--watch]
+       branchy repl
+       branchy coverage <file> [-n RUNS] [--seed N] [-i INPUT]... [--json|--lcov|--annotate]
+       branchy debug <file> [input] [--seed N] [-b LINE|NAME]...
+Include search paths: -I DIR (option of any command, repeatable), BRANCHY_PATH, branchy.json
+";
+
 fn main() -> Result<(), String> {
   let args: Vec<String> = env::args().collect();
   if args.len() < 2 {
-    eprintln!("Usage: branchy run <file.branchy|file.branchyc> [input] [--seed N]");
-    eprintln!("       branchy compile <file.branchy> -o <file.branchyc>");
-    eprintln!("       branchy fmt [path] [-w|--write] [-c|--check]");
+    eprint!("{USAGE}");
     std::process::exit(1);
   }
   let sub = &args[1];
   match sub.as_str() {
     "run" => {
       if args.len() < 3 {
-        return Err("branchy run <file> [input] [--seed N]".into());
+        return Err("branchy run <file> [input] [--seed N] [--profile] [--watch]".into());
+      }
+      let (input, seed, profile, watch, dirs) = parse_run_args(&args[3..])?;
+      if watch {
+        run::run_watch(&args[2], input, seed, profile, &dirs)
+      } else {
+        run::run(&args[2], input, seed, profile, &dirs)
       }
-      let (input, seed) = parse_run_args(&args[3..])?;
-      run::run(&args[2], input, seed)
     }
     "compile" => {
       let mut input = None;
       let mut output = None;
+      let mut dirs = Vec::new();
       let mut i = 2;
       while i < args.len() {
         if args[i] == "-o" {
@@ -32,6 +49,9 @@ fn main() -> Result<(), String> {
             output = Some(args[i].clone());
             i += 1;
           }
+        } else if args[i].starts_with("-I") {
+          dirs.push(include_dir(&args, &mut i)?);
+          i += 1;
         } else {
           input = Some(args[i].clone());
           i += 1;
@@ -39,50 +59,228 @@ fn main() -> Result<(), String> {
       }
       let inp = input.ok_or("branchy compile <input.branchy> -o <output.branchyc>")?;
       let out = output.ok_or("branchy compile <input.branchy> -o <output.branchyc>")?;
-      run::compile(&inp, &out)
+      run::compile(&inp, &out, &dirs)
+    }
+    "fmt" | "format" => fmt(&args[2..]),
+    "check" => {
+      let (path, watch, dirs) = parse_check_args(&args[2..])?;
+      if watch {
+        run::check_watch(path, &dirs)
+      } else {
+        run::check(path, &dirs)
+      }
     }
-    "fmt" | "format" => {
-      let mut path = None;
-      let mut write = false;
-      let mut check = false;
+    "repl" => {
+      let mut dirs = Vec::new();
       let mut i = 2;
       while i < args.len() {
-        if args[i] == "-w" || args[i] == "--write" {
-          write = true;
-          i += 1;
-        } else if args[i] == "-c" || args[i] == "--check" {
-          check = true;
-          i += 1;
-        } else if path.is_none() {
-          path = Some(args[i].as_str());
-          i += 1;
-        } else {
-          return Err("branchy fmt: unexpected argument".into());
+        if !args[i].starts_with("-I") {
+          return Err(format!("unexpected argument: {}", args[i]));
         }
+        dirs.push(include_dir(&args, &mut i)?);
+        i += 1;
       }
-      run::fmt(path, write, check)
+      run::repl(&dirs)
+    }
+    "coverage" => {
+      if args.len() < 3 {
+        return Err("branchy coverage <file> [-n RUNS] [--seed N] [-i INPUT]...".into());
+      }
+      let (runs, seed, inputs, format, dirs) = parse_coverage_args(&args[3..])?;
+      run::coverage(&args[2], runs, seed, &inputs, &format, &dirs)
+    }
+    "debug" => {
+      if args.len() < 3 {
+        return Err("branchy debug <file> [input] [--seed N] [-b LINE|NAME]...".into());
+      }
+      let (input, seed, breakpoints, dirs) = parse_debug_args(&args[3..])?;
+      run::debug(&args[2], input, seed, breakpoints, &dirs)
+    }
+    _ => run::run(sub, None, None, false, &[]),
+  }
+}
+
+/// `fmt [path] [-w|--write] [-c|--check] [--watch] [-I DIR]...`
+fn fmt(args: &[String]) -> Result<(), String> {
+  let mut path = None;
+  let mut write = false;
+  let mut check = false;
+  let mut watch = false;
+  let mut dirs = Vec::new();
+  let mut i = 0;
+  while i < args.len() {
+    if args[i] == "-w" || args[i] == "--write" {
+      write = true;
+      i += 1;
+    } else if args[i] == "-c" || args[i] == "--check" {
+      check = true;
+      i += 1;
+    } else if args[i] == "--watch" {
+      watch = true;
+      i += 1;
+    } else if args[i].starts_with("-I") {
+      dirs.push(include_dir(args, &mut i)?);
+      i += 1;
+    } else if path.is_none() {
+      path = Some(args[i].as_str());
+      i += 1;
+    } else {
+      return Err("branchy fmt: unexpected argument".into());
+    }
+  }
+  if watch {
+    if write {
+      return Err("branchy fmt: --watch cannot be combined with --write".into());
+    }
+    run::fmt_watch(
+      path.ok_or("branchy fmt --watch requires a file path")?,
+      check,
+      &dirs,
+    )
+  } else {
+    run::fmt(path, write, check)
+  }
+}
+
+/// Include directory of the `-I DIR` or `-IDIR` option at `args[*i]`; leaves `i` on the last
+/// argument it used.
+fn include_dir(args: &[String], i: &mut usize) -> Result<PathBuf, String> {
+  match &args[*i][2..] {
+    "" => {
+      *i += 1;
+      Ok(PathBuf::from(
+        args.get(*i).ok_or("-I requires a directory")?,
+      ))
     }
-    _ => run::run(sub, None, None),
+    dir => Ok(PathBuf::from(dir)),
   }
 }
 
-fn parse_run_args(args: &[String]) -> Result<(Option<&str>, Option<u64>), String> {
+type RunArgs<'a> = (Option<&'a str>, Option<u64>, bool, bool, Vec<PathBuf>);
+
+/// Options after `run <file>`; `--` makes the next argument the input even if it starts with `-`.
+fn parse_run_args(args: &[String]) -> Result<RunArgs<'_>, String> {
   let mut input = None;
   let mut seed = None;
+  let mut profile = false;
+  let mut watch = false;
+  let mut dirs = Vec::new();
   let mut i = 0;
   while i < args.len() {
-    if args[i] == "--seed" || args[i] == "-s" {
+    if args[i] == "--profile" {
+      profile = true;
+      i += 1;
+    } else if args[i] == "--watch" {
+      watch = true;
+      i += 1;
+    } else if args[i] == "--seed" || args[i] == "-s" {
       i += 1;
       let s = args.get(i).ok_or("--seed requires a number")?;
       let n: u64 = s.parse().map_err(|_| format!("invalid seed: {}", s))?;
       seed = Some(n);
       i += 1;
+    } else if args[i].starts_with("-I") {
+      dirs.push(include_dir(args, &mut i)?);
+      i += 1;
     } else if input.is_none() {
-      input = Some(args[i].as_str());
+      if args[i] == "--" {
+        i += 1;
+      }
+      input = Some(args.get(i).ok_or("-- requires an input")?.as_str());
       i += 1;
     } else {
       return Err("unexpected argument".into());
     }
   }
-  Ok((input, seed))
+  Ok((input, seed, profile, watch, dirs))
+}
+
+/// `check <file> [--watch] [-I DIR]...`: the file, whether to watch, and include directories.
+fn parse_check_args(args: &[String]) -> Result<(&str, bool, Vec<PathBuf>), String> {
+  let mut path = None;
+  let mut watch = false;
+  let mut dirs = Vec::new();
+  let mut i = 0;
+  while i < args.len() {
+    match args[i].as_str() {
+      "--watch" => watch = true,
+      a if a.starts_with("-I") => dirs.push(include_dir(args, &mut i)?),
+      a if path.is_none() => path = Some(a),
+      _ => return Err("branchy check <file> [--watch]".into()),
+    }
+    i += 1;
+  }
+  let path = path.ok_or("branchy check <file> [--watch]")?;
+  Ok((path, watch, dirs))
+}
+
+type DebugArgs<'a> = (Option<&'a str>, Option<u64>, Vec<Breakpoint>, Vec<PathBuf>);
+
+/// Options after `debug <file>`; `--` makes the next argument the input like for `run`.
+fn parse_debug_args(args: &[String]) -> Result<DebugArgs<'_>, String> {
+  let mut input = None;
+  let mut seed = None;
+  let mut breakpoints = Vec::new();
+  let mut dirs = Vec::new();
+  let mut i = 0;
+  while i < args.len() {
+    match args[i].as_str() {
+      "--seed" | "-s" => {
+        i += 1;
+        let s = args.get(i).ok_or("--seed requires a number")?;
+        seed = Some(s.parse().map_err(|_| format!("invalid seed: {s}"))?);
+      }
+      "-b" | "--break" => {
+        i += 1;
+        breakpoints.push(Breakpoint::parse(
+          args.get(i).ok_or("-b requires a line or name")?,
+        ));
+      }
+      a if a.starts_with("-I") => dirs.push(include_dir(args, &mut i)?),
+      "--" if input.is_none() => {
+        i += 1;
+        input = Some(args.get(i).ok_or("-- requires an input")?.as_str());
+      }
+      other if input.is_none() => input = Some(other),
+      other => return Err(format!("unexpected argument: {other}")),
+    }
+    i += 1;
+  }
+  Ok((input, seed, breakpoints, dirs))
+}
+
+type CoverageArgs<'a> = (u64, u64, Vec<&'a str>, run::CoverageFormat, Vec<PathBuf>);
+
+fn parse_coverage_args(args: &[String]) -> Result<CoverageArgs<'_>, String> {
+  let mut runs = 1000;
+  let mut seed = 0;
+  let mut inputs = Vec::new();
+  let mut format = run::CoverageFormat::Text;
+  let mut dirs = Vec::new();
+  let mut i = 0;
+  while i < args.len() {
+    match args[i].as_str() {
+      "-n" | "--runs" => {
+        i += 1;
+        let s = args.get(i).ok_or("-n requires a number")?;
+        runs = s.parse().map_err(|_| format!("invalid run count: {s}"))?;
+      }
+      "--seed" | "-s" => {
+        i += 1;
+        let s = args.get(i).ok_or("--seed requires a number")?;
+        seed = s.parse().map_err(|_| format!("invalid seed: {s}"))?;
+      }
+      "-i" | "--input" => {
+        i += 1;
+        inputs.push(args.get(i).ok_or("--input requires a value")?.as_str());
+      }
+      "--json" => format = run::CoverageFormat::Json,
+      "--lcov" => format = run::CoverageFormat::Lcov,
+      "--annotate" => format = run::CoverageFormat::Annotate,
+      a if a.starts_with("-I") => dirs.push(include_dir(args, &mut i)?),
+      other => return Err(format!("unexpected argument: {other}")),
+    }
+    i += 1;
+  }
+  Ok((runs, seed, inputs, format, dirs))
 }
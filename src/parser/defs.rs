@@ -40,7 +40,11 @@ pub(crate) fn parse_event_def(it: &mut TokenIter) -> Result<Option<Event>, Sourc
     _ => return Ok(None),
   };
   stream::skip_semicolon(it);
-  Ok(Some(Event { matcher, body }))
+  Ok(Some(Event {
+    matcher,
+    body,
+    file: None,
+  }))
 }
 
 pub(crate) fn parse_function_def(it: &mut TokenIter) -> Result<Option<FunctionDef>, SourceError> {
@@ -49,7 +53,7 @@ pub(crate) fn parse_function_def(it: &mut TokenIter) -> Result<Option<FunctionDe
     _ => return Ok(None),
   }
   it.next();
-  let name = stream::expect_ident(it)?;
+  let name = super::program::plain_name(it)?;
   stream::expect(it, Token::LParen)?;
   let mut params = Vec::new();
   loop {
@@ -68,5 +72,10 @@ pub(crate) fn parse_function_def(it: &mut TokenIter) -> Result<Option<FunctionDe
   stream::expect(it, Token::Equals)?;
   let body = expr::parse_value(it)?;
   stream::skip_semicolon(it);
-  Ok(Some(FunctionDef { name, params, body }))
+  Ok(Some(FunctionDef {
+    name,
+    params,
+    body,
+    file: None,
+  }))
 }
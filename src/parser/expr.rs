@@ -1,6 +1,6 @@
 //! Expression, element and branch parsing.
 
-use crate::ast::{BinOp, Literal, Node, SourceError};
+use crate::ast::{BinOp, DataFormat, Literal, Node, SourceError};
 use crate::lexer::Token;
 
 use super::char_block;
@@ -10,7 +10,7 @@ use super::stream::{self, TokenIter};
 pub(crate) use super::expr_call::parse_value;
 
 pub(crate) fn parse_branch(it: &mut TokenIter) -> Result<Node, SourceError> {
-  it.start_span();
+  let start = it.peek_span();
   stream::expect(it, Token::LBrack)?;
   let mut elements = Vec::new();
   loop {
@@ -18,7 +18,7 @@ pub(crate) fn parse_branch(it: &mut TokenIter) -> Result<Node, SourceError> {
       it.next();
       return Ok(Node::Branch {
         children: elements,
-        span: it.get_span(),
+        span: stream::merge_span(start, it.current_span()),
       });
     }
     elements.push(parse_expression(it)?);
@@ -73,7 +73,13 @@ fn parse_element(it: &mut TokenIter) -> Result<Node, SourceError> {
             span: it.get_span(),
           })
         }
-        _ => Err(stream::err_span(it, "expected :param or include after ...")),
+        Some(Token::Ident(kind)) if matches!(kind.as_str(), "lines" | "csv" | "json") => {
+          parse_data_spread(it)
+        }
+        _ => Err(stream::err_span(
+          it,
+          "expected :param, include, lines, csv or json after ...",
+        )),
       }
     }
     Some(Token::Bang) => parse_func_call(it),
@@ -128,12 +134,12 @@ fn parse_element(it: &mut TokenIter) -> Result<Node, SourceError> {
     Some(Token::CharBlock(s)) => {
       let content = s.clone();
       it.next();
-      let (ranges, count) = char_block::parse_char_block_content(&content).map_err(|msg| {
-        SourceError {
+      let (ranges, count) =
+        char_block::parse_char_block_content(&content).map_err(|msg| SourceError {
           message: msg,
           span: it.get_span(),
-        }
-      })?;
+          file: None,
+        })?;
       Ok(Node::CharBlock {
         ranges,
         count,
@@ -143,3 +149,50 @@ fn parse_element(it: &mut TokenIter) -> Result<Node, SourceError> {
     _ => Err(stream::err_span(it, "expected element")),
   }
 }
+
+/// After `...`: `lines "f"`, `csv "f" column name` or `json "f" [path]`
+/// (column and path are identifiers or strings).
+fn parse_data_spread(it: &mut TokenIter) -> Result<Node, SourceError> {
+  let Some(Token::Ident(kind)) = it.next() else {
+    return Err(stream::err_span(
+      it,
+      "expected lines, csv or json after ...",
+    ));
+  };
+  let Some(Token::Str(path)) = it.next() else {
+    return Err(stream::err_span(
+      it,
+      format!("expected string after ...{kind}"),
+    ));
+  };
+  let format = match kind.as_str() {
+    "lines" => DataFormat::Lines,
+    "csv" => {
+      if !matches!(it.next(), Some(Token::Ident(c)) if c == "column") {
+        return Err(stream::err_span(
+          it,
+          "expected column after ...csv \"file\"",
+        ));
+      }
+      match it.next() {
+        Some(Token::Ident(column) | Token::Str(column)) => DataFormat::Csv { column },
+        _ => return Err(stream::err_span(it, "expected column name")),
+      }
+    }
+    _ => match it.peek() {
+      Some(Token::Ident(p) | Token::Str(p)) => {
+        let path = p.clone();
+        it.next();
+        DataFormat::Json { path }
+      }
+      _ => DataFormat::Json {
+        path: String::new(),
+      },
+    },
+  };
+  Ok(Node::SpreadData {
+    path,
+    format,
+    span: it.get_span(),
+  })
+}
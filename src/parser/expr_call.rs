@@ -7,7 +7,7 @@ use super::expr::{parse_branch, parse_expression};
 use super::stream::{self, TokenIter};
 
 pub(crate) fn parse_func_call(it: &mut TokenIter) -> Result<Node, SourceError> {
-  it.start_span();
+  let start = it.peek_span();
   it.next();
   let name = stream::expect_ident(it)?;
   stream::expect(it, Token::LParen)?;
@@ -26,11 +26,12 @@ pub(crate) fn parse_func_call(it: &mut TokenIter) -> Result<Node, SourceError> {
   Ok(Node::FuncCall {
     name,
     args,
-    span: it.get_span(),
+    span: stream::merge_span(start, it.current_span()),
   })
 }
 
 pub(crate) fn parse_ident_start(it: &mut TokenIter) -> Result<Node, SourceError> {
+  let start = it.peek_span();
   it.start_span();
   let name = stream::expect_ident(it)?;
   match it.peek() {
@@ -48,7 +49,7 @@ pub(crate) fn parse_ident_start(it: &mut TokenIter) -> Result<Node, SourceError>
       Ok(Node::InlineCall {
         name,
         options,
-        span: it.get_span(),
+        span: stream::merge_span(start, it.current_span()),
       })
     }
     Some(Token::Param(_)) | Some(Token::OptionalParam(_)) | Some(Token::LBrace) => {
@@ -77,7 +78,7 @@ pub(crate) fn parse_ident_start(it: &mut TokenIter) -> Result<Node, SourceError>
         params,
         optional_params,
         block,
-        span: it.get_span(),
+        span: stream::merge_span(start, it.current_span()),
       })
     }
     _ => Ok(Node::Leaf {
@@ -10,4 +10,4 @@ mod stream;
 #[cfg(test)]
 mod tests;
 
-pub use program::parse_program;
+pub use program::{parse_fragment, parse_program, Fragment};
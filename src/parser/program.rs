@@ -1,31 +1,121 @@
 //! Top-level program parsing.
 
-use crate::ast::{Node, Program, SourceError};
+use crate::ast::{Event, FunctionDef, Import, Include, Node, Program, SourceError};
 use crate::lexer::{tokenize_with_offsets, Token};
 
 use super::defs::{parse_event_def, parse_function_def};
 use super::expr;
 use super::stream;
 
+/// Top-level items of a source fragment (e.g. one REPL line): like a program,
+/// but the main branch is optional.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+  pub includes: Vec<Include>,
+  pub functions: Vec<FunctionDef>,
+  pub events: Vec<Event>,
+  pub main: Option<Node>,
+}
+
 pub fn parse_program(input: &str) -> Result<Program, SourceError> {
   let tokens = tokenize_with_offsets(input)?;
   let mut it = stream::SpanStream::new(tokens, input);
-  let mut includes = Vec::new();
-  while matches!(it.peek(), Some(Token::Include)) {
+  let f = parse_items(&mut it)?;
+  let main = f
+    .main
+    .ok_or_else(|| stream::err_span(&it, "expected at least one main branch"))?;
+  if it.next().is_some() {
+    return Err(stream::err_span(&it, "unexpected tokens after main branch"));
+  }
+  Ok(Program {
+    includes: f.includes,
+    functions: f.functions,
+    events: f.events,
+    main,
+  })
+}
+
+/// Parse includes, definitions and optional main branches.
+pub fn parse_fragment(input: &str) -> Result<Fragment, SourceError> {
+  let tokens = tokenize_with_offsets(input)?;
+  let mut it = stream::SpanStream::new(tokens, input);
+  let f = parse_items(&mut it)?;
+  if it.next().is_some() {
+    return Err(stream::err_span(&it, "unexpected tokens"));
+  }
+  Ok(f)
+}
+
+/// `include "path" [as ns]` or `from "path" import name, ...` (`as`, `from` and `import`
+/// are contextual keywords).
+fn parse_include(it: &mut stream::TokenIter) -> Result<Option<Include>, SourceError> {
+  let from = match it.peek() {
+    Some(Token::Include) => false,
+    Some(Token::Ident(s)) if s == "from" => true,
+    _ => return Ok(None),
+  };
+  it.next();
+  let Some(Token::Str(path)) = it.next() else {
+    let what = if from { "from" } else { "include" };
+    return Err(stream::err_span(
+      it,
+      format!("expected string path after {what}"),
+    ));
+  };
+  if from {
+    match it.next() {
+      Some(Token::Ident(s)) if s == "import" => {}
+      _ => return Err(stream::err_span(it, "expected import after from \"path\"")),
+    }
+    let mut names = Vec::new();
+    loop {
+      if matches!(it.peek(), Some(Token::Bang)) {
+        it.next();
+      }
+      names.push(plain_name(it)?);
+      if !matches!(it.peek(), Some(Token::Comma)) {
+        break;
+      }
+      it.next();
+    }
+    return Ok(Some(Include {
+      path,
+      import: Import::Names(names),
+    }));
+  }
+  let import = if matches!(it.peek(), Some(Token::Ident(s)) if s == "as") {
     it.next();
-    let path = match it.next() {
-      Some(Token::Str(s)) => s,
-      _ => return Err(stream::err_span(&it, "expected string path after include")),
-    };
-    includes.push(path);
-    stream::skip_semicolon(&mut it);
+    Import::Namespace(plain_name(it)?)
+  } else {
+    Import::All
+  };
+  Ok(Some(Include { path, import }))
+}
+
+/// Identifier without a namespace qualifier.
+pub(super) fn plain_name(it: &mut stream::TokenIter) -> Result<String, SourceError> {
+  let name = stream::expect_ident(it)?;
+  if name.contains('.') {
+    return Err(stream::err_span(
+      it,
+      format!("expected a plain name, got {name}"),
+    ));
+  }
+  Ok(name)
+}
+
+fn parse_items(it: &mut stream::TokenIter) -> Result<Fragment, SourceError> {
+  let mut includes = Vec::new();
+  while let Some(include) = parse_include(it)? {
+    includes.push(include);
+    stream::skip_semicolon(it);
   }
   let mut functions = Vec::new();
   let mut events = Vec::new();
   loop {
-    if let Some(f) = parse_function_def(&mut it)? {
+    if let Some(f) = parse_function_def(it)? {
       functions.push(f);
-    } else if let Some(e) = parse_event_def(&mut it)? {
+    } else if let Some(e) = parse_event_def(it)? {
       events.push(e);
     } else {
       break;
@@ -33,21 +123,18 @@ pub fn parse_program(input: &str) -> Result<Program, SourceError> {
   }
   let mut main_branches = Vec::new();
   while matches!(it.peek(), Some(Token::LBrack)) {
-    main_branches.push(expr::parse_branch(&mut it)?);
-    stream::skip_semicolon(&mut it);
+    main_branches.push(expr::parse_branch(it)?);
+    stream::skip_semicolon(it);
   }
   let main = match main_branches.len() {
-    0 => return Err(stream::err_span(&it, "expected at least one main branch")),
-    1 => main_branches.into_iter().next().unwrap(),
-    _ => Node::Branch {
+    0 => None,
+    1 => main_branches.into_iter().next(),
+    _ => Some(Node::Branch {
       children: main_branches,
       span: None,
-    },
+    }),
   };
-  if it.next().is_some() {
-    return Err(stream::err_span(&it, "unexpected tokens after main branch"));
-  }
-  Ok(Program {
+  Ok(Fragment {
     includes,
     functions,
     events,
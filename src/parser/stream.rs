@@ -25,6 +25,7 @@ pub(crate) fn offset_to_span(line_index: &[usize], start: usize, end: usize) ->
     start_column: (start - line_index[start_line] + 1) as u32,
     end_line: (end_line + 1) as u32,
     end_column: (end - line_index[end_line] + 1) as u32,
+    file: 0,
   }
 }
 
@@ -58,6 +59,7 @@ impl SpanStream {
         start_column: s.start_column,
         end_line: e.end_line,
         end_column: e.end_column,
+        file: s.file,
       }),
       _ => None,
     }
@@ -90,6 +92,7 @@ pub(crate) fn err_span(it: &TokenIter, message: impl Into<String>) -> SourceErro
   SourceError {
     message: message.into(),
     span: it.current_span().or_else(|| it.peek_span()),
+    file: None,
   }
 }
 
@@ -126,6 +129,7 @@ pub(crate) fn node_span(n: &Node) -> Option<Span> {
     | Node::FuncCall { span, .. }
     | Node::SpreadParam { span, .. }
     | Node::SpreadInclude { span, .. }
+    | Node::SpreadData { span, .. }
     | Node::CharBlock { span, .. } => *span,
   }
 }
@@ -149,6 +153,7 @@ pub(crate) fn merge_span(a: Option<Span>, b: Option<Span>) -> Option<Span> {
         start_column: start_col,
         end_line: end_ln,
         end_column: end_col,
+        file: s1.file,
       })
     }
     (s, None) | (None, s) => s,
@@ -2,7 +2,7 @@
 
 #[cfg(test)]
 mod tests {
-  use crate::ast::{BinOp, Literal, Node};
+  use crate::ast::{BinOp, DataFormat, Import, Include, Literal, Node};
   use crate::parser::parse_program;
 
   #[test]
@@ -128,13 +128,35 @@ mod tests {
   #[test]
   fn parse_include() {
     let p = parse_program(r#"include "lib.branchy"; [ x; ]"#).unwrap();
-    assert_eq!(p.includes, ["lib.branchy"]);
+    assert_eq!(p.includes, [Include::all("lib.branchy")]);
     let Node::Branch { children, .. } = &p.main else {
       panic!("branch")
     };
     assert_eq!(children.len(), 1);
   }
 
+  #[test]
+  fn parse_namespaced_and_selective_imports() {
+    let src = r#"include "ui.branchy" as ui; from "lib" import greet, !tag; [ !ui.title(x); ui.card :_ { :_ = [ a; ]; }; ]"#;
+    let p = parse_program(src).unwrap();
+    assert_eq!(p.includes[0].import, Import::Namespace("ui".into()));
+    assert_eq!(
+      p.includes[1],
+      Include {
+        path: "lib".into(),
+        import: Import::Names(vec!["greet".into(), "tag".into()]),
+      }
+    );
+    let Node::Branch { children, .. } = &p.main else {
+      panic!("branch")
+    };
+    assert!(matches!(&children[0], Node::FuncCall { name, .. } if name == "ui.title"));
+    assert!(matches!(&children[1], Node::Call { name, .. } if name == "ui.card"));
+    assert!(parse_program(r#"include "a" as x.y; [ x; ]"#).is_err());
+    assert!(parse_program(r#"from "a" greet; [ x; ]"#).is_err());
+    assert!(parse_program("!ui.f() = [ x; ]; [ x; ]").is_err());
+  }
+
   #[test]
   fn parse_spread_param() {
     let p = parse_program(r#"[ a; ...:x; b; ]"#).unwrap();
@@ -157,6 +179,45 @@ mod tests {
     assert!(matches!(&children[0], Node::SpreadInclude { path: s, .. } if s == "mix.branchy"));
   }
 
+  #[test]
+  fn parse_data_spreads() {
+    let p = parse_program(
+      r#"[ ...lines "cities.txt"; ...csv "people.csv" column name; ...json "d.json" a.b; ...json "l.json"; ]"#,
+    )
+    .unwrap();
+    let Node::Branch { children, .. } = &p.main else {
+      panic!("branch")
+    };
+    let formats: Vec<_> = children
+      .iter()
+      .map(|c| match c {
+        Node::SpreadData { path, format, .. } => (path.as_str(), format.clone()),
+        other => panic!("expected data spread, got {other:?}"),
+      })
+      .collect();
+    assert_eq!(
+      formats,
+      [
+        ("cities.txt", DataFormat::Lines),
+        (
+          "people.csv",
+          DataFormat::Csv {
+            column: "name".into()
+          }
+        ),
+        ("d.json", DataFormat::Json { path: "a.b".into() }),
+        (
+          "l.json",
+          DataFormat::Json {
+            path: String::new()
+          }
+        ),
+      ]
+    );
+    let err = parse_program(r#"[ ...csv "p.csv" name; ]"#).unwrap_err();
+    assert!(err.message.contains("expected column"), "{}", err.message);
+  }
+
   #[test]
   fn parse_two_adjacent_branches() {
     let p = parse_program("[ a; b; ] [ a; b; ];").unwrap();
@@ -173,4 +234,18 @@ mod tests {
     assert_eq!(c1.len(), 2);
     assert_eq!(c2.len(), 2);
   }
+
+  #[test]
+  fn parse_branch_span_covers_brackets() {
+    let p = parse_program("[\n  a;\n  hi :x { :x = [ y ]; };\n]").unwrap();
+    let span = p.main.span().unwrap();
+    assert_eq!((span.start_line, span.start_column), (1, 1));
+    assert_eq!((span.end_line, span.end_column), (4, 2));
+    let Node::Branch { children, .. } = &p.main else {
+      panic!("branch")
+    };
+    let call = children[1].span().unwrap();
+    assert_eq!((call.start_line, call.start_column), (3, 3));
+    assert_eq!((call.end_line, call.end_column), (3, 24));
+  }
 }
@@ -0,0 +1,347 @@
+//! Interactive session for `branchy repl`: incremental definitions, evaluation, re-roll and trace.
+//!
+//! Every entered fragment is appended to a transcript and its spans are shifted to the
+//! transcript's line numbers, so `:trace` can show the source of each traced span.
+//! Definitions pulled in by `include` keep the line numbers of their own files.
+
+use crate::ast::{Event, FunctionDef, Node, Program, Span};
+use crate::builtins::BuiltinFn;
+use crate::parser::Fragment;
+use crate::{
+  default_registry, interpret, parse_fragment, resolve_includes_with, FsResolver, SearchPath,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub const HELP: &str = "\
+Enter definitions (!f(:x) = [ ... ], @event = [ ... ]) or expressions ([ a; b ], hi <x|y>).
+  :again, :r          re-roll the last expression or event
+  :event <input>, :e  run the event matching <input>
+  :seed [N]           set (or show) the seed
+  :load <file>        load definitions from a file (its main becomes the last expression)
+  :trace              show the spans chosen by the last evaluation
+  :defs               list functions and events
+  :reset              forget all definitions
+  :help               this text
+  :quit, :q           exit";
+
+/// Result of handling one input.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+  Print(String),
+  Silent,
+  Quit,
+}
+
+pub struct Session {
+  functions: Vec<FunctionDef>,
+  events: Vec<Event>,
+  builtins: HashMap<String, BuiltinFn>,
+  rng: StdRng,
+  seed: Option<u64>,
+  /// Last expression (or a loaded file's main) and the event input it ran with.
+  last: Option<(Node, Option<String>)>,
+  last_trace: Vec<Span>,
+  transcript: String,
+  search: SearchPath,
+}
+
+impl Default for Session {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Session {
+  pub fn new() -> Self {
+    Self::with_search_path(SearchPath::default())
+  }
+
+  /// Session whose includes are also looked up in `search`.
+  pub fn with_search_path(search: SearchPath) -> Self {
+    Self {
+      functions: Vec::new(),
+      events: Vec::new(),
+      builtins: default_registry(),
+      rng: StdRng::seed_from_u64(rand::random::<u64>()),
+      seed: None,
+      last: None,
+      last_trace: Vec::new(),
+      transcript: String::new(),
+      search,
+    }
+  }
+
+  /// Handle one (possibly multi-line) input: a `:command` or Branchy source.
+  pub fn handle(&mut self, input: &str) -> Result<Outcome, String> {
+    let input = input.trim();
+    if input.is_empty() {
+      return Ok(Outcome::Silent);
+    }
+    let Some(cmd) = input.strip_prefix(':') else {
+      return self.enter_source(input);
+    };
+    let (name, arg) = cmd
+      .split_once(char::is_whitespace)
+      .map_or((cmd, ""), |(n, a)| (n, a.trim()));
+    match name {
+      "quit" | "q" => Ok(Outcome::Quit),
+      "help" | "h" => Ok(Outcome::Print(HELP.into())),
+      "again" | "r" => {
+        let (node, input) = self.last.clone().ok_or("nothing to re-roll yet")?;
+        self.evaluate(&node, input.as_deref()).map(Outcome::Print)
+      }
+      "event" | "e" => self.event(arg).map(Outcome::Print),
+      "seed" => self.seed(arg).map(Outcome::Print),
+      "load" | "l" => self.load(arg).map(Outcome::Print),
+      "trace" | "t" => Ok(Outcome::Print(self.trace())),
+      "defs" => Ok(Outcome::Print(self.defs())),
+      "reset" => {
+        self.functions.clear();
+        self.events.clear();
+        self.last = None;
+        self.last_trace.clear();
+        Ok(Outcome::Print("definitions cleared".into()))
+      }
+      _ => Err(format!("unknown command :{name} (try :help)")),
+    }
+  }
+
+  fn enter_source(&mut self, src: &str) -> Result<Outcome, String> {
+    // Trailing `;` keeps a bare word like `hello` from lexing as a char block.
+    let wrapped = format!("[ {src}; ]");
+    let (src, fragment) = match parse_fragment(src) {
+      Ok(f) => (src, f),
+      Err(e) => match parse_fragment(&wrapped) {
+        Ok(f) => (wrapped.as_str(), f),
+        Err(_) => return Err(e.to_string()),
+      },
+    };
+    let (defined, main) = self.add(src, fragment, Path::new("."))?;
+    match main {
+      Some(main) => {
+        self.last = Some((main.clone(), None));
+        self.evaluate(&main, None).map(Outcome::Print)
+      }
+      None => Ok(Outcome::Print(format!("defined {}", defined.join(", ")))),
+    }
+  }
+
+  /// Append `src` to the transcript, shift the fragment's spans onto it, resolve includes
+  /// relative to `base` and merge definitions. Returns defined names and the main, if any.
+  fn add(
+    &mut self,
+    src: &str,
+    fragment: Fragment,
+    base: &Path,
+  ) -> Result<(Vec<String>, Option<Node>), String> {
+    let offset = u32::try_from(self.transcript.matches('\n').count()).unwrap_or(u32::MAX);
+    self.transcript.push_str(src);
+    if !src.ends_with('\n') {
+      self.transcript.push('\n');
+    }
+    let has_main = fragment.main.is_some();
+    let mut program = Program {
+      includes: fragment.includes,
+      functions: fragment.functions,
+      events: fragment.events,
+      main: fragment.main.unwrap_or(Node::Branch {
+        children: Vec::new(),
+        span: None,
+      }),
+    };
+    for f in &mut program.functions {
+      shift_spans(&mut f.body, offset);
+    }
+    for e in &mut program.events {
+      shift_spans(&mut e.body, offset);
+    }
+    shift_spans(&mut program.main, offset);
+    let program = resolve_includes_with(program, &FsResolver::new(base, self.search.clone()))?;
+    let mut defined = Vec::new();
+    for f in program.functions {
+      defined.push(format!("!{}", f.name));
+      self.functions.retain(|g| g.name != f.name);
+      self.functions.push(f);
+    }
+    for e in program.events {
+      defined.push(e.matcher.label());
+      self.events.retain(|g| g.matcher != e.matcher);
+      self.events.push(e);
+    }
+    Ok((defined, has_main.then_some(program.main)))
+  }
+
+  fn evaluate(&mut self, main: &Node, input: Option<&str>) -> Result<String, String> {
+    let program = Program {
+      includes: Vec::new(),
+      functions: self.functions.clone(),
+      events: self.events.clone(),
+      main: main.clone(),
+    };
+    self.last_trace.clear();
+    let (out, trace) =
+      interpret(&program, &self.builtins, &mut self.rng, input).map_err(|e| e.to_string())?;
+    self.last_trace = trace;
+    Ok(out)
+  }
+
+  fn event(&mut self, input: &str) -> Result<String, String> {
+    if self.events.is_empty() {
+      return Err("no events defined".into());
+    }
+    let main = Node::Branch {
+      children: Vec::new(),
+      span: None,
+    };
+    self.last = Some((main.clone(), Some(input.to_string())));
+    self.evaluate(&main, Some(input))
+  }
+
+  fn seed(&mut self, arg: &str) -> Result<String, String> {
+    if arg.is_empty() {
+      return Ok(
+        self
+          .seed
+          .map_or_else(|| "seed: random".into(), |s| format!("seed: {s}")),
+      );
+    }
+    let n: u64 = arg.parse().map_err(|_| format!("invalid seed: {arg}"))?;
+    self.rng = StdRng::seed_from_u64(n);
+    self.seed = Some(n);
+    Ok(format!("seed: {n}"))
+  }
+
+  fn load(&mut self, path: &str) -> Result<String, String> {
+    if path.is_empty() {
+      return Err(":load <file>".into());
+    }
+    let src = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let fragment = parse_fragment(&src).map_err(|e| format!("{path}: {e}"))?;
+    let base = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+    let (defined, main) = self.add(&src, fragment, base)?;
+    let mut msg = format!("loaded {} definition(s) from {path}", defined.len());
+    if let Some(main) = main {
+      self.last = Some((main, None));
+      msg.push_str("; main is the last expression (:again to run)");
+    }
+    Ok(msg)
+  }
+
+  fn trace(&self) -> String {
+    if self.last_trace.is_empty() {
+      return "(empty trace)".into();
+    }
+    self
+      .last_trace
+      .iter()
+      .map(|s| {
+        let text = s.text(&self.transcript).unwrap_or("?");
+        format!(
+          "{}:{}-{}:{}  {}",
+          s.start_line, s.start_column, s.end_line, s.end_column, text
+        )
+      })
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  fn defs(&self) -> String {
+    let mut out: Vec<String> = self
+      .functions
+      .iter()
+      .map(|f| {
+        let params: Vec<_> = f.params.iter().map(|p| format!(":{p}")).collect();
+        format!("!{}({})", f.name, params.join(", "))
+      })
+      .collect();
+    out.extend(self.events.iter().map(|e| e.matcher.label()));
+    if out.is_empty() {
+      "(no definitions)".into()
+    } else {
+      out.join("\n")
+    }
+  }
+}
+
+/// True while brackets opened in `src` are not yet closed (continue reading lines).
+pub fn is_incomplete(src: &str) -> bool {
+  if src.trim_start().starts_with(':') {
+    return false;
+  }
+  let mut depth = 0i32;
+  let mut quote = None;
+  let mut escaped = false;
+  for c in src.chars() {
+    if let Some(q) = quote {
+      if escaped {
+        escaped = false;
+      } else if c == '\\' {
+        escaped = true;
+      } else if c == q {
+        quote = None;
+      }
+      continue;
+    }
+    match c {
+      '"' | '\'' => quote = Some(c),
+      '[' | '{' | '(' => depth += 1,
+      ']' | '}' | ')' => depth -= 1,
+      _ => {}
+    }
+  }
+  depth > 0 || quote.is_some()
+}
+
+const fn shift_span(span: &mut Option<Span>, lines: u32) {
+  if let Some(s) = span {
+    s.start_line += lines;
+    s.end_line += lines;
+  }
+}
+
+/// Move all spans in `node` down by `lines` lines.
+fn shift_spans(node: &mut Node, lines: u32) {
+  match node {
+    Node::Branch { children, span } => {
+      shift_span(span, lines);
+      for c in children {
+        shift_spans(c, lines);
+      }
+    }
+    Node::BinaryOp {
+      left, right, span, ..
+    } => {
+      shift_span(span, lines);
+      shift_spans(left, lines);
+      shift_spans(right, lines);
+    }
+    Node::Call { block, span, .. } => {
+      shift_span(span, lines);
+      for (_, v) in block.iter_mut().flat_map(|b| b.bindings.iter_mut()) {
+        shift_spans(v, lines);
+      }
+    }
+    Node::InlineCall {
+      options: nodes,
+      span,
+      ..
+    }
+    | Node::FuncCall {
+      args: nodes, span, ..
+    } => {
+      shift_span(span, lines);
+      for n in nodes {
+        shift_spans(n, lines);
+      }
+    }
+    Node::Leaf { span, .. }
+    | Node::SpreadParam { span, .. }
+    | Node::SpreadInclude { span, .. }
+    | Node::SpreadData { span, .. }
+    | Node::CharBlock { span, .. } => shift_span(span, lines),
+  }
+}
@@ -0,0 +1,117 @@
+//! Includes served from a zip or tar archive (a bundled project).
+
+use super::{glob, normalize, MemoryResolver, SourceResolver};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+/// The files of an archive, read into memory up front; paths are relative to the archive root
+/// and behave like [`MemoryResolver`] paths. Files that are not UTF-8 text are only an error
+/// when included.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveResolver {
+  files: MemoryResolver,
+  /// Normalized paths of the files that are not UTF-8 text.
+  binary: BTreeSet<String>,
+}
+
+impl ArchiveResolver {
+  /// Open a `.zip` or `.tar` file.
+  pub fn open(path: &Path) -> Result<Self, String> {
+    let bytes = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let archive = match path.extension().and_then(|e| e.to_str()) {
+      Some("zip") => Self::from_zip(&bytes),
+      Some("tar") => Self::from_tar(&bytes),
+      _ => {
+        return Err(format!(
+          "{}: expected a .zip or .tar archive",
+          path.display()
+        ))
+      }
+    };
+    archive.map_err(|e| format!("{}: {e}", path.display()))
+  }
+
+  pub fn from_zip(bytes: &[u8]) -> Result<Self, String> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let mut archive = Self::default();
+    for i in 0..zip.len() {
+      let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+      if !entry.is_file() {
+        continue;
+      }
+      let name = entry.name().to_string();
+      archive.add(&name, &mut entry)?;
+    }
+    Ok(archive)
+  }
+
+  pub fn from_tar(bytes: &[u8]) -> Result<Self, String> {
+    let mut tar = tar::Archive::new(bytes);
+    let mut archive = Self::default();
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+      let mut entry = entry.map_err(|e| e.to_string())?;
+      if !entry.header().entry_type().is_file() {
+        continue;
+      }
+      let name = entry
+        .path()
+        .map_err(|e| e.to_string())?
+        .to_string_lossy()
+        .into_owned();
+      archive.add(&name, &mut entry)?;
+    }
+    Ok(archive)
+  }
+
+  /// Paths of the text files in the archive, sorted.
+  pub fn paths(&self) -> impl Iterator<Item = &str> {
+    self.files.paths()
+  }
+
+  /// Read the entry `name`, keeping it as text when it is UTF-8.
+  fn add(&mut self, name: &str, entry: &mut impl Read) -> Result<(), String> {
+    let mut bytes = Vec::new();
+    entry
+      .read_to_end(&mut bytes)
+      .map_err(|e| format!("{name}: {e}"))?;
+    match String::from_utf8(bytes) {
+      Ok(text) => self.files.insert(name, text),
+      Err(_) => {
+        self.binary.insert(normalize(name));
+      }
+    }
+    Ok(())
+  }
+}
+
+impl SourceResolver for ArchiveResolver {
+  fn canonicalize(&self, include: &str) -> Result<String, String> {
+    let path = normalize(include);
+    if self.binary.contains(&path) {
+      return Ok(path);
+    }
+    self.files.canonicalize(include)
+  }
+
+  fn read(&self, path: &str) -> Result<String, String> {
+    if self.binary.contains(path) {
+      return Err(format!("{path}: not a UTF-8 text file"));
+    }
+    self.files.read(path)
+  }
+
+  fn glob(&self, pattern: &str) -> Result<Vec<String>, String> {
+    let pattern = normalize(pattern);
+    let mut found = self.files.glob(&pattern)?;
+    found.extend(
+      self
+        .binary
+        .iter()
+        .filter(|p| glob::matches(&pattern, p))
+        .cloned(),
+    );
+    Ok(found)
+  }
+}
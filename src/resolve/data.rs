@@ -0,0 +1,119 @@
+//! Data-file spreads: records of text, CSV and JSON files as branch leaves.
+
+use crate::ast::DataFormat;
+use serde_json::Value;
+
+/// Records of `text` (the contents of a data file) in file order.
+pub(super) fn records(format: &DataFormat, text: &str) -> Result<Vec<String>, String> {
+  match format {
+    DataFormat::Lines => Ok(
+      text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect(),
+    ),
+    DataFormat::Csv { column } => csv_column(text, column),
+    DataFormat::Json { path } => {
+      let doc: Value = serde_json::from_str(text).map_err(|e| format!("invalid JSON: {e}"))?;
+      let mut out = Vec::new();
+      let parts: Vec<&str> = path.split('.').filter(|p| !p.is_empty()).collect();
+      json_values(&doc, &parts, path, &mut out)?;
+      Ok(out)
+    }
+  }
+}
+
+fn csv_column(text: &str, column: &str) -> Result<Vec<String>, String> {
+  let mut rows = csv_rows(text)?.into_iter();
+  let header = rows.next().ok_or("empty CSV file")?;
+  let index = header
+    .iter()
+    .position(|h| h.trim() == column)
+    .ok_or_else(|| format!("no column \"{column}\" (columns: {})", header.join(", ")))?;
+  Ok(
+    rows
+      .filter_map(|mut r| (index < r.len()).then(|| r.swap_remove(index)))
+      .filter(|v| !v.trim().is_empty())
+      .collect(),
+  )
+}
+
+/// RFC 4180 rows: comma-separated, fields optionally quoted with `""` escaping a quote.
+/// Blank lines are skipped.
+fn csv_rows(text: &str) -> Result<Vec<Vec<String>>, String> {
+  let mut rows = Vec::new();
+  let mut row = Vec::new();
+  let mut field = String::new();
+  let mut chars = text.chars().peekable();
+  let mut line = 1;
+  while let Some(c) = chars.next() {
+    match c {
+      '"' if field.is_empty() => loop {
+        match chars.next() {
+          Some('"') if chars.peek() == Some(&'"') => {
+            chars.next();
+            field.push('"');
+          }
+          Some('"') => break,
+          Some(c) => {
+            if c == '\n' {
+              line += 1;
+            }
+            field.push(c);
+          }
+          None => return Err(format!("line {line}: unterminated quoted field")),
+        }
+      },
+      ',' => row.push(std::mem::take(&mut field)),
+      '\r' if chars.peek() == Some(&'\n') => {}
+      '\n' => {
+        line += 1;
+        row.push(std::mem::take(&mut field));
+        if row.len() > 1 || !row[0].is_empty() {
+          rows.push(std::mem::take(&mut row));
+        }
+        row.clear();
+      }
+      c => field.push(c),
+    }
+  }
+  row.push(field);
+  if row.len() > 1 || !row[0].is_empty() {
+    rows.push(row);
+  }
+  Ok(rows)
+}
+
+/// Collect the scalars at `parts` below `value`; arrays are flattened at every level.
+fn json_values(
+  value: &Value,
+  parts: &[&str],
+  path: &str,
+  out: &mut Vec<String>,
+) -> Result<(), String> {
+  match (value, parts.split_first()) {
+    (Value::Array(items), _) => {
+      for v in items {
+        json_values(v, parts, path, out)?;
+      }
+      Ok(())
+    }
+    (Value::Object(map), Some((key, rest))) => map.get(*key).map_or_else(
+      || Err(format!("no key \"{key}\" at \"{path}\"")),
+      |v| json_values(v, rest, path, out),
+    ),
+    (Value::String(s), None) => {
+      out.push(s.clone());
+      Ok(())
+    }
+    (Value::Number(_) | Value::Bool(_), None) => {
+      out.push(value.to_string());
+      Ok(())
+    }
+    (Value::Object(_), None) => Err(format!("\"{path}\" is an object, expected strings")),
+    (Value::Null, _) => Ok(()),
+    (_, Some((key, _))) => Err(format!("\"{key}\" in \"{path}\" is not an object key")),
+  }
+}
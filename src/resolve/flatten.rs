@@ -1,17 +1,64 @@
 //! Flatten node: resolve ...include in place.
 
-use crate::ast::Node;
-use crate::parser::parse_program;
+use super::{data, ResolveError, SourceResolver, Sources};
+use crate::ast::{DataFormat, Literal, Node, Span};
 use std::collections::HashSet;
 
-pub(crate) fn flatten_node<F>(
+/// Main branches of the files matched by a `...include` path, flattened, in sorted order.
+fn spread<R: SourceResolver + ?Sized>(
+  path: &str,
+  sources: &Sources<R>,
+  in_progress: &mut HashSet<String>,
+) -> Result<Vec<Node>, ResolveError> {
+  let pattern = (super::glob::is_glob(path)).then_some(path);
+  let mut mains = Vec::new();
+  for file in sources.expand(path)? {
+    let key = sources.canonical(&file)?;
+    if in_progress.contains(&key) {
+      return Err(format!("circular ...include: {}", file).into());
+    }
+    in_progress.insert(key.clone());
+    let inc = sources.parse(&file, &key, pattern)?;
+    mains.push(flatten_node(inc.main, sources, in_progress)?);
+    in_progress.remove(&key);
+  }
+  Ok(mains)
+}
+
+/// One string leaf per record of the data files matched by `path`, in sorted file order. A
+/// record's span is in its data file, on the line numbered by its position among the file's
+/// records, so runs trace each record apart and coverage gives them no points in the entry file.
+fn data_leaves<R: SourceResolver + ?Sized>(
+  path: &str,
+  format: &DataFormat,
+  sources: &Sources<R>,
+) -> Result<Vec<Node>, String> {
+  let mut leaves = Vec::new();
+  for file in sources.expand(path)? {
+    let (id, text) = sources.read(&file)?;
+    let records = data::records(format, &text).map_err(|e| format!("{file}: {e}"))?;
+    leaves.extend((1..).zip(records).map(|(line, s)| Node::Leaf {
+      lit: Literal::Str(s),
+      span: Some(Span {
+        start_line: line,
+        start_column: 1,
+        end_line: line,
+        end_column: 1,
+        file: id,
+      }),
+    }));
+  }
+  if leaves.is_empty() {
+    return Err(format!("{path}: no records"));
+  }
+  Ok(leaves)
+}
+
+pub(crate) fn flatten_node<R: SourceResolver + ?Sized>(
   node: Node,
-  resolver: &F,
+  sources: &Sources<R>,
   in_progress: &mut HashSet<String>,
-) -> Result<Node, String>
-where
-  F: Fn(&str) -> Result<String, String>,
-{
+) -> Result<Node, ResolveError> {
   use crate::ast::Node::*;
   match node {
     Branch { children, span } => {
@@ -19,22 +66,19 @@ where
       for c in children {
         match c {
           SpreadInclude { path, .. } => {
-            if in_progress.contains(&path) {
-              return Err(format!("circular ...include: {}", path));
-            }
-            in_progress.insert(path.clone());
-            let src = resolver(&path)?;
-            let inc = parse_program(&src).map_err(|e| e.to_string())?;
-            let main = flatten_node(inc.main, resolver, in_progress)?;
-            in_progress.remove(&path);
-            match main {
-              Branch {
-                children: nodes, ..
-              } => out.extend(nodes),
-              other => out.push(other),
+            for main in spread(&path, sources, in_progress)? {
+              match main {
+                Branch {
+                  children: nodes, ..
+                } => out.extend(nodes),
+                other => out.push(other),
+              }
             }
           }
-          other => out.push(flatten_node(other, resolver, in_progress)?),
+          SpreadData { path, format, .. } => {
+            out.extend(data_leaves(&path, &format, sources)?);
+          }
+          other => out.push(flatten_node(other, sources, in_progress)?),
         }
       }
       Ok(Branch {
@@ -49,8 +93,8 @@ where
       span,
     } => Ok(BinaryOp {
       op,
-      left: Box::new(flatten_node(*left, resolver, in_progress)?),
-      right: Box::new(flatten_node(*right, resolver, in_progress)?),
+      left: Box::new(flatten_node(*left, sources, in_progress)?),
+      right: Box::new(flatten_node(*right, sources, in_progress)?),
       span,
     }),
     Call {
@@ -64,7 +108,7 @@ where
         .map(|b| {
           b.bindings
             .into_iter()
-            .map(|(k, v)| flatten_node(v, resolver, in_progress).map(|n| (k, n)))
+            .map(|(k, v)| flatten_node(v, sources, in_progress).map(|n| (k, n)))
             .collect::<Result<Vec<_>, _>>()
             .map(|bindings| crate::ast::CallBlock { bindings })
         })
@@ -85,7 +129,7 @@ where
       name,
       options: options
         .into_iter()
-        .map(|n| flatten_node(n, resolver, in_progress))
+        .map(|n| flatten_node(n, sources, in_progress))
         .collect::<Result<Vec<_>, _>>()?,
       span,
     }),
@@ -93,21 +137,24 @@ where
       name,
       args: args
         .into_iter()
-        .map(|n| flatten_node(n, resolver, in_progress))
+        .map(|n| flatten_node(n, sources, in_progress))
         .collect::<Result<Vec<_>, _>>()?,
       span,
     }),
     Leaf { .. } | SpreadParam { .. } | CharBlock { .. } => Ok(node),
-    SpreadInclude { path, .. } => {
-      if in_progress.contains(&path) {
-        return Err(format!("circular ...include: {}", path));
+    SpreadData { path, format, span } => Ok(Branch {
+      children: data_leaves(&path, &format, sources)?,
+      span,
+    }),
+    SpreadInclude { path, span } => {
+      let mut mains = spread(&path, sources, in_progress)?;
+      if mains.len() == 1 {
+        return Ok(mains.remove(0));
       }
-      in_progress.insert(path.clone());
-      let src = resolver(&path)?;
-      let inc = parse_program(&src).map_err(|e| e.to_string())?;
-      let main = flatten_node(inc.main, resolver, in_progress)?;
-      in_progress.remove(&path);
-      Ok(main)
+      Ok(Branch {
+        children: mains,
+        span,
+      })
     }
   }
 }
@@ -0,0 +1,39 @@
+//! Wildcards in include paths: `*` (any run of characters except `/`) and `?` (one character).
+
+pub(super) fn is_glob(path: &str) -> bool {
+  path.contains(['*', '?'])
+}
+
+/// True if `text` matches `pattern` as a whole.
+pub(super) fn matches(pattern: &str, text: &str) -> bool {
+  let p: Vec<char> = pattern.chars().collect();
+  let t: Vec<char> = text.chars().collect();
+  // Backtracking over the last `*`: (pattern index after it, text index it matched up to).
+  let (mut pi, mut ti) = (0, 0);
+  let mut star: Option<(usize, usize)> = None;
+  while ti < t.len() {
+    match p.get(pi) {
+      Some('*') => {
+        star = Some((pi + 1, ti));
+        pi += 1;
+      }
+      Some('?') if t[ti] != '/' => {
+        pi += 1;
+        ti += 1;
+      }
+      Some(&c) if c == t[ti] => {
+        pi += 1;
+        ti += 1;
+      }
+      _ => match star {
+        Some((sp, st)) if t[st] != '/' => {
+          star = Some((sp, st + 1));
+          pi = sp;
+          ti = st + 1;
+        }
+        _ => return false,
+      },
+    }
+  }
+  p[pi..].iter().all(|&c| c == '*')
+}
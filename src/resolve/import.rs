@@ -0,0 +1,85 @@
+//! Namespaced and selective imports: renaming module functions and tracking name origins.
+
+use crate::ast::{FunctionDef, Node};
+use std::collections::HashMap;
+
+/// Where each unqualified function name came from: `None` for the program's own definitions,
+/// `Some(path)` for an include. Used to reject ambiguous names.
+pub(super) struct Origins(HashMap<String, Option<String>>);
+
+impl Origins {
+  pub fn new(local: &[FunctionDef]) -> Self {
+    Self(local.iter().map(|f| (f.name.clone(), None)).collect())
+  }
+
+  /// Record `name` as coming from `path`. Plain includes are shadowed by local definitions;
+  /// an explicit import (`as` / `from`) of a locally defined name is an error, as is the same
+  /// name coming from two different files.
+  pub fn add(&mut self, name: &str, path: &str, explicit: bool) -> Result<(), String> {
+    match self.0.get(name) {
+      None => {
+        self.0.insert(name.to_string(), Some(path.to_string()));
+        Ok(())
+      }
+      Some(None) if !explicit => Ok(()),
+      Some(None) => Err(format!(
+        "ambiguous function !{name}: defined locally and imported from \"{path}\""
+      )),
+      Some(Some(p)) if p == path => Ok(()),
+      Some(Some(p)) => Err(format!(
+        "ambiguous function !{name}: defined in both \"{p}\" and \"{path}\" \
+         (use include \"...\" as ns)"
+      )),
+    }
+  }
+}
+
+/// Rename functions of an imported module and every call to them inside the module.
+pub(super) fn rename_functions(functions: &mut [FunctionDef], renames: &HashMap<String, String>) {
+  for f in functions {
+    if let Some(n) = renames.get(&f.name) {
+      f.name.clone_from(n);
+    }
+    rename_calls(&mut f.body, renames);
+  }
+}
+
+fn rename_calls(node: &mut Node, renames: &HashMap<String, String>) {
+  match node {
+    Node::Branch { children, .. } => {
+      for c in children {
+        rename_calls(c, renames);
+      }
+    }
+    Node::BinaryOp { left, right, .. } => {
+      rename_calls(left, renames);
+      rename_calls(right, renames);
+    }
+    Node::Call { name, block, .. } => {
+      if let Some(n) = renames.get(name.as_str()) {
+        name.clone_from(n);
+      }
+      for (_, v) in block.iter_mut().flat_map(|b| b.bindings.iter_mut()) {
+        rename_calls(v, renames);
+      }
+    }
+    Node::FuncCall { name, args, .. } => {
+      if let Some(n) = renames.get(name.as_str()) {
+        name.clone_from(n);
+      }
+      for a in args {
+        rename_calls(a, renames);
+      }
+    }
+    Node::InlineCall { options, .. } => {
+      for o in options {
+        rename_calls(o, renames);
+      }
+    }
+    Node::Leaf { .. }
+    | Node::SpreadParam { .. }
+    | Node::SpreadInclude { .. }
+    | Node::SpreadData { .. }
+    | Node::CharBlock { .. } => {}
+  }
+}
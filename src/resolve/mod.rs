@@ -1,40 +1,299 @@
-//! Resolve includes and flatten SpreadInclude.
+//! Resolve includes and flatten `SpreadInclude` and `SpreadData`.
 
+mod archive;
+mod data;
 mod flatten;
+mod glob;
+mod import;
+mod resolver;
+mod search;
 
-use crate::ast::Program;
+use crate::ast::{Import, Include, Program, SourceError};
 use crate::parser::parse_program;
-use std::collections::{HashSet, VecDeque};
+use crate::stdlib;
+use import::Origins;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+pub use archive::ArchiveResolver;
+pub use resolver::{normalize, FsResolver, MemoryResolver, SourceResolver};
+pub use search::SearchPath;
+
+/// Why includes could not be resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+  pub message: String,
+  /// For a parse error in an included file: that error, with `file` set to the file's
+  /// canonical name and the span within it.
+  pub parse: Option<SourceError>,
+}
+
+impl From<String> for ResolveError {
+  fn from(message: String) -> Self {
+    Self {
+      message,
+      parse: None,
+    }
+  }
+}
+
+impl fmt::Display for ResolveError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+impl std::error::Error for ResolveError {}
 
 /// Resolves all `include "path"` directives and `...include "path"` mixins.
-pub fn resolve_includes<F>(mut program: Program, resolver: F) -> Result<Program, String>
+///
+/// Files are read with `resolver` (paths as written, after lexical normalization of `.` and
+/// `..`). Embedded standard library modules (`std:name`) are served before it is asked.
+///
+/// Plain includes merge functions and events; `include "path" as ns` imports functions as
+/// `ns.name` and `from "path" import a, b` only the listed ones (other functions of the module
+/// stay reachable from its own bodies under hidden `file::name` names, `file` being the
+/// module's canonical name).
+pub fn resolve_includes<F>(program: Program, resolver: F) -> Result<Program, String>
 where
   F: Fn(&str) -> Result<String, String>,
 {
+  resolve_includes_with(program, &resolver)
+}
+
+/// Like [`resolve_includes`], with any [`SourceResolver`] (filesystem, in-memory, archive).
+///
+/// Files are identified by the resolver's canonical names, so a file reached through different
+/// paths is included once. Glob patterns (`words/*.branchy`) are expanded by the resolver and
+/// included in sorted order; `std:` patterns match embedded modules.
+pub fn resolve_includes_with<R>(program: Program, resolver: &R) -> Result<Program, String>
+where
+  R: SourceResolver + ?Sized,
+{
+  resolve_includes_detailed(program, resolver).map_err(|e| e.message)
+}
+
+/// Like [`resolve_includes_with`], telling parse errors in included files apart (see
+/// [`ResolveError::parse`]).
+pub fn resolve_includes_detailed<R>(program: Program, resolver: &R) -> Result<Program, ResolveError>
+where
+  R: SourceResolver + ?Sized,
+{
+  let sources = Sources {
+    resolver,
+    files: RefCell::default(),
+  };
+  resolve_with(program, &sources, &mut Vec::new())
+}
+
+/// The embedded standard library in front of a [`SourceResolver`].
+pub(crate) struct Sources<'a, R: ?Sized> {
+  resolver: &'a R,
+  /// Canonical names of the files parsed so far; a file's span id is its position plus one.
+  files: RefCell<Vec<String>>,
+}
+
+impl<R: SourceResolver + ?Sized> Sources<'_, R> {
+  /// Includes matched by `path` in sorted order if it is a glob pattern, else just `path`.
+  pub fn expand(&self, path: &str) -> Result<Vec<String>, String> {
+    if !glob::is_glob(path) {
+      return Ok(vec![path.to_string()]);
+    }
+    let mut found = if let Some(name) = path.strip_prefix(stdlib::PREFIX) {
+      let name = name.strip_suffix(".branchy").unwrap_or(name);
+      stdlib::MODULES
+        .iter()
+        .filter(|(n, _)| glob::matches(name, n))
+        .map(|(n, _)| format!("{}{n}", stdlib::PREFIX))
+        .collect()
+    } else {
+      self.resolver.glob(path)?
+    };
+    found.sort();
+    found.dedup();
+    if found.is_empty() {
+      return Err(format!("include \"{path}\": no files match"));
+    }
+    Ok(found)
+  }
+
+  /// Canonical name of `include`: `std:name` for embedded modules, else the resolver's.
+  pub fn canonical(&self, include: &str) -> Result<String, String> {
+    if stdlib::lookup(include).is_some() {
+      return Ok(
+        include
+          .strip_suffix(".branchy")
+          .unwrap_or(include)
+          .to_string(),
+      );
+    }
+    self.resolver.canonicalize(include)
+  }
+
+  /// Span file id and contents of a data file (`...lines` and friends); never an embedded
+  /// module.
+  pub fn read(&self, include: &str) -> Result<(u32, String), String> {
+    let key = self.resolver.canonicalize(include)?;
+    let text = self.resolver.read(&key)?;
+    Ok((self.file_id(&key), text))
+  }
+
+  /// Read and parse the file `key` (the canonical name of `include`), marking its functions and
+  /// events as coming from `key` and its spans with the file's id. `pattern` is the glob it was matched by, named in errors.
+  pub fn parse(
+    &self,
+    include: &str,
+    key: &str,
+    pattern: Option<&str>,
+  ) -> Result<Program, ResolveError> {
+    let matched = pattern.map_or_else(String::new, |p| format!(" (matched by \"{p}\")"));
+    let src = match stdlib::lookup(key) {
+      Some(s) => s.to_string(),
+      None => self.resolver.read(key).map_err(|e| {
+        if matched.is_empty() {
+          e
+        } else {
+          format!("{include}{matched}: {e}")
+        }
+      })?,
+    };
+    let mut program = parse_program(&src).map_err(|e| ResolveError {
+      message: format!("{include}{matched}: {e}"),
+      parse: Some(e.in_file(Some(key))),
+    })?;
+    let id = self.file_id(key);
+    for f in &mut program.functions {
+      f.file = Some(key.to_string());
+      f.body.set_file(id);
+    }
+    for e in &mut program.events {
+      e.file = Some(key.to_string());
+      e.body.set_file(id);
+    }
+    program.main.set_file(id);
+    Ok(program)
+  }
+
+  /// Span file id of `key`, numbering files from 1 in the order they are first parsed.
+  fn file_id(&self, key: &str) -> u32 {
+    let mut files = self.files.borrow_mut();
+    let i = files.iter().position(|f| f == key).unwrap_or_else(|| {
+      files.push(key.to_string());
+      files.len() - 1
+    });
+    u32::try_from(i + 1).unwrap_or(u32::MAX)
+  }
+}
+
+/// `modules` holds the paths of modules being imported, outermost first (cycle detection).
+fn resolve_with<R: SourceResolver + ?Sized>(
+  mut program: Program,
+  sources: &Sources<R>,
+  modules: &mut Vec<String>,
+) -> Result<Program, ResolveError> {
+  let mut origins = Origins::new(&program.functions);
   let mut resolved = HashSet::new();
-  let mut queue: VecDeque<String> = program.includes.iter().cloned().collect();
-  while let Some(path) = queue.pop_front() {
-    if resolved.contains(&path) {
+  let mut queue: VecDeque<(Include, Option<String>)> =
+    program.includes.drain(..).map(|i| (i, None)).collect();
+  while let Some((Include { path, import }, pattern)) = queue.pop_front() {
+    if glob::is_glob(&path) {
+      if matches!(import, Import::Names(_)) {
+        return Err(format!("from \"{path}\": glob patterns cannot be used with import").into());
+      }
+      for p in sources.expand(&path)?.into_iter().rev() {
+        let include = Include {
+          path: p,
+          import: import.clone(),
+        };
+        queue.push_front((include, Some(path.clone())));
+      }
       continue;
     }
-    resolved.insert(path.clone());
-    let src = resolver(&path)?;
-    let inc = parse_program(&src).map_err(|e| e.to_string())?;
-    for p in inc.includes {
-      queue.push_back(p);
-    }
-    program.functions.extend(inc.functions);
-    program.events.extend(inc.events);
+    let pattern = pattern.as_deref();
+    let key = sources.canonical(&path)?;
+    let (mut functions, renames): (_, HashMap<_, _>) = match import {
+      Import::All => {
+        if !resolved.insert(key.clone()) {
+          continue;
+        }
+        let inc = sources.parse(&path, &key, pattern)?;
+        queue.extend(inc.includes.into_iter().map(|i| (i, None)));
+        for f in &inc.functions {
+          origins.add(&f.name, &key, false)?;
+        }
+        program.functions.extend(inc.functions);
+        program.events.extend(inc.events);
+        continue;
+      }
+      Import::Namespace(ns) => {
+        let module = load_module(&path, &key, pattern, sources, modules)?;
+        let renames = module
+          .functions
+          .iter()
+          .map(|f| (f.name.clone(), format!("{ns}.{}", f.name)))
+          .collect();
+        for f in &module.functions {
+          origins.add(&format!("{ns}.{}", f.name), &key, true)?;
+        }
+        (module.functions, renames)
+      }
+      Import::Names(names) => {
+        let module = load_module(&path, &key, pattern, sources, modules)?;
+        for n in &names {
+          if !module.functions.iter().any(|f| f.name == *n) {
+            return Err(format!("\"{path}\" has no function !{n} to import").into());
+          }
+          origins.add(n, &key, true)?;
+        }
+        let renames = module
+          .functions
+          .iter()
+          .filter(|f| !names.contains(&f.name))
+          .map(|f| (f.name.clone(), format!("{key}::{}", f.name)))
+          .collect();
+        (module.functions, renames)
+      }
+    };
+    import::rename_functions(&mut functions, &renames);
+    // A module imported twice brings its hidden helpers twice, under the same names.
+    functions.retain(|f| {
+      !program
+        .functions
+        .iter()
+        .any(|g| g.name == f.name && g.file == f.file)
+    });
+    program.functions.extend(functions);
   }
   program.includes.clear();
 
   let mut in_progress = HashSet::new();
-  program.main = flatten::flatten_node(program.main.clone(), &resolver, &mut in_progress)?;
+  program.main = flatten::flatten_node(program.main.clone(), sources, &mut in_progress)?;
   for fd in &mut program.functions {
-    fd.body = flatten::flatten_node(fd.body.clone(), &resolver, &mut in_progress)?;
+    fd.body = flatten::flatten_node(fd.body.clone(), sources, &mut in_progress)?;
   }
   for ev in &mut program.events {
-    ev.body = flatten::flatten_node(ev.body.clone(), &resolver, &mut in_progress)?;
+    ev.body = flatten::flatten_node(ev.body.clone(), sources, &mut in_progress)?;
   }
   Ok(program)
 }
+
+/// Parse and resolve an imported module on its own (its plain includes are merged into it).
+/// `key` is the canonical name of `path`.
+fn load_module<R: SourceResolver + ?Sized>(
+  path: &str,
+  key: &str,
+  pattern: Option<&str>,
+  sources: &Sources<R>,
+  modules: &mut Vec<String>,
+) -> Result<Program, ResolveError> {
+  if modules.iter().any(|m| m == key) {
+    let chain = modules.join(" -> ");
+    return Err(format!("circular import: {chain} -> {key}").into());
+  }
+  let module = sources.parse(path, key, pattern)?;
+  modules.push(key.to_string());
+  let out = resolve_with(module, sources, modules);
+  modules.pop();
+  out
+}
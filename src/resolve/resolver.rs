@@ -0,0 +1,172 @@
+//! Where include sources come from: the [`SourceResolver`] trait and its filesystem and
+//! in-memory implementations.
+
+use super::glob;
+use super::search::SearchPath;
+use crate::stdlib::PREFIX as LIBRARY_PREFIX;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Source of included files. Includes are first mapped to a canonical name (the same file
+/// reached through different paths must get the same name), which is then read.
+pub trait SourceResolver {
+  /// Canonical name of the file an include path refers to. The default normalizes `.` and
+  /// `..` components lexically.
+  fn canonicalize(&self, include: &str) -> Result<String, String> {
+    Ok(normalize(include))
+  }
+
+  /// Contents of the file with canonical name `path`.
+  fn read(&self, path: &str) -> Result<String, String>;
+
+  /// Include paths matched by a glob pattern (`words/*.branchy`), in any order.
+  fn glob(&self, pattern: &str) -> Result<Vec<String>, String> {
+    Err(format!(
+      "include \"{pattern}\": glob patterns are not supported here"
+    ))
+  }
+}
+
+/// A plain function of the include path, as accepted by `resolve_includes`.
+impl<F> SourceResolver for F
+where
+  F: Fn(&str) -> Result<String, String>,
+{
+  fn read(&self, path: &str) -> Result<String, String> {
+    self(path)
+  }
+}
+
+/// Lexically normalize a `/`-separated path: drop empty and `.` components and fold `..` into
+/// the previous component (`a/./b/../c` → `a/c`). A `std:` prefix is kept.
+pub fn normalize(path: &str) -> String {
+  let (prefix, rest) = path
+    .strip_prefix(LIBRARY_PREFIX)
+    .map_or(("", path), |rest| (LIBRARY_PREFIX, rest));
+  let absolute = rest.starts_with('/');
+  let mut parts: Vec<&str> = Vec::new();
+  for part in rest.split('/') {
+    match part {
+      "" | "." => {}
+      ".." if parts.last().is_some_and(|p| *p != "..") => {
+        parts.pop();
+      }
+      ".." if absolute => {}
+      p => parts.push(p),
+    }
+  }
+  let root = if absolute { "/" } else { "" };
+  format!("{prefix}{root}{}", parts.join("/"))
+}
+
+/// Files on disk, looked up relative to the entry script's directory and then in a
+/// [`SearchPath`]. Canonical names are absolute paths with symlinks resolved.
+///
+/// Every file read and directory listed is remembered (see [`files`](Self::files)), so watch
+/// mode knows what to watch.
+pub struct FsResolver {
+  base: PathBuf,
+  search: SearchPath,
+  seen: RefCell<Vec<PathBuf>>,
+}
+
+impl FsResolver {
+  pub fn new(base: impl Into<PathBuf>, search: SearchPath) -> Self {
+    Self {
+      base: base.into(),
+      search,
+      seen: RefCell::new(Vec::new()),
+    }
+  }
+
+  /// Files read and directories listed so far; also paths of includes that were not found.
+  pub fn files(&self) -> Vec<PathBuf> {
+    self.seen.borrow().clone()
+  }
+}
+
+impl SourceResolver for FsResolver {
+  fn canonicalize(&self, include: &str) -> Result<String, String> {
+    let found = self
+      .search
+      .find(&self.base, include)
+      .inspect_err(|_| self.seen.borrow_mut().push(self.base.join(include)))?;
+    let full = fs::canonicalize(&found).map_err(|e| format!("{}: {e}", found.display()))?;
+    Ok(full.display().to_string())
+  }
+
+  fn read(&self, path: &str) -> Result<String, String> {
+    self.seen.borrow_mut().push(PathBuf::from(path));
+    fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))
+  }
+
+  fn glob(&self, pattern: &str) -> Result<Vec<String>, String> {
+    Ok(
+      self
+        .search
+        .glob(&self.base, pattern, &mut self.seen.borrow_mut()),
+    )
+  }
+}
+
+/// Virtual files held in memory, keyed by normalized path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryResolver {
+  files: BTreeMap<String, String>,
+}
+
+impl MemoryResolver {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, path: &str, source: impl Into<String>) {
+    self.files.insert(normalize(path), source.into());
+  }
+
+  /// Normalized paths of all files, sorted.
+  pub fn paths(&self) -> impl Iterator<Item = &str> {
+    self.files.keys().map(String::as_str)
+  }
+}
+
+impl<P: AsRef<str>, S: Into<String>> FromIterator<(P, S)> for MemoryResolver {
+  fn from_iter<I: IntoIterator<Item = (P, S)>>(iter: I) -> Self {
+    let mut out = Self::new();
+    for (path, source) in iter {
+      out.insert(path.as_ref(), source);
+    }
+    out
+  }
+}
+
+impl SourceResolver for MemoryResolver {
+  fn canonicalize(&self, include: &str) -> Result<String, String> {
+    let path = normalize(include);
+    if self.files.contains_key(&path) {
+      return Ok(path);
+    }
+    Err(format!("include \"{include}\": file not found"))
+  }
+
+  fn read(&self, path: &str) -> Result<String, String> {
+    self
+      .files
+      .get(path)
+      .cloned()
+      .ok_or_else(|| format!("{path}: file not found"))
+  }
+
+  fn glob(&self, pattern: &str) -> Result<Vec<String>, String> {
+    let pattern = normalize(pattern);
+    Ok(
+      self
+        .paths()
+        .filter(|p| glob::matches(&pattern, p))
+        .map(str::to_string)
+        .collect(),
+    )
+  }
+}
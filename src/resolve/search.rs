@@ -0,0 +1,183 @@
+//! Include search paths: where `include "path"` and `include "std:name"` are looked up.
+
+use super::glob;
+use crate::stdlib::PREFIX as LIBRARY_PREFIX;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Project config file looked up from the entry file's directory upwards.
+const CONFIG_FILE: &str = "branchy.json";
+/// Environment variable with extra search directories (platform path-list syntax).
+const PATH_ENV: &str = "BRANCHY_PATH";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchPath {
+  dirs: Vec<PathBuf>,
+}
+
+#[derive(serde::Deserialize)]
+struct Config {
+  #[serde(default)]
+  include_paths: Vec<PathBuf>,
+}
+
+impl SearchPath {
+  pub const fn new(dirs: Vec<PathBuf>) -> Self {
+    Self { dirs }
+  }
+
+  /// `cli_dirs` (from `-I`), then `include_paths` of the nearest `branchy.json` above
+  /// `entry_dir` (relative to that file), then `BRANCHY_PATH`.
+  pub fn discover(entry_dir: &Path, cli_dirs: &[PathBuf]) -> Result<Self, String> {
+    let mut dirs = cli_dirs.to_vec();
+    if let Some(config) = entry_dir
+      .ancestors()
+      .map(|d| d.join(CONFIG_FILE))
+      .find(|p| p.is_file())
+    {
+      let text = fs::read_to_string(&config).map_err(|e| format!("{}: {e}", config.display()))?;
+      let parsed: Config =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {e}", config.display()))?;
+      let root = config.parent().unwrap_or_else(|| Path::new("."));
+      dirs.extend(parsed.include_paths.into_iter().map(|d| root.join(d)));
+    }
+    if let Some(paths) = env::var_os(PATH_ENV) {
+      dirs.extend(env::split_paths(&paths).filter(|d| !d.as_os_str().is_empty()));
+    }
+    Ok(Self { dirs })
+  }
+
+  pub fn dirs(&self) -> &[PathBuf] {
+    &self.dirs
+  }
+
+  /// Locate an include. Plain paths are tried relative to `base` (the entry script's directory)
+  /// and then in each search directory; `std:name` only in the search directories, with
+  /// `.branchy` appended when `name` has no extension. The error lists every directory tried.
+  pub fn find(&self, base: &Path, include: &str) -> Result<PathBuf, String> {
+    let (rel, candidates) = match include.strip_prefix(LIBRARY_PREFIX) {
+      Some(name) => {
+        let mut rel = PathBuf::from(name);
+        if rel.extension().is_none() {
+          rel.set_extension("branchy");
+        }
+        (rel, self.dirs.clone())
+      }
+      None if Path::new(include).is_absolute() => {
+        let p = PathBuf::from(include);
+        return if p.is_file() {
+          Ok(p)
+        } else {
+          Err(format!("include \"{include}\": file not found"))
+        };
+      }
+      None => {
+        let mut dirs = vec![base.to_path_buf()];
+        dirs.extend(self.dirs.iter().cloned());
+        (PathBuf::from(include), dirs)
+      }
+    };
+    if let Some(found) = candidates
+      .iter()
+      .map(|d| d.join(&rel))
+      .find(|p| p.is_file())
+    {
+      return Ok(found);
+    }
+    let searched: Vec<_> = candidates.iter().map(|d| d.display().to_string()).collect();
+    if searched.is_empty() {
+      return Err(format!(
+        "include \"{include}\": not a standard library module and no library directories \
+         (use -I, {PATH_ENV} or {CONFIG_FILE})"
+      ));
+    }
+    Err(format!(
+      "include \"{include}\": file not found; searched: {}",
+      searched.join(", ")
+    ))
+  }
+
+  /// Includes matched by a glob pattern (`words/*.branchy`), sorted, as paths that
+  /// [`find`](Self::find) resolves to the matched files. Candidate directories are tried in
+  /// [`find`](Self::find) order and the first one with any match wins. For `std:` patterns
+  /// `.branchy` is appended when the last component has no extension, as `find` does. Every
+  /// directory listed is added to `scanned` (watch mode uses them to notice added and removed
+  /// files).
+  pub fn glob(&self, base: &Path, pattern: &str, scanned: &mut Vec<PathBuf>) -> Vec<String> {
+    let (prefix, rel, candidates) = match pattern.strip_prefix(LIBRARY_PREFIX) {
+      Some(name) => (LIBRARY_PREFIX, name, self.dirs.clone()),
+      None if Path::new(pattern).is_absolute() => (
+        "/",
+        pattern.trim_start_matches('/'),
+        vec![PathBuf::from("/")],
+      ),
+      None => {
+        let mut dirs = vec![base.to_path_buf()];
+        dirs.extend(self.dirs.iter().cloned());
+        ("", pattern, dirs)
+      }
+    };
+    let mut parts: Vec<String> = rel.split('/').map(str::to_string).collect();
+    if let Some(last) = parts.last_mut().filter(|_| prefix == LIBRARY_PREFIX) {
+      if !last.contains('.') {
+        last.push_str(".branchy");
+      }
+    }
+    for dir in candidates {
+      let mut found = Vec::new();
+      walk(&dir, &parts, prefix.to_string(), &mut found, scanned);
+      if !found.is_empty() {
+        found.sort();
+        return found;
+      }
+    }
+    Vec::new()
+  }
+
+  /// [`find`](Self::find) and read the file.
+  pub fn read(&self, base: &Path, include: &str) -> Result<String, String> {
+    let path = self.find(base, include)?;
+    fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))
+  }
+}
+
+/// Match `parts` (path components, possibly with wildcards) below `dir`, pushing the include
+/// path (`rel` plus the matched components) of every matching file.
+fn walk(
+  dir: &Path,
+  parts: &[String],
+  rel: String,
+  found: &mut Vec<String>,
+  scanned: &mut Vec<PathBuf>,
+) {
+  let Some((part, rest)) = parts.split_first() else {
+    if dir.is_file() {
+      found.push(rel);
+    }
+    return;
+  };
+  let join = |name: &str| {
+    if rel.is_empty() || rel.ends_with(['/', ':']) {
+      format!("{rel}{name}")
+    } else {
+      format!("{rel}/{name}")
+    }
+  };
+  if !glob::is_glob(part) {
+    walk(&dir.join(part), rest, join(part), found, scanned);
+    return;
+  }
+  scanned.push(dir.to_path_buf());
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+  let mut names: Vec<String> = entries
+    .filter_map(|e| e.ok()?.file_name().into_string().ok())
+    .filter(|n| !n.starts_with('.') && glob::matches(part, n))
+    .collect();
+  names.sort();
+  for name in names {
+    walk(&dir.join(&name), rest, join(&name), found, scanned);
+  }
+}
@@ -1,46 +1,128 @@
-//! Run, compile and fmt commands for CLI.
+//! Run, compile, fmt and check commands for CLI.
 
+use branchy::coverage::{Coverage, PointKind};
+use branchy::debugger::{Breakpoint, Debugger};
+use branchy::repl::{self, Outcome, Session};
+use branchy::watch::Watcher;
 use branchy::{
-  default_registry, deserialize_program, format_program, interpret, parse_program,
-  resolve_includes, serialize_program, FormatOptions,
+  default_registry, deserialize_program, format_program, interpret, interpret_profiled,
+  interpret_with_hook, parse_program, resolve_includes_with, serialize_program, FormatOptions,
+  FsResolver, Profile, Program, SearchPath, Span,
 };
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn run(path: &str, input: Option<&str>, seed: Option<u64>) -> Result<(), String> {
+/// Load a program from source (resolving includes with the `-I` directories `dirs` first on
+/// the search path) or from a compiled .branchyc. Returns the source text too when the file is
+/// not compiled.
+fn load_program(path: &str, dirs: &[PathBuf]) -> Result<(Program, Option<String>), String> {
+  load_program_with_deps(path, dirs, &mut Vec::new())
+}
+
+/// Like [`load_program`], also collecting the entry file, every file read by
+/// `resolve_includes` and every directory listed for a glob include into `deps`
+/// (even when loading fails part way).
+fn load_program_with_deps(
+  path: &str,
+  dirs: &[PathBuf],
+  deps: &mut Vec<PathBuf>,
+) -> Result<(Program, Option<String>), String> {
+  deps.push(PathBuf::from(path));
   let bytes = fs::read(path).map_err(|e| e.to_string())?;
-  let program = if path.ends_with(".branchyc") || (bytes.len() >= 4 && &bytes[0..4] == b"BRCH") {
-    deserialize_program(&bytes)?
-  } else {
-    let src = String::from_utf8(bytes).map_err(|e| e.to_string())?;
-    let p = parse_program(&src).map_err(|e| e.to_string())?;
-    let base = Path::new(path).parent().unwrap_or(Path::new("."));
-    resolve_includes(p, |pth| {
-      let full = base.join(pth);
-      fs::read_to_string(&full).map_err(|e| e.to_string())
-    })?
-  };
+  if path.ends_with(".branchyc") || (bytes.len() >= 4 && &bytes[0..4] == b"BRCH") {
+    return Ok((deserialize_program(&bytes)?, None));
+  }
+  let src = String::from_utf8(bytes).map_err(|e| e.to_string())?;
+  let p = parse_program(&src).map_err(|e| e.to_string())?;
+  let base = Path::new(path).parent().unwrap_or(Path::new("."));
+  let resolver = FsResolver::new(base, SearchPath::discover(base, dirs)?);
+  let program = resolve_includes_with(p, &resolver);
+  deps.extend(resolver.files());
+  Ok((program?, Some(src)))
+}
+
+/// Call `step` now and again whenever one of the files it reports changes.
+/// Errors are printed and do not stop watching; runs until interrupted.
+fn watch(mut step: impl FnMut(&mut Vec<PathBuf>) -> Result<(), String>) -> Result<(), String> {
+  loop {
+    let mut deps = Vec::new();
+    if let Err(e) = step(&mut deps) {
+      eprintln!("error: {e}");
+    }
+    let mut watcher = Watcher::new(deps);
+    eprintln!(
+      "[watching {} file(s), Ctrl+C to stop]",
+      watcher.files().len()
+    );
+    let changed: Vec<_> = watcher
+      .wait()
+      .iter()
+      .map(|p| p.display().to_string())
+      .collect();
+    eprintln!("\n[changed: {}]", changed.join(", "));
+  }
+}
+
+/// Run a program and print its output. With `profile`, a timing report goes to stderr.
+pub fn run(
+  path: &str,
+  input: Option<&str>,
+  seed: Option<u64>,
+  profile: bool,
+  dirs: &[PathBuf],
+) -> Result<(), String> {
+  run_with_deps(path, input, seed, profile, dirs, &mut Vec::new())
+}
+
+/// `run --watch`: re-run on every change to the file or its includes. Without `--seed` one
+/// random seed is picked and reused, so consecutive outputs differ only by the edit.
+pub fn run_watch(
+  path: &str,
+  input: Option<&str>,
+  seed: Option<u64>,
+  profile: bool,
+  dirs: &[PathBuf],
+) -> Result<(), String> {
+  let seed = seed.unwrap_or_else(rand::random::<u64>);
+  eprintln!("[seed {seed}]");
+  watch(|deps| run_with_deps(path, input, Some(seed), profile, dirs, deps))
+}
+
+fn run_with_deps(
+  path: &str,
+  input: Option<&str>,
+  seed: Option<u64>,
+  profile: bool,
+  dirs: &[PathBuf],
+  deps: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+  let (program, _) = load_program_with_deps(path, dirs, deps)?;
   let builtins = default_registry();
   let mut rng: StdRng = match seed {
     Some(s) => StdRng::seed_from_u64(s),
     None => StdRng::seed_from_u64(rand::random::<u64>()),
   };
-  let (result, _trace) =
-    interpret(&program, &builtins, &mut rng, input).map_err(|e| e.to_string())?;
+  let (result, _trace) = if profile {
+    let mut prof = Profile::new();
+    let out = interpret_profiled(&program, &builtins, &mut rng, input, &mut prof);
+    eprint!("{}", prof.report());
+    out
+  } else {
+    interpret(&program, &builtins, &mut rng, input)
+  }
+  .map_err(|e| e.to_string())?;
   println!("{}", result);
   Ok(())
 }
 
-pub fn compile(input: &str, output: &str) -> Result<(), String> {
+pub fn compile(input: &str, output: &str, dirs: &[PathBuf]) -> Result<(), String> {
   let src = fs::read_to_string(input).map_err(|e| e.to_string())?;
   let program = parse_program(&src).map_err(|e| e.to_string())?;
   let base = Path::new(input).parent().unwrap_or(Path::new("."));
-  let program = resolve_includes(program, |pth| {
-    let full = base.join(pth);
-    fs::read_to_string(&full).map_err(|e| e.to_string())
-  })?;
+  let search = SearchPath::discover(base, dirs)?;
+  let program = resolve_includes_with(program, &FsResolver::new(base, search))?;
   let bytes = serialize_program(&program)?;
   fs::write(output, bytes).map_err(|e| e.to_string())?;
   Ok(())
@@ -54,7 +136,9 @@ pub fn fmt(path: Option<&str>, write: bool, check: bool) -> Result<(), String> {
     None => {
       use std::io::Read;
       let mut s = String::new();
-      std::io::stdin().read_to_string(&mut s).map_err(|e| e.to_string())?;
+      std::io::stdin()
+        .read_to_string(&mut s)
+        .map_err(|e| e.to_string())?;
       s
     }
   };
@@ -63,7 +147,10 @@ pub fn fmt(path: Option<&str>, write: bool, check: bool) -> Result<(), String> {
   if check {
     if out != src {
       let name = path.unwrap_or("stdin");
-      return Err(format!("{} is not formatted (run branchy fmt -w to fix)", name));
+      return Err(format!(
+        "{} is not formatted (run branchy fmt -w to fix)",
+        name
+      ));
     }
     return Ok(());
   }
@@ -75,3 +162,190 @@ pub fn fmt(path: Option<&str>, write: bool, check: bool) -> Result<(), String> {
   }
   Ok(())
 }
+
+/// `fmt --watch`: re-format (or re-check) the file whenever it or one of its includes changes.
+pub fn fmt_watch(path: &str, check: bool, dirs: &[PathBuf]) -> Result<(), String> {
+  watch(|deps| {
+    // Only for the watch list; parse errors are reported by fmt itself.
+    let _ = load_program_with_deps(path, dirs, deps);
+    fmt(Some(path), false, check)?;
+    if check {
+      eprintln!("{path} is formatted");
+    }
+    Ok(())
+  })
+}
+
+/// Parse the program and resolve its includes without running it.
+pub fn check(path: &str, dirs: &[PathBuf]) -> Result<(), String> {
+  check_with_deps(path, dirs, &mut Vec::new())
+}
+
+/// `check --watch`: re-check on every change to the file or its includes.
+pub fn check_watch(path: &str, dirs: &[PathBuf]) -> Result<(), String> {
+  watch(|deps| check_with_deps(path, dirs, deps))
+}
+
+fn check_with_deps(path: &str, dirs: &[PathBuf], deps: &mut Vec<PathBuf>) -> Result<(), String> {
+  let (program, _) = load_program_with_deps(path, dirs, deps)?;
+  println!(
+    "{path}: ok ({} function(s), {} event(s))",
+    program.functions.len(),
+    program.events.len()
+  );
+  Ok(())
+}
+
+/// Interactive loop: read inputs (continuing lines while brackets are open) until :quit or EOF.
+pub fn repl(dirs: &[PathBuf]) -> Result<(), String> {
+  use std::io::Write;
+  let mut session = Session::with_search_path(SearchPath::discover(Path::new("."), dirs)?);
+  println!("branchy repl (:help for commands, :quit to exit)");
+  let mut buf = String::new();
+  loop {
+    print!(
+      "{}",
+      if buf.is_empty() {
+        "branchy> "
+      } else {
+        "     ... "
+      }
+    );
+    std::io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    if std::io::stdin()
+      .read_line(&mut line)
+      .map_err(|e| e.to_string())?
+      == 0
+    {
+      break;
+    }
+    buf.push_str(&line);
+    if repl::is_incomplete(&buf) {
+      continue;
+    }
+    match session.handle(&std::mem::take(&mut buf)) {
+      Ok(Outcome::Print(s)) => println!("{s}"),
+      Ok(Outcome::Silent) => {}
+      Ok(Outcome::Quit) => break,
+      Err(e) => eprintln!("error: {e}"),
+    }
+  }
+  Ok(())
+}
+
+/// Run a program under the step debugger, reading commands from stdin.
+pub fn debug(
+  path: &str,
+  input: Option<&str>,
+  seed: Option<u64>,
+  breakpoints: Vec<Breakpoint>,
+  dirs: &[PathBuf],
+) -> Result<(), String> {
+  let (program, src) = load_program(path, dirs)?;
+  let builtins = default_registry();
+  let mut rng = StdRng::seed_from_u64(seed.unwrap_or_else(rand::random::<u64>));
+  let commands = std::iter::from_fn(|| {
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+      Ok(0) | Err(_) => None,
+      Ok(_) => Some(line),
+    }
+  });
+  let mut debugger = Debugger::new(commands, std::io::stdout(), src, breakpoints);
+  println!("branchy debug (help for commands)");
+  let (result, _trace) = interpret_with_hook(&program, &builtins, &mut rng, input, &mut debugger)
+    .map_err(|e| e.to_string())?;
+  println!("{result}");
+  Ok(())
+}
+
+/// Output of `branchy coverage`.
+pub enum CoverageFormat {
+  Text,
+  Json,
+  Lcov,
+  Annotate,
+}
+
+/// Run the program `runs` times (seeds `seed..seed+runs`, cycling through `inputs`) and report
+/// which branch alternatives, inline options, functions and events were reached.
+pub fn coverage(
+  path: &str,
+  runs: u64,
+  seed: u64,
+  inputs: &[&str],
+  format: &CoverageFormat,
+  dirs: &[PathBuf],
+) -> Result<(), String> {
+  let (program, src) = load_program(path, dirs)?;
+  let builtins = default_registry();
+  let mut cov = Coverage::new(&program);
+  let mut cycle = inputs.iter().copied().cycle();
+  for i in 0..runs {
+    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(i));
+    let input = cycle.next();
+    match interpret(&program, &builtins, &mut rng, input) {
+      Ok((_, trace)) => cov.record(&trace),
+      Err(_) => cov.record_error(),
+    }
+  }
+  match format {
+    CoverageFormat::Json => {
+      let json = serde_json::to_string_pretty(&cov).map_err(|e| e.to_string())?;
+      println!("{json}");
+    }
+    CoverageFormat::Lcov => print!("{}", cov.to_lcov(path)),
+    CoverageFormat::Annotate => {
+      let src = src.ok_or("--annotate needs the .branchy source, not a compiled file")?;
+      print!("{}", cov.annotate(&src));
+    }
+    CoverageFormat::Text => print_coverage_text(&cov, src.as_deref()),
+  }
+  Ok(())
+}
+
+fn print_coverage_text(cov: &Coverage, src: Option<&str>) {
+  let (br_hit, br_total) = cov.branch_summary();
+  let (fn_hit, fn_total) = cov.function_summary();
+  println!("runs: {} ({} errors)", cov.runs, cov.errors);
+  println!("alternatives: {br_hit}/{br_total} chosen");
+  println!("functions: {fn_hit}/{fn_total} reached");
+  for p in cov.points.iter().filter(|p| p.kind == PointKind::Function) {
+    println!("  {:>8}  {}", p.hits, p.owner);
+  }
+  let never: Vec<_> = cov.never_chosen().collect();
+  if !never.is_empty() {
+    println!("never chosen:");
+    for p in never {
+      let snippet = src.map(|s| span_snippet(s, &p.span)).unwrap_or_default();
+      println!(
+        "  line {}, column {} ({}, {} of {}): {}",
+        p.span.start_line,
+        p.span.start_column,
+        p.owner,
+        p.index + 1,
+        p.of,
+        snippet
+      );
+    }
+  }
+  for p in cov
+    .points
+    .iter()
+    .filter(|p| p.kind == PointKind::Function && p.hits == 0)
+  {
+    println!("never called: {} (line {})", p.owner, p.span.start_line);
+  }
+}
+
+/// First line of the source text covered by `span`, shortened for one-line reports.
+fn span_snippet(src: &str, span: &Span) -> String {
+  let text = span.text(src).unwrap_or_default();
+  let text = text.lines().next().unwrap_or_default().trim();
+  if text.chars().count() > 60 {
+    format!("{}...", text.chars().take(57).collect::<String>())
+  } else {
+    text.to_string()
+  }
+}
@@ -0,0 +1,320 @@
+//! Optional API key authentication, with per-key quotas, rate limits and access to stored
+//! programs.
+//!
+//! Keys are listed in a local JSON file by their SHA-256 hash (`branchy-server --hash-key KEY`
+//! prints it), so the file holds no secrets. Clients send a key as `Authorization: Bearer KEY`
+//! or `X-Api-Key: KEY`. Without a keys file the API stays open.
+
+use super::handlers::{message_error, ApiError};
+use super::rate_limit::{take_token, with_retry_after, RateLimit};
+use super::AppState;
+use axum::async_trait;
+use axum::extract::{FromRequestParts, MatchedPath, Request, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Prefix of the key hashes in the keys file.
+pub const HASH_PREFIX: &str = "sha256:";
+
+/// Routes open without a key.
+const PUBLIC_ROUTES: &[&str] = &["/health", "/openapi.json"];
+
+/// The keys file: `{ "keys": [ ... ] }`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeysFile {
+  pub keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyConfig {
+  /// Who holds the key; used in errors and as the rate limit client.
+  pub id: String,
+  /// `sha256:` and the hex SHA-256 of the key.
+  pub hash: String,
+  /// Stored programs the key may use: names, or prefixes ending in `*` (`*` for all).
+  #[serde(default)]
+  pub programs: Vec<String>,
+  /// May read and run stored programs but not save or delete them.
+  #[serde(default)]
+  pub read_only: bool,
+  /// Rate limits replacing the server's on these routes.
+  #[serde(default)]
+  pub rate_limits: HashMap<String, RateLimit>,
+  #[serde(default)]
+  pub quota: Option<Quota>,
+}
+
+/// Requests a key may make per period (counted in memory, so a restart resets it).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+  pub requests: u64,
+  /// Length of the period in seconds (a day by default).
+  #[serde(default = "one_day")]
+  pub period_secs: u64,
+}
+
+const fn one_day() -> u64 {
+  24 * 60 * 60
+}
+
+/// Requests counted in the current quota period.
+struct Window {
+  start: Instant,
+  used: u64,
+}
+
+/// A configured key, as attached to the requests made with it.
+pub struct ApiKey {
+  pub config: KeyConfig,
+  window: Mutex<Window>,
+}
+
+impl ApiKey {
+  /// Whether the key may use the stored program `name`.
+  pub fn can_access(&self, name: &str) -> bool {
+    self.config.programs.iter().any(|p| {
+      p.strip_suffix('*')
+        .map_or_else(|| p == name, |prefix| name.starts_with(prefix))
+    })
+  }
+
+  /// Count a request against the quota; Err(the quota, time until the period ends) when it is
+  /// used up.
+  fn take_quota(&self, now: Instant) -> Result<(), (Quota, Duration)> {
+    let Some(quota) = self.config.quota else {
+      return Ok(());
+    };
+    let period = Duration::from_secs(quota.period_secs);
+    let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+    if now.duration_since(window.start) >= period {
+      *window = Window {
+        start: now,
+        used: 0,
+      };
+    }
+    if window.used >= quota.requests {
+      return Err((
+        quota,
+        period.saturating_sub(now.duration_since(window.start)),
+      ));
+    }
+    window.used += 1;
+    drop(window);
+    Ok(())
+  }
+}
+
+/// The configured keys by hash; `None` when authentication is off.
+#[derive(Default)]
+pub struct Auth {
+  keys: Option<HashMap<String, Arc<ApiKey>>>,
+}
+
+impl Auth {
+  /// Authentication off: every request is allowed.
+  pub fn open() -> Self {
+    Self::default()
+  }
+
+  /// Require one of `keys`. Fails on a malformed hash, a repeated id or hash, or a quota with a
+  /// zero period.
+  pub fn new(keys: Vec<KeyConfig>) -> Result<Self, String> {
+    let mut ids = HashSet::new();
+    let mut by_hash = HashMap::new();
+    for config in keys {
+      let hex = config
+        .hash
+        .strip_prefix(HASH_PREFIX)
+        .filter(|h| h.len() == 64 && h.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| {
+          format!(
+            "key \"{}\": hash must be \"{HASH_PREFIX}\" and 64 hex digits",
+            config.id
+          )
+        })?
+        .to_ascii_lowercase();
+      if config.quota.is_some_and(|q| q.period_secs == 0) {
+        return Err(format!(
+          "key \"{}\": quota period_secs must be at least 1",
+          config.id
+        ));
+      }
+      if !ids.insert(config.id.clone()) {
+        return Err(format!("key \"{}\" is listed twice", config.id));
+      }
+      let key = Arc::new(ApiKey {
+        config,
+        window: Mutex::new(Window {
+          start: Instant::now(),
+          used: 0,
+        }),
+      });
+      if let Some(other) = by_hash.insert(hex, key) {
+        return Err(format!(
+          "key \"{}\" has the hash of another key",
+          other.config.id
+        ));
+      }
+    }
+    Ok(Self {
+      keys: Some(by_hash),
+    })
+  }
+
+  /// Read a keys file.
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let file: KeysFile =
+      serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    Self::new(file.keys).map_err(|e| format!("{}: {e}", path.display()))
+  }
+
+  pub const fn enabled(&self) -> bool {
+    self.keys.is_some()
+  }
+
+  /// Number of configured keys (0 when authentication is off).
+  pub fn len(&self) -> usize {
+    self.keys.as_ref().map_or(0, HashMap::len)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn find(&self, key: &str) -> Option<Arc<ApiKey>> {
+    let hash = hash_key(key);
+    let hex = hash.strip_prefix(HASH_PREFIX)?;
+    self.keys.as_ref()?.get(hex).cloned()
+  }
+}
+
+/// `sha256:<hex>` of `key`, as written in the keys file.
+pub fn hash_key(key: &str) -> String {
+  let digest = Sha256::digest(key.as_bytes());
+  let mut out = String::from(HASH_PREFIX);
+  for byte in digest {
+    let _ = write!(out, "{byte:02x}");
+  }
+  out
+}
+
+/// Key of a request: `Authorization: Bearer KEY`, else `X-Api-Key`.
+fn presented(headers: &HeaderMap) -> Option<&str> {
+  let header = |name| {
+    headers
+      .get(name)
+      .and_then(|v: &HeaderValue| v.to_str().ok())
+  };
+  header(header::AUTHORIZATION.as_str())
+    .and_then(|v| v.strip_prefix("Bearer "))
+    .or_else(|| header("x-api-key"))
+    .map(str::trim)
+}
+
+fn unauthorized(error: &str) -> Response {
+  let mut response =
+    message_error(StatusCode::UNAUTHORIZED, error.to_string(), None).into_response();
+  response
+    .headers_mut()
+    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+  response
+}
+
+/// API key of the request; None when authentication is off.
+pub struct Caller(pub Option<Arc<ApiKey>>);
+
+#[async_trait]
+impl<S: Sync> FromRequestParts<S> for Caller {
+  type Rejection = Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+    Ok(Self(parts.extensions.get::<Arc<ApiKey>>().cloned()))
+  }
+}
+
+impl Caller {
+  /// 403 unless the caller may use the stored program `name`; `write` for saving and deleting.
+  pub(super) fn check_program(&self, name: &str, write: bool) -> Result<(), ApiError> {
+    let Some(key) = &self.0 else {
+      return Ok(());
+    };
+    let forbidden = |error| Err(message_error(StatusCode::FORBIDDEN, error, None));
+    if !key.can_access(name) {
+      return forbidden(format!(
+        "API key \"{}\" has no access to program \"{name}\"",
+        key.config.id
+      ));
+    }
+    if write && key.config.read_only {
+      return forbidden(format!(
+        "API key \"{}\" may not change stored programs",
+        key.config.id
+      ));
+    }
+    Ok(())
+  }
+
+  /// Whether the caller may see the stored program `name` (in `/programs`).
+  pub fn can_access(&self, name: &str) -> bool {
+    self.0.as_ref().is_none_or(|key| key.can_access(name))
+  }
+}
+
+/// Middleware: when keys are configured, require one (401) on all but the public routes,
+/// enforce its quota (429) and attach it to the request as `Arc<ApiKey>`. Rejected requests
+/// count against the client's IP in the rate limits, as requests without a key would.
+pub(super) async fn authenticate(
+  State(state): State<AppState>,
+  mut req: Request,
+  next: Next,
+) -> Response {
+  let auth = &state.auth;
+  if !auth.enabled() {
+    return next.run(req).await;
+  }
+  let public = req
+    .extensions()
+    .get::<MatchedPath>()
+    .is_some_and(|p| PUBLIC_ROUTES.contains(&p.as_str()));
+  if public {
+    return next.run(req).await;
+  }
+  let presented = presented(req.headers());
+  let Some(key) = presented.and_then(|k| auth.find(k)) else {
+    if let Some(response) = take_token(&state, &req) {
+      return response;
+    }
+    return unauthorized(if presented.is_some() {
+      "invalid API key"
+    } else {
+      "API key required: send it as \"Authorization: Bearer KEY\""
+    });
+  };
+  if let Err((quota, wait)) = key.take_quota(Instant::now()) {
+    let error = message_error(
+      StatusCode::TOO_MANY_REQUESTS,
+      format!(
+        "quota of {} requests per {} s exceeded for API key \"{}\"",
+        quota.requests, quota.period_secs, key.config.id
+      ),
+      None,
+    );
+    return with_retry_after(error.into_response(), wait);
+  }
+  req.extensions_mut().insert(key);
+  next.run(req).await
+}
@@ -0,0 +1,198 @@
+//! Cache of parsed and resolved programs keyed by a SHA-256 of their sources, so re-running the
+//! same program (e.g. re-rolling with another seed) skips parsing.
+//!
+//! Least recently used entries are evicted when either the entry count or the approximate memory
+//! size goes over its limit. The size of an entry is an estimate of the parsed tree, taken from
+//! the size of its sources.
+
+use super::types::SourceFiles;
+use crate::Program;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use utoipa::ToSchema;
+
+/// Parsed tree size estimate, as a multiple of the source size.
+const TREE_FACTOR: usize = 4;
+
+/// SHA-256 of a program's sources.
+type Key = [u8; 32];
+
+struct Slot {
+  program: Arc<Program>,
+  bytes: usize,
+  /// Value of `Entries::clock` when last used.
+  used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+  slots: HashMap<Key, Slot>,
+  /// Keys by `Slot::used`, least recently used first.
+  order: BTreeMap<u64, Key>,
+  bytes: usize,
+  clock: u64,
+}
+
+impl Entries {
+  const fn tick(&mut self) -> u64 {
+    self.clock += 1;
+    self.clock
+  }
+}
+
+pub struct ParseCache {
+  entries: Mutex<Entries>,
+  max_entries: usize,
+  max_bytes: usize,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct CacheStats {
+  pub entries: usize,
+  pub bytes: usize,
+  pub max_entries: usize,
+  pub max_bytes: usize,
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl Default for ParseCache {
+  /// 256 programs, 64 MiB.
+  fn default() -> Self {
+    Self::new(256, 64 << 20)
+  }
+}
+
+impl ParseCache {
+  pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+    Self {
+      entries: Mutex::new(Entries::default()),
+      max_entries,
+      max_bytes,
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+
+  /// The cached program for `files`, or the result of `load` (cached when it succeeds).
+  /// `load` runs without the cache locked.
+  pub fn get_or_load<E>(
+    &self,
+    files: &SourceFiles,
+    load: impl FnOnce(&SourceFiles) -> Result<Program, E>,
+  ) -> Result<Arc<Program>, E> {
+    let key = digest(files);
+    if let Some(program) = self.lookup(&key) {
+      self.hits.fetch_add(1, Ordering::Relaxed);
+      return Ok(program);
+    }
+    self.misses.fetch_add(1, Ordering::Relaxed);
+    let program = Arc::new(load(files)?);
+    let bytes = weight(files);
+    if bytes <= self.max_bytes && self.max_entries > 0 {
+      let mut entries = self.lock();
+      let used = entries.tick();
+      let slot = Slot {
+        program: Arc::clone(&program),
+        bytes,
+        used,
+      };
+      if let Some(old) = entries.slots.insert(key, slot) {
+        entries.order.remove(&old.used);
+        entries.bytes -= old.bytes;
+      }
+      entries.order.insert(used, key);
+      entries.bytes += bytes;
+      self.evict(&mut entries);
+      drop(entries);
+    }
+    Ok(program)
+  }
+
+  /// The cached program for `files`, without loading or counting a miss. For sources that are
+  /// likely not worth keeping, such as half-typed ones in the editor.
+  pub fn get(&self, files: &SourceFiles) -> Option<Arc<Program>> {
+    self.lookup(&digest(files))
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    let entries = self.lock();
+    CacheStats {
+      entries: entries.slots.len(),
+      bytes: entries.bytes,
+      max_entries: self.max_entries,
+      max_bytes: self.max_bytes,
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    }
+  }
+
+  /// The program for `key`, marked as just used.
+  fn lookup(&self, key: &Key) -> Option<Arc<Program>> {
+    let mut entries = self.lock();
+    let now = entries.tick();
+    let slot = entries.slots.get_mut(key)?;
+    let used = std::mem::replace(&mut slot.used, now);
+    let program = Arc::clone(&slot.program);
+    entries.order.remove(&used);
+    entries.order.insert(now, *key);
+    drop(entries);
+    Some(program)
+  }
+
+  /// Drop least recently used entries until both limits hold.
+  fn evict(&self, entries: &mut Entries) {
+    while entries.slots.len() > self.max_entries || entries.bytes > self.max_bytes {
+      let Some((_, oldest)) = entries.order.pop_first() else {
+        break;
+      };
+      if let Some(slot) = entries.slots.remove(&oldest) {
+        entries.bytes -= slot.bytes;
+      }
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, Entries> {
+    self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+/// SHA-256 of `files`. Every part is prefixed with its length, so different splits of the same
+/// text get different keys.
+fn digest(files: &SourceFiles) -> Key {
+  let mut hash = Sha256::new();
+  let mut part = |text: &str| {
+    hash.update((text.len() as u64).to_le_bytes());
+    hash.update(text.as_bytes());
+  };
+  part(&files.source);
+  match &files.entry {
+    Some(entry) => {
+      part("entry");
+      part(entry);
+    }
+    None => part("source"),
+  }
+  for (path, source) in &files.files {
+    part(path);
+    part(source);
+  }
+  hash.finalize().into()
+}
+
+/// Approximate memory size of the cached program loaded from `files`.
+fn weight(files: &SourceFiles) -> usize {
+  let sources = files.source.len()
+    + files.entry.as_ref().map_or(0, String::len)
+    + files
+      .files
+      .iter()
+      .map(|(path, source)| path.len() + source.len())
+      .sum::<usize>();
+  sources * TREE_FACTOR
+}
@@ -0,0 +1,264 @@
+//! Server configuration: a JSON file, overridden by environment variables, overridden by
+//! command-line flags.
+
+use super::{Limits, RateLimits};
+use axum::http::HeaderValue;
+use serde::{Deserialize, Deserializer};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Config file read when no `--config` / `BRANCHY_SERVER_CONFIG` is given (if it exists).
+pub const DEFAULT_CONFIG_FILE: &str = "branchy-server.json";
+
+pub const USAGE: &str = "\
+Usage: branchy-server [--config FILE] [--bind ADDR] [--examples DIR] [--store DIR]
+                      [--shares DIR] [--cors-origin ORIGIN]... [--keys FILE] [--log FILTER]
+       branchy-server --hash-key KEY    print the hash of KEY for the keys file
+Environment: BRANCHY_SERVER_CONFIG, BRANCHY_BIND, BRANCHY_EXAMPLES, BRANCHY_STORE,
+             BRANCHY_SHARES, BRANCHY_CORS_ORIGINS (comma-separated), BRANCHY_KEYS, BRANCHY_TRUST_PROXY,
+             BRANCHY_LOG / RUST_LOG
+";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+  pub bind: SocketAddr,
+  /// Directory of `/examples` (`*.branchy`), read once at startup.
+  pub examples_dir: PathBuf,
+  /// Directory of named programs (`/programs`).
+  pub store_dir: PathBuf,
+  /// Directory of shared runs (`/share`).
+  pub share_dir: PathBuf,
+  /// Most runs kept in `share_dir`; `POST /share` fails with 507 past it.
+  pub max_shares: usize,
+  /// Origins allowed by CORS; `*` allows any.
+  pub cors_origins: Vec<String>,
+  /// API keys file (see [`KeysFile`](super::KeysFile)); without one the API is open.
+  pub keys_file: Option<PathBuf>,
+  /// Log filter (`tracing` env-filter syntax, e.g. `info` or `branchy=debug`).
+  pub log: String,
+  pub limits: LimitsConfig,
+  pub rate_limits: RateLimits,
+  pub cache: CacheConfig,
+}
+
+/// [`Limits`] with the timeouts in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+  pub body_bytes: usize,
+  #[serde(deserialize_with = "millis")]
+  pub timeout_ms: Duration,
+  pub output_bytes: usize,
+  pub batch_runs: usize,
+  pub stream_runs: usize,
+  #[serde(deserialize_with = "millis")]
+  pub stream_timeout_ms: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+  pub max_entries: usize,
+  pub max_bytes: usize,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    Self {
+      bind: SocketAddr::from(([0, 0, 0, 0], 3000)),
+      examples_dir: PathBuf::from("examples"),
+      store_dir: PathBuf::from("data/programs"),
+      share_dir: PathBuf::from("data/shares"),
+      max_shares: super::DEFAULT_MAX_SHARES,
+      cors_origins: vec!["*".to_string()],
+      keys_file: None,
+      log: "info".to_string(),
+      limits: LimitsConfig::default(),
+      rate_limits: RateLimits::default(),
+      cache: CacheConfig::default(),
+    }
+  }
+}
+
+impl Default for LimitsConfig {
+  fn default() -> Self {
+    let limits = Limits::default();
+    Self {
+      body_bytes: limits.body_bytes,
+      timeout_ms: limits.timeout,
+      output_bytes: limits.output_bytes,
+      batch_runs: limits.batch_runs,
+      stream_runs: limits.stream_runs,
+      stream_timeout_ms: limits.stream_timeout,
+    }
+  }
+}
+
+impl From<LimitsConfig> for Limits {
+  fn from(c: LimitsConfig) -> Self {
+    Self {
+      body_bytes: c.body_bytes,
+      timeout: c.timeout_ms,
+      output_bytes: c.output_bytes,
+      batch_runs: c.batch_runs,
+      stream_runs: c.stream_runs,
+      stream_timeout: c.stream_timeout_ms,
+    }
+  }
+}
+
+impl Default for CacheConfig {
+  /// Same as [`ParseCache::default`](super::ParseCache).
+  fn default() -> Self {
+    Self {
+      max_entries: 256,
+      max_bytes: 64 << 20,
+    }
+  }
+}
+
+fn millis<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+  u64::deserialize(d).map(Duration::from_millis)
+}
+
+impl ServerConfig {
+  /// Load the config file (from `--config`, `BRANCHY_SERVER_CONFIG` or [`DEFAULT_CONFIG_FILE`]
+  /// if present), then apply environment variables (read through `env`) and `args` (without
+  /// the program name). Later sources override earlier ones field by field.
+  pub fn load(args: &[String], env: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+    let flags = Flags::parse(args)?;
+    let file = flags
+      .config
+      .clone()
+      .or_else(|| env("BRANCHY_SERVER_CONFIG").map(PathBuf::from));
+    let mut config = match file {
+      Some(path) => Self::read(&path)?,
+      None if std::path::Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+        Self::read(DEFAULT_CONFIG_FILE.as_ref())?
+      }
+      None => Self::default(),
+    };
+    config.apply_env(&env)?;
+    config.apply_flags(flags)?;
+    if let Some(bad) = config
+      .cors_origins
+      .iter()
+      .find(|o| *o != "*" && o.parse::<HeaderValue>().is_err())
+    {
+      return Err(format!("invalid CORS origin \"{bad}\""));
+    }
+    Ok(config)
+  }
+
+  fn read(path: &std::path::Path) -> Result<Self, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+  }
+
+  fn apply_env(&mut self, env: &impl Fn(&str) -> Option<String>) -> Result<(), String> {
+    if let Some(bind) = env("BRANCHY_BIND") {
+      self.bind = parse_bind(&bind).map_err(|e| format!("BRANCHY_BIND: {e}"))?;
+    }
+    if let Some(dir) = env("BRANCHY_EXAMPLES") {
+      self.examples_dir = dir.into();
+    }
+    if let Some(dir) = env("BRANCHY_STORE") {
+      self.store_dir = dir.into();
+    }
+    if let Some(dir) = env("BRANCHY_SHARES") {
+      self.share_dir = dir.into();
+    }
+    if let Some(origins) = env("BRANCHY_CORS_ORIGINS") {
+      self.cors_origins = origins
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .map(str::to_string)
+        .collect();
+    }
+    if let Some(file) = env("BRANCHY_KEYS") {
+      self.keys_file = Some(file.into());
+    }
+    if let Some(v) = env("BRANCHY_TRUST_PROXY") {
+      self.rate_limits.trust_proxy = v == "1" || v == "true";
+    }
+    if let Some(log) = env("BRANCHY_LOG").or_else(|| env("RUST_LOG")) {
+      self.log = log;
+    }
+    Ok(())
+  }
+
+  fn apply_flags(&mut self, flags: Flags) -> Result<(), String> {
+    if let Some(bind) = flags.bind {
+      self.bind = parse_bind(&bind).map_err(|e| format!("--bind: {e}"))?;
+    }
+    if let Some(dir) = flags.examples {
+      self.examples_dir = dir;
+    }
+    if let Some(dir) = flags.store {
+      self.store_dir = dir;
+    }
+    if let Some(dir) = flags.shares {
+      self.share_dir = dir;
+    }
+    if !flags.cors_origins.is_empty() {
+      self.cors_origins = flags.cors_origins;
+    }
+    if let Some(file) = flags.keys {
+      self.keys_file = Some(file);
+    }
+    if let Some(log) = flags.log {
+      self.log = log;
+    }
+    Ok(())
+  }
+}
+
+/// `host:port`, or just a port (all interfaces).
+fn parse_bind(s: &str) -> Result<SocketAddr, String> {
+  if let Ok(port) = s.parse::<u16>() {
+    return Ok(SocketAddr::from(([0, 0, 0, 0], port)));
+  }
+  s.parse()
+    .map_err(|_| format!("invalid address \"{s}\" (use host:port or a port)"))
+}
+
+#[derive(Default)]
+struct Flags {
+  config: Option<PathBuf>,
+  bind: Option<String>,
+  examples: Option<PathBuf>,
+  store: Option<PathBuf>,
+  shares: Option<PathBuf>,
+  cors_origins: Vec<String>,
+  keys: Option<PathBuf>,
+  log: Option<String>,
+}
+
+impl Flags {
+  fn parse(args: &[String]) -> Result<Self, String> {
+    let mut flags = Self::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+      let mut value = || {
+        it.next()
+          .cloned()
+          .ok_or_else(|| format!("{arg} requires a value"))
+      };
+      match arg.as_str() {
+        "--config" => flags.config = Some(value()?.into()),
+        "--bind" => flags.bind = Some(value()?),
+        "--examples" => flags.examples = Some(value()?.into()),
+        "--store" => flags.store = Some(value()?.into()),
+        "--shares" => flags.shares = Some(value()?.into()),
+        "--cors-origin" => flags.cors_origins.push(value()?),
+        "--keys" => flags.keys = Some(value()?.into()),
+        "--log" => flags.log = Some(value()?),
+        other => return Err(format!("unexpected argument: {other}\n{USAGE}")),
+      }
+    }
+    Ok(flags)
+  }
+}
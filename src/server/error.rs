@@ -1,12 +1,16 @@
 //! Error response for API.
 
-use crate::ast::SourceError;
+use crate::ast::{SourceError, Span};
 use axum::{http::StatusCode, Json};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone, ToSchema)]
 pub struct ErrorResponse {
   pub error: String,
+  /// File of `files` the error occurred in (omitted for single-source requests).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub line: Option<u32>,
   #[serde(skip_serializing_if = "Option::is_none")]
@@ -17,6 +21,37 @@ pub struct ErrorResponse {
   pub end_column: Option<u32>,
 }
 
+/// Schema of an [`ErrorResponse`] flattened into an item that may have no error: the same
+/// fields, none of them required.
+pub fn optional_error_schema() -> utoipa::openapi::Schema {
+  let utoipa::openapi::RefOr::T(utoipa::openapi::Schema::Object(mut object)) =
+    <ErrorResponse as utoipa::PartialSchema>::schema()
+  else {
+    unreachable!("ErrorResponse has an object schema")
+  };
+  object.required.clear();
+  object.into()
+}
+
+/// The [`SourceError`] an [`ErrorResponse`] was made from (see [`error_response`]).
+pub fn source_error(e: ErrorResponse) -> SourceError {
+  let span = match (e.line, e.column, e.end_line, e.end_column) {
+    (Some(start_line), Some(start_column), Some(end_line), Some(end_column)) => Some(Span {
+      start_line,
+      start_column,
+      end_line,
+      end_column,
+      file: 0,
+    }),
+    _ => None,
+  };
+  SourceError {
+    message: e.error,
+    span,
+    file: e.file,
+  }
+}
+
 pub fn error_response(e: SourceError) -> (StatusCode, Json<ErrorResponse>) {
   let (line, column, end_line, end_column) = e
     .span
@@ -34,6 +69,7 @@ pub fn error_response(e: SourceError) -> (StatusCode, Json<ErrorResponse>) {
     StatusCode::BAD_REQUEST,
     Json(ErrorResponse {
       error: e.message,
+      file: e.file,
       line,
       column,
       end_line,
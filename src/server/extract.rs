@@ -0,0 +1,36 @@
+//! JSON request body extractor whose rejections use the API's error shape.
+
+use super::handlers::{message_error, ApiError};
+use super::AppState;
+use axum::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, Request};
+use axum::http::StatusCode;
+use axum::Json;
+
+/// Like [`Json`], but a bad body is answered with an `ErrorResponse`: 413 when it is over
+/// `limits.body_bytes`, 415 without a JSON content type, 400/422 when it does not parse.
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T> FromRequest<AppState> for ApiJson<T>
+where
+  Json<T>: FromRequest<AppState, Rejection = JsonRejection>,
+{
+  type Rejection = ApiError;
+
+  async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+    match Json::<T>::from_request(req, state).await {
+      Ok(Json(value)) => Ok(Self(value)),
+      Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => Err(message_error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!(
+          "request body exceeds the limit of {} bytes",
+          state.limits.body_bytes
+        ),
+        None,
+      )),
+      Err(e) => Err(message_error(e.status(), e.body_text(), None)),
+    }
+  }
+}
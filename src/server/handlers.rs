@@ -1,22 +1,53 @@
-//! API handlers: examples, health, run, format.
+//! API handlers: examples, stdlib, health, run, batch run, format, check, analysis, completion
+//! and hover, cache stats.
 
+use crate::analysis::{self, Analysis};
 use crate::ast::SourceError;
-use crate::{format_program, interpret, parse_program, FormatOptions};
+use crate::editor::{self, Completions, Hover, Prefix};
+use crate::interpreter::interpret_with;
+use crate::resolve::normalize;
+use crate::{
+  format_program, parse_program, resolve_includes_detailed, stdlib, EvalHook, FormatOptions,
+  MemoryResolver, Profile, Program, SourceResolver,
+};
 use axum::{extract::State, Json};
 use rand::rngs::StdRng;
 use rand::SeedableRng;
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
 
-use super::error::{error_response, ErrorResponse};
-use super::types::{ExampleItem, FormatRequest, FormatResponse, RunRequest, RunResponse};
+use super::cache::CacheStats;
+use super::error::{error_response, source_error, ErrorResponse};
+use super::extract::ApiJson;
+use super::limits::{blocking, Deadline, Guard, Stop};
+use super::metrics::RunOutcome;
+use super::types::{
+  AnalyzeRequest, BatchItem, BatchRequest, CheckRequest, CheckResponse, CursorRequest, ExampleItem,
+  FormatRequest, FormatResponse, RunOptions, RunRequest, RunResponse, SourceFiles, StdlibModule,
+};
 use super::AppState;
 use axum::http::StatusCode;
 
-pub async fn examples() -> Json<Vec<ExampleItem>> {
-  let base = std::env::current_dir().unwrap_or_else(|_| Path::new(".").into());
-  let examples_dir = base.join("examples");
+#[utoipa::path(
+  get,
+  path = "/examples",
+  tag = "source",
+  responses(
+    (status = 200, body = [ExampleItem]),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn examples(State(state): State<AppState>) -> Json<Vec<ExampleItem>> {
+  Json(state.examples.as_ref().clone())
+}
+
+/// Every `*.branchy` file of `examples_dir`, sorted by name (none if it cannot be read).
+pub fn load_examples(examples_dir: &Path) -> Vec<ExampleItem> {
   let mut out = Vec::new();
-  if let Ok(rd) = std::fs::read_dir(&examples_dir) {
+  if let Ok(rd) = std::fs::read_dir(examples_dir) {
     let mut names: Vec<String> = rd
       .filter_map(|e| e.ok())
       .map(|e| e.path())
@@ -36,53 +67,561 @@ pub async fn examples() -> Json<Vec<ExampleItem>> {
       }
     }
   }
-  Json(out)
+  out
 }
 
+/// Embedded standard library modules (includable as `std:<name>` in /run).
+#[utoipa::path(
+  get,
+  path = "/stdlib",
+  tag = "source",
+  responses(
+    (status = 200, body = [StdlibModule]),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn stdlib_modules() -> Json<Vec<StdlibModule>> {
+  Json(
+    stdlib::MODULES
+      .iter()
+      .map(|(name, source)| StdlibModule {
+        include: format!("{}{name}", stdlib::PREFIX),
+        source: (*source).to_string(),
+      })
+      .collect(),
+  )
+}
+
+#[utoipa::path(
+  get,
+  path = "/health",
+  tag = "server",
+  responses(
+    (status = 200, description = "Server is up", body = String, content_type = "text/plain"),
+  )
+)]
 pub async fn health() -> &'static str {
   "ok"
 }
 
+/// Parse cache size and hit/miss counts.
+#[utoipa::path(
+  get,
+  path = "/cache",
+  tag = "server",
+  responses(
+    (status = 200, body = CacheStats),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn cache_stats(State(state): State<AppState>) -> Json<CacheStats> {
+  Json(state.cache.stats())
+}
+
+/// Error response of a handler: status and JSON body.
+pub(super) type ApiError = (StatusCode, Json<ErrorResponse>);
+
+pub(super) const fn message_error(
+  status: StatusCode,
+  error: String,
+  file: Option<String>,
+) -> ApiError {
+  (
+    status,
+    Json(ErrorResponse {
+      error,
+      file,
+      line: None,
+      column: None,
+      end_line: None,
+      end_column: None,
+    }),
+  )
+}
+
+/// Entry path and source of a request: the `entry` file of `files`, or `source`.
+fn entry_source(body: &SourceFiles) -> Result<(Option<String>, &str), ApiError> {
+  let Some(entry) = &body.entry else {
+    return Ok((None, &body.source));
+  };
+  let path = normalize(entry);
+  let source = body
+    .files
+    .get(entry)
+    .or_else(|| body.files.get(&path))
+    .ok_or_else(|| {
+      message_error(
+        StatusCode::BAD_REQUEST,
+        format!("entry \"{entry}\" is not in files"),
+        None,
+      )
+    })?;
+  Ok((Some(path), source))
+}
+
+/// Resolver over the request's `files` that remembers which files were read, so a resolve error
+/// can be traced back to the file that failed to parse.
+struct RequestFiles {
+  files: MemoryResolver,
+}
+
+impl SourceResolver for RequestFiles {
+  fn canonicalize(&self, include: &str) -> Result<String, String> {
+    if self.files.paths().next().is_none() {
+      return Err(format!(
+        "include \"{include}\": no files in request; send them in \"files\" \
+         (std: modules are available, see /stdlib)"
+      ));
+    }
+    self.files.canonicalize(include)
+  }
+
+  fn read(&self, path: &str) -> Result<String, String> {
+    self.files.read(path)
+  }
+
+  fn glob(&self, pattern: &str) -> Result<Vec<String>, String> {
+    self.files.glob(pattern)
+  }
+}
+
+/// Parse the request's entry source and resolve its includes against `files`. Parse errors
+/// carry the file they occurred in and its line/column.
+pub(super) fn load_program(body: &SourceFiles) -> Result<Program, ApiError> {
+  let (entry, source) = entry_source(body)?;
+  let program = parse_program(source).map_err(|e| error_response(e.in_file(entry.as_deref())))?;
+  let resolver = RequestFiles {
+    files: body.files.iter().collect(),
+  };
+  resolve_includes_detailed(program, &resolver).map_err(|e| match e.parse {
+    Some(parse) => error_response(parse),
+    None => message_error(StatusCode::BAD_REQUEST, e.message, None),
+  })
+}
+
+/// [`load_program`] through the parse cache; load errors are counted in the metrics.
+pub(super) fn load_cached(state: &AppState, body: &SourceFiles) -> Result<Arc<Program>, ApiError> {
+  state
+    .cache
+    .get_or_load(body, load_program)
+    .inspect_err(|e| state.metrics.parse_error(e))
+}
+
+/// Program for the editor requests: from the parse cache if it is there, otherwise loaded
+/// without caching, so half-typed sources don't push out programs that are run. Load errors are
+/// not counted either.
+fn editor_program(state: &AppState, body: &SourceFiles) -> Option<Arc<Program>> {
+  state
+    .cache
+    .get(body)
+    .or_else(|| load_program(body).ok().map(Arc::new))
+}
+
+#[utoipa::path(
+  post,
+  path = "/run",
+  tag = "run",
+  request_body = RunRequest,
+  responses(
+    (status = 200, body = RunResponse),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 408, description = "Run timed out", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 422, description = "Runtime error or output too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
 pub async fn run(
   State(state): State<AppState>,
-  Json(body): Json<RunRequest>,
-) -> Result<Json<RunResponse>, (StatusCode, Json<ErrorResponse>)> {
-  let program = parse_program(&body.source).map_err(error_response)?;
-  if !program.includes.is_empty() {
-    return Err((
+  ApiJson(body): ApiJson<RunRequest>,
+) -> Result<Json<RunResponse>, ApiError> {
+  let program = load_cached(&state, &body.program)?;
+  let entry = body.program.entry.as_deref().map(normalize);
+  let permit = state.throttle.run_permit()?;
+  blocking(state.limits, permit, move |deadline| {
+    execute(
+      &state,
+      &program,
+      entry.as_deref(),
+      &body.options,
+      deadline,
+      None,
+    )
+  })
+  .await
+  .map(Json)
+}
+
+/// Run one program many times.
+///
+/// A failing run is reported in its item and does not stop the batch; load errors and bad batch
+/// sizes fail the whole request. On timeout the finished runs are returned and the rest are
+/// reported as timed out.
+#[utoipa::path(
+  post,
+  path = "/run/batch",
+  tag = "run",
+  request_body = BatchRequest,
+  responses(
+    (status = 200, description = "One item per run", body = [BatchItem]),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn run_batch(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<BatchRequest>,
+) -> Result<Json<Vec<BatchItem>>, ApiError> {
+  let mut runs = batch_runs(&body, state.limits.batch_runs)?.ok_or_else(|| {
+    message_error(
       StatusCode::BAD_REQUEST,
-      Json(ErrorResponse {
-        error: "includes are not supported in API; send merged source".into(),
-        line: None,
-        column: None,
-        end_line: None,
-        end_column: None,
-      }),
-    ));
+      "send either \"count\" or \"items\"".to_string(),
+      None,
+    )
+  })?;
+  // Seeds are fixed up front, so runs cut off by the timeout still report theirs.
+  for options in &mut runs {
+    options.seed.get_or_insert_with(rand::random::<u64>);
   }
-  let seed = body.seed.unwrap_or_else(rand::random::<u64>);
+  let program = load_cached(&state, &body.program)?;
+  let entry = body.program.entry.as_deref().map(normalize);
+  let finished = Arc::new(Mutex::new(Vec::with_capacity(runs.len())));
+  let task = {
+    let (state, finished, runs) = (state.clone(), Arc::clone(&finished), runs.clone());
+    move |deadline: &Deadline| {
+      for options in runs {
+        if deadline.expired() {
+          break;
+        }
+        let item = run_item(&state, &program, entry.as_deref(), options, deadline, None);
+        lock(&finished).push(item);
+      }
+      Ok(())
+    }
+  };
+  match blocking(state.limits, state.throttle.run_permit()?, task).await {
+    Err((status, _)) if status == StatusCode::REQUEST_TIMEOUT => {}
+    other => other?,
+  }
+  let finished = std::mem::take(&mut *lock(&finished));
+  let (_, Json(timed_out)) = state.limits.timeout_error();
+  let rest = runs[finished.len()..].iter().map(|options| BatchItem {
+    seed: options.seed.unwrap_or_default(),
+    result: None,
+    error: Some(timed_out.clone()),
+  });
+  let mut results = HashSet::new();
+  let items = finished.into_iter().chain(rest).filter(|item| {
+    !body.unique
+      || item
+        .result
+        .as_ref()
+        .is_none_or(|r| results.insert(r.clone()))
+  });
+  Ok(Json(items.collect()))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Options of every run a batch asks for: `count` copies of the shared options (with
+/// consecutive seeds) or `items`; None when neither is given. Fails when both are given or there
+/// are more than `limit` runs.
+pub(super) fn batch_runs(
+  body: &BatchRequest,
+  limit: usize,
+) -> Result<Option<Vec<RunOptions>>, ApiError> {
+  let bad_request = |error: String| message_error(StatusCode::BAD_REQUEST, error, None);
+  let runs = match (body.count, &body.items) {
+    (None, None) => return Ok(None),
+    (Some(count), None) => {
+      if count > limit {
+        return Err(bad_request(format!(
+          "batch of {count} runs exceeds the limit of {limit}"
+        )));
+      }
+      (0..count)
+        .map(|i| RunOptions {
+          seed: body.options.seed.map(|s| s.wrapping_add(i as u64)),
+          ..body.options.clone()
+        })
+        .collect()
+    }
+    (None, Some(items)) => items.clone(),
+    (Some(_), Some(_)) => {
+      return Err(bad_request(
+        "send either \"count\" or \"items\"".to_string(),
+      ))
+    }
+  };
+  if runs.len() > limit {
+    return Err(bad_request(format!(
+      "batch of {} runs exceeds the limit of {limit}",
+      runs.len()
+    )));
+  }
+  Ok(Some(runs))
+}
+
+/// One run of a batch, with its seed fixed up front so the item reports it.
+pub(super) fn run_item(
+  state: &AppState,
+  program: &Program,
+  entry: Option<&str>,
+  options: RunOptions,
+  deadline: &Deadline,
+  hook: Option<&mut dyn EvalHook>,
+) -> BatchItem {
+  let seed = options.seed.unwrap_or_else(rand::random::<u64>);
+  let options = RunOptions {
+    seed: Some(seed),
+    profile: false,
+    ..options
+  };
+  match execute(state, program, entry, &options, deadline, hook) {
+    Ok(response) => BatchItem {
+      seed,
+      result: Some(response.result),
+      error: None,
+    },
+    Err((_, Json(error))) => BatchItem {
+      seed,
+      result: None,
+      error: Some(error),
+    },
+  }
+}
+
+/// Run a loaded program; runtime errors outside included files are attributed to `entry`.
+/// The run stops at `deadline` (408) or when its output goes over the limit (422); `hook` is
+/// called around every node (e.g. to stop a run early).
+pub(super) fn execute(
+  state: &AppState,
+  program: &Program,
+  entry: Option<&str>,
+  options: &RunOptions,
+  deadline: &Deadline,
+  hook: Option<&mut dyn EvalHook>,
+) -> Result<RunResponse, ApiError> {
+  let limits = &state.limits;
+  let mut guard = Guard::new(deadline, limits.output_bytes, hook);
+  let seed = options.seed.unwrap_or_else(rand::random::<u64>);
   let mut rng = StdRng::seed_from_u64(seed);
-  let input = body.input.as_deref();
-  let (result, trace) = interpret(&program, &state.builtins, &mut rng, input).map_err(|e: SourceError| {
-    let s = e.span.as_ref();
-    (
-      StatusCode::UNPROCESSABLE_ENTITY,
-      Json(ErrorResponse {
-        error: e.message,
-        line: s.map(|x| x.start_line),
-        column: s.map(|x| x.start_column),
-        end_line: s.map(|x| x.end_line),
-        end_column: s.map(|x| x.end_column),
-      }),
-    )
+  let input = options.input.as_deref();
+  let mut profile = options.profile.then(Profile::new);
+  let start = Instant::now();
+  let out = interpret_with(
+    program,
+    &state.builtins,
+    &mut rng,
+    input,
+    &options.vars,
+    profile.as_mut(),
+    Some(&mut guard),
+  );
+  if let Some(p) = profile.as_mut() {
+    p.finish(start.elapsed());
+  }
+  let outcome = match (guard.stop, &out) {
+    (Some(Stop::Timeout), _) => RunOutcome::Timeout,
+    (Some(Stop::Output), _) => RunOutcome::OutputLimit,
+    (None, Ok((r, _))) if r.len() > limits.output_bytes => RunOutcome::OutputLimit,
+    (None, Ok((r, _))) => RunOutcome::Ok(r.len()),
+    (None, Err(_)) => RunOutcome::Runtime,
+  };
+  state.metrics.run(guard.steps, outcome);
+  match outcome {
+    RunOutcome::Timeout => return Err(limits.timeout_error()),
+    RunOutcome::OutputLimit => return Err(limits.output_error()),
+    RunOutcome::Ok(_) | RunOutcome::Runtime => {}
+  }
+  let (result, trace) = out.map_err(|e: SourceError| {
+    let (_, body) = error_response(e.in_file(entry));
+    (StatusCode::UNPROCESSABLE_ENTITY, body)
   })?;
-  Ok(Json(RunResponse { result, trace }))
+  Ok(RunResponse {
+    result,
+    trace,
+    profile,
+  })
 }
 
+/// Format the entry source and, with `files`, every file (all must parse).
+#[utoipa::path(
+  post,
+  path = "/format",
+  tag = "source",
+  request_body = FormatRequest,
+  responses(
+    (status = 200, body = FormatResponse),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
 pub async fn format(
-  Json(body): Json<FormatRequest>,
-) -> Result<Json<FormatResponse>, (StatusCode, Json<ErrorResponse>)> {
-  let program = parse_program(&body.source).map_err(error_response)?;
-  let formatted = format_program(&program, &FormatOptions::default());
-  Ok(Json(FormatResponse { formatted }))
+  ApiJson(body): ApiJson<FormatRequest>,
+) -> Result<Json<FormatResponse>, ApiError> {
+  let options = FormatOptions::default();
+  let (entry, source) = entry_source(&body.program)?;
+  let program = parse_program(source).map_err(|e| error_response(e.in_file(entry.as_deref())))?;
+  let formatted = format_program(&program, &options);
+  let mut files = BTreeMap::new();
+  for (path, source) in &body.program.files {
+    let program = parse_program(source).map_err(|e| error_response(e.in_file(Some(path))))?;
+    files.insert(path.clone(), format_program(&program, &options));
+  }
+  Ok(Json(FormatResponse { formatted, files }))
+}
+
+/// Parse and resolve includes without running; reports what the program defines.
+#[utoipa::path(
+  post,
+  path = "/check",
+  tag = "source",
+  request_body = CheckRequest,
+  responses(
+    (status = 200, body = CheckResponse),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn check(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<CheckRequest>,
+) -> Result<Json<CheckResponse>, ApiError> {
+  let program = load_cached(&state, &body.program)?;
+  Ok(Json(CheckResponse {
+    functions: program.functions.len(),
+    events: program.events.len(),
+  }))
+}
+
+/// Diagnostics, symbols, call graph, output counts and builtins of a program, for the editor.
+///
+/// Nothing is run, but the analysis counts as a run for the concurrency cap and the timeout. A
+/// program that does not load gets its error as the only diagnostic.
+#[utoipa::path(
+  post,
+  path = "/analyze",
+  tag = "source",
+  request_body = AnalyzeRequest,
+  responses(
+    (status = 200, body = Analysis),
+    (status = 400, description = "Bad request", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 408, description = "Analysis timed out", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn analyze(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<AnalyzeRequest>,
+) -> Result<Json<Analysis>, ApiError> {
+  let program = match load_cached(&state, &body.program) {
+    Ok(program) => program,
+    Err((_, Json(error))) => return Ok(Json(Analysis::failed(source_error(error)))),
+  };
+  blocking(state.limits, state.throttle.run_permit()?, move |_| {
+    Ok(analysis::analyze(&program, &state.builtins))
+  })
+  .await
+  .map(Json)
+}
+
+/// `body` with its entry source replaced by `source`.
+fn with_entry_source(body: &SourceFiles, source: String) -> SourceFiles {
+  let mut body = body.clone();
+  match &body.entry {
+    Some(entry) => {
+      let path = if body.files.contains_key(entry) {
+        entry.clone()
+      } else {
+        normalize(entry)
+      };
+      body.files.insert(path, source);
+    }
+    None => body.source = source,
+  }
+  body
+}
+
+/// Builtins, functions, events and params that complete the name being typed at the cursor.
+///
+/// When the source does not load as it is (the name is half typed), it is loaded without that
+/// name; when that fails too, only builtins are offered.
+#[utoipa::path(
+  post,
+  path = "/complete",
+  tag = "source",
+  request_body = CursorRequest,
+  responses(
+    (status = 200, body = Completions),
+    (status = 400, description = "Bad request or position outside the source", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 422, description = "Body without `line` and `column`", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn complete(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<CursorRequest>,
+) -> Result<Json<Completions>, ApiError> {
+  let (_, source) = entry_source(&body.program)?;
+  let prefix = Prefix::at(source, body.line, body.column).ok_or_else(|| {
+    message_error(
+      StatusCode::BAD_REQUEST,
+      format!(
+        "line {}, column {} is outside the source",
+        body.line, body.column
+      ),
+      None,
+    )
+  })?;
+  let program = editor_program(&state, &body.program).or_else(|| {
+    editor_program(
+      &state,
+      &with_entry_source(&body.program, prefix.strip(source)),
+    )
+  });
+  Ok(Json(editor::complete(
+    &prefix,
+    program.as_deref(),
+    &state.builtins,
+  )))
+}
+
+/// Signature and description of the function called at the cursor; null when the cursor is not
+/// on the name of a known function, or the source does not load.
+#[utoipa::path(
+  post,
+  path = "/hover",
+  tag = "source",
+  request_body = CursorRequest,
+  responses(
+    (status = 200, body = Option<Hover>),
+    (status = 400, description = "Bad request", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 422, description = "Body without `line` and `column`", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn hover(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<CursorRequest>,
+) -> Json<Option<Hover>> {
+  let program = editor_program(&state, &body.program);
+  Json(program.and_then(|program| editor::hover(&program, body.line, body.column, &state.builtins)))
 }
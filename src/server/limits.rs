@@ -0,0 +1,203 @@
+//! Request limits: body size, run time, output size, batch size and stream size and time.
+//!
+//! Runs go to the blocking thread pool so a slow program does not stall the async executor.
+//! The handler gives up after the timeout; the run itself stops at its next node through
+//! [`Guard`], which also stops a run whose output grows past the cap.
+
+use super::handlers::{message_error, ApiError};
+use crate::ast::{SourceError, Span};
+use crate::interpreter::HookContext;
+use crate::EvalHook;
+use axum::http::StatusCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OwnedSemaphorePermit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+  /// Largest request body accepted (413 above it).
+  pub body_bytes: usize,
+  /// Wall-clock time a request may spend running programs (408 after it).
+  pub timeout: Duration,
+  /// Largest output of one run (422 above it).
+  pub output_bytes: usize,
+  /// Most runs one `/run/batch` request may ask for (400 above it).
+  pub batch_runs: usize,
+  /// Most runs one `/run/stream` request may ask for (400 above it).
+  pub stream_runs: usize,
+  /// Wall-clock time one `/run/stream` request may take in all; each run also stops at
+  /// `timeout`.
+  pub stream_timeout: Duration,
+}
+
+impl Default for Limits {
+  /// 1 MiB bodies, 5 s per request, 1 MiB outputs, 1000 runs per batch, 100 000 runs and
+  /// 60 s per stream.
+  fn default() -> Self {
+    Self {
+      body_bytes: 1 << 20,
+      timeout: Duration::from_secs(5),
+      output_bytes: 1 << 20,
+      batch_runs: 1000,
+      stream_runs: 100_000,
+      stream_timeout: Duration::from_mins(1),
+    }
+  }
+}
+
+impl Limits {
+  pub(super) fn timeout_error(&self) -> ApiError {
+    message_error(
+      StatusCode::REQUEST_TIMEOUT,
+      format!("run timed out after {} ms", self.timeout.as_millis()),
+      None,
+    )
+  }
+
+  pub(super) fn stream_timeout_error(&self) -> ApiError {
+    message_error(
+      StatusCode::REQUEST_TIMEOUT,
+      format!(
+        "stream timed out after {} ms",
+        self.stream_timeout.as_millis()
+      ),
+      None,
+    )
+  }
+
+  pub(super) fn output_error(&self) -> ApiError {
+    message_error(
+      StatusCode::UNPROCESSABLE_ENTITY,
+      format!("output exceeds the limit of {} bytes", self.output_bytes),
+      None,
+    )
+  }
+}
+
+/// When runs of a request must stop: at `at`, or earlier once cancelled.
+pub struct Deadline {
+  at: Instant,
+  cancelled: AtomicBool,
+}
+
+impl Deadline {
+  pub fn after(timeout: Duration) -> Self {
+    Self {
+      at: Instant::now() + timeout,
+      cancelled: AtomicBool::new(false),
+    }
+  }
+
+  pub fn cancel(&self) {
+    self.cancelled.store(true, Ordering::Relaxed);
+  }
+
+  pub fn expired(&self) -> bool {
+    self.cancelled.load(Ordering::Relaxed) || Instant::now() >= self.at
+  }
+
+  /// Time left until `at` (zero once it has passed).
+  pub fn remaining(&self) -> Duration {
+    self.at.saturating_duration_since(Instant::now())
+  }
+}
+
+/// Why [`Guard`] stopped a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Stop {
+  Timeout,
+  Output,
+}
+
+/// Hook that stops a run past its deadline or once some node's output is over the cap, then
+/// defers to `inner`. The cap is also passed to the interpreter, so a long repeat, concatenation
+/// or builtin call stops as soon as it goes over instead of building the whole output.
+pub(super) struct Guard<'d, 'h> {
+  deadline: &'d Deadline,
+  output_bytes: usize,
+  inner: Option<&'h mut dyn EvalHook>,
+  pub stop: Option<Stop>,
+  /// Nodes evaluated so far.
+  pub steps: u64,
+}
+
+impl<'d, 'h> Guard<'d, 'h> {
+  pub fn new(
+    deadline: &'d Deadline,
+    output_bytes: usize,
+    inner: Option<&'h mut dyn EvalHook>,
+  ) -> Self {
+    Self {
+      deadline,
+      output_bytes,
+      inner,
+      stop: None,
+      steps: 0,
+    }
+  }
+}
+
+impl EvalHook for Guard<'_, '_> {
+  fn before(&mut self, ctx: &HookContext<'_>) -> Result<(), String> {
+    self.steps += 1;
+    if self.stop.is_none() && self.deadline.expired() {
+      self.stop = Some(Stop::Timeout);
+    }
+    match self.stop {
+      Some(Stop::Timeout) => Err("run timed out".to_string()),
+      Some(Stop::Output) => Err("output too large".to_string()),
+      None => self.inner.as_mut().map_or(Ok(()), |h| h.before(ctx)),
+    }
+  }
+
+  fn after(&mut self, ctx: &HookContext<'_>, result: Result<&str, &SourceError>) {
+    if result.is_ok_and(|out| out.len() > self.output_bytes) {
+      self.stop.get_or_insert(Stop::Output);
+    }
+    if let Some(h) = self.inner.as_mut() {
+      h.after(ctx, result);
+    }
+  }
+
+  fn output_limit(&self) -> Option<usize> {
+    Some(self.output_bytes)
+  }
+
+  fn choose(&mut self, span: Option<Span>, depth: usize, chosen: usize, count: usize) -> usize {
+    self
+      .inner
+      .as_mut()
+      .map_or(chosen, |h| h.choose(span, depth, chosen, count))
+  }
+}
+
+/// Run `f` on the blocking pool, failing with 408 after `limits.timeout`. On timeout the
+/// deadline is cancelled, so `f` (which should check it) stops soon after; `permit` (a slot of
+/// the concurrent run cap) is held until `f` returns.
+pub(super) async fn blocking<T: Send + 'static>(
+  limits: Limits,
+  permit: OwnedSemaphorePermit,
+  f: impl FnOnce(&Deadline) -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+  let deadline = Arc::new(Deadline::after(limits.timeout));
+  let task = {
+    let deadline = Arc::clone(&deadline);
+    tokio::task::spawn_blocking(move || {
+      let _permit = permit;
+      f(&deadline)
+    })
+  };
+  match tokio::time::timeout(limits.timeout, task).await {
+    Ok(Ok(out)) => out,
+    Ok(Err(e)) => Err(message_error(
+      StatusCode::INTERNAL_SERVER_ERROR,
+      format!("run failed: {e}"),
+      None,
+    )),
+    Err(_) => {
+      deadline.cancel();
+      Err(limits.timeout_error())
+    }
+  }
+}
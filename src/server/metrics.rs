@@ -0,0 +1,326 @@
+//! In-process metrics, served by `/metrics` in the Prometheus text format.
+//!
+//! Counters and histograms live behind one mutex; they are updated once per request or run,
+//! so contention is negligible next to the work being measured.
+
+use super::cache::CacheStats;
+use super::error::ErrorResponse;
+use super::handlers::ApiError;
+use super::AppState;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+/// Upper bounds of the request latency buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+  0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Upper bounds of the evaluation step buckets (nodes evaluated per run).
+const STEP_BUCKETS: &[f64] = &[10.0, 100.0, 1e3, 1e4, 1e5, 1e6, 1e7];
+/// Upper bounds of the output size buckets, in bytes.
+const OUTPUT_BUCKETS: &[f64] = &[
+  64.0,
+  256.0,
+  1024.0,
+  4096.0,
+  16384.0,
+  65536.0,
+  262_144.0,
+  1_048_576.0,
+];
+
+struct Histogram {
+  bounds: &'static [f64],
+  /// Observations per bucket (not cumulative); the last one is `+Inf`.
+  counts: Vec<u64>,
+  sum: f64,
+}
+
+impl Histogram {
+  fn new(bounds: &'static [f64]) -> Self {
+    Self {
+      bounds,
+      counts: vec![0; bounds.len() + 1],
+      sum: 0.0,
+    }
+  }
+
+  fn observe(&mut self, value: f64) {
+    let bucket = self.bounds.partition_point(|b| *b < value);
+    self.counts[bucket] += 1;
+    self.sum += value;
+  }
+
+  fn write(&self, out: &mut String, name: &str, labels: &str) {
+    let sep = if labels.is_empty() { "" } else { "," };
+    let mut total = 0;
+    for (i, count) in self.counts.iter().enumerate() {
+      total += count;
+      let le = self
+        .bounds
+        .get(i)
+        .map_or_else(|| "+Inf".to_string(), f64::to_string);
+      let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{le}\"}} {total}");
+    }
+    let braces = if labels.is_empty() {
+      String::new()
+    } else {
+      format!("{{{labels}}}")
+    };
+    let _ = writeln!(out, "{name}_sum{braces} {}", self.sum);
+    let _ = writeln!(out, "{name}_count{braces} {total}");
+  }
+}
+
+struct Inner {
+  /// By (method, route, status).
+  requests: BTreeMap<(String, String, u16), u64>,
+  /// By (method, route).
+  latency: BTreeMap<(String, String), Histogram>,
+  /// By (stage, kind).
+  errors: BTreeMap<(&'static str, &'static str), u64>,
+  runs: u64,
+  steps: Histogram,
+  output: Histogram,
+}
+
+pub struct Metrics {
+  inner: Mutex<Inner>,
+}
+
+impl Default for Metrics {
+  fn default() -> Self {
+    Self {
+      inner: Mutex::new(Inner {
+        requests: BTreeMap::new(),
+        latency: BTreeMap::new(),
+        errors: BTreeMap::new(),
+        runs: 0,
+        steps: Histogram::new(STEP_BUCKETS),
+        output: Histogram::new(OUTPUT_BUCKETS),
+      }),
+    }
+  }
+}
+
+/// How a run ended, for [`Metrics::run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RunOutcome {
+  /// Output of this many bytes.
+  Ok(usize),
+  Runtime,
+  Timeout,
+  OutputLimit,
+}
+
+impl Metrics {
+  fn lock(&self) -> MutexGuard<'_, Inner> {
+    self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  pub(super) fn request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+    let mut inner = self.lock();
+    *inner
+      .requests
+      .entry((method.to_string(), route.to_string(), status))
+      .or_default() += 1;
+    inner
+      .latency
+      .entry((method.to_string(), route.to_string()))
+      .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+      .observe(seconds);
+  }
+
+  /// A program that failed to load: `syntax` when the error has a position, else `resolve`
+  /// (includes, missing entry).
+  pub(super) fn parse_error(&self, (_, error): &ApiError) {
+    let kind = if error.line.is_some() {
+      "syntax"
+    } else {
+      "resolve"
+    };
+    *self.lock().errors.entry(("parse", kind)).or_default() += 1;
+  }
+
+  /// A finished run that evaluated `steps` nodes.
+  pub(super) fn run(&self, steps: u64, outcome: RunOutcome) {
+    let mut inner = self.lock();
+    inner.runs += 1;
+    #[allow(clippy::cast_precision_loss)] // step counts are far below 2^52
+    inner.steps.observe(steps as f64);
+    let kind = match outcome {
+      RunOutcome::Ok(bytes) => {
+        #[allow(clippy::cast_precision_loss)] // output is capped well below 2^52
+        inner.output.observe(bytes as f64);
+        return;
+      }
+      RunOutcome::Runtime => "runtime",
+      RunOutcome::Timeout => "timeout",
+      RunOutcome::OutputLimit => "output_limit",
+    };
+    *inner.errors.entry(("eval", kind)).or_default() += 1;
+  }
+
+  /// All metrics in the Prometheus text exposition format, parse cache figures included.
+  pub fn render(&self, state: &AppState) -> String {
+    let mut out = String::new();
+    let inner = self.lock();
+    describe(
+      &mut out,
+      "branchy_http_requests_total",
+      "counter",
+      "HTTP requests by route and status.",
+    );
+    for ((method, route, status), count) in &inner.requests {
+      let _ = writeln!(
+        out,
+        "branchy_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{status}\"}} {count}",
+        escape(method),
+        escape(route)
+      );
+    }
+    describe(
+      &mut out,
+      "branchy_http_request_duration_seconds",
+      "histogram",
+      "Time to the response headers, by route.",
+    );
+    for ((method, route), histogram) in &inner.latency {
+      let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+      histogram.write(&mut out, "branchy_http_request_duration_seconds", &labels);
+    }
+    describe(
+      &mut out,
+      "branchy_errors_total",
+      "counter",
+      "Parse and evaluation errors by kind.",
+    );
+    for ((stage, kind), count) in &inner.errors {
+      let _ = writeln!(
+        out,
+        "branchy_errors_total{{stage=\"{stage}\",kind=\"{kind}\"}} {count}"
+      );
+    }
+    describe(&mut out, "branchy_runs_total", "counter", "Program runs.");
+    let _ = writeln!(out, "branchy_runs_total {}", inner.runs);
+    describe(
+      &mut out,
+      "branchy_eval_steps",
+      "histogram",
+      "Nodes evaluated per run.",
+    );
+    inner.steps.write(&mut out, "branchy_eval_steps", "");
+    describe(
+      &mut out,
+      "branchy_output_bytes",
+      "histogram",
+      "Output size of successful runs.",
+    );
+    inner.output.write(&mut out, "branchy_output_bytes", "");
+    drop(inner);
+    write_cache(&mut out, &state.cache.stats());
+    out
+  }
+}
+
+/// Parse cache counters and size, read at scrape time.
+fn write_cache(out: &mut String, cache: &CacheStats) {
+  let cache_metrics = [
+    (
+      "branchy_parse_cache_hits_total",
+      "counter",
+      "Parse cache hits.",
+      cache.hits,
+    ),
+    (
+      "branchy_parse_cache_misses_total",
+      "counter",
+      "Parse cache misses.",
+      cache.misses,
+    ),
+    (
+      "branchy_parse_cache_entries",
+      "gauge",
+      "Programs in the parse cache.",
+      cache.entries as u64,
+    ),
+    (
+      "branchy_parse_cache_bytes",
+      "gauge",
+      "Approximate size of the parse cache.",
+      cache.bytes as u64,
+    ),
+  ];
+  for (name, kind, help, value) in cache_metrics {
+    describe(out, name, kind, help);
+    let _ = writeln!(out, "{name} {value}");
+  }
+  let lookups = cache.hits + cache.misses;
+  describe(
+    out,
+    "branchy_parse_cache_hit_ratio",
+    "gauge",
+    "Parse cache hits over all lookups since start (0 before the first lookup).",
+  );
+  #[allow(clippy::cast_precision_loss)] // a ratio; precision loss is irrelevant
+  let ratio = if lookups == 0 {
+    0.0
+  } else {
+    cache.hits as f64 / lookups as f64
+  };
+  let _ = writeln!(out, "branchy_parse_cache_hit_ratio {ratio}");
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+  let _ = writeln!(out, "# HELP {name} {help}");
+  let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Label value escaped for the text format.
+fn escape(value: &str) -> String {
+  value
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
+
+#[utoipa::path(
+  get,
+  path = "/metrics",
+  tag = "server",
+  responses(
+    (status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+  (
+    [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+    state.metrics.render(&state),
+  )
+}
+
+/// Middleware: count requests and their latency by matched route. For streams the latency is
+/// the time to the response headers, not to the end of the stream.
+pub(super) async fn track(State(state): State<AppState>, req: Request, next: Next) -> Response {
+  let route = req
+    .extensions()
+    .get::<MatchedPath>()
+    .map_or_else(|| req.uri().path().to_string(), |p| p.as_str().to_string());
+  let method = req.method().to_string();
+  let start = Instant::now();
+  let response = next.run(req).await;
+  state.metrics.request(
+    &method,
+    &route,
+    response.status().as_u16(),
+    start.elapsed().as_secs_f64(),
+  );
+  response
+}
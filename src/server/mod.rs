@@ -1,21 +1,125 @@
 //! HTTP API server.
 
+mod auth;
+mod cache;
+mod config;
 mod error;
+mod extract;
 mod handlers;
+mod limits;
+mod metrics;
+mod openapi;
+mod programs;
+mod rate_limit;
+mod share;
+mod store;
+mod stream;
 mod types;
 
-use axum::{routing::get, routing::post, Router};
-use tower_http::cors::CorsLayer;
+use axum::extract::DefaultBodyLimit;
+use axum::http::HeaderValue;
+use axum::middleware;
+use axum::routing::{delete, get, post, put, MethodRouter};
+use axum::Router;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
-pub use handlers::{examples, format, health, run};
-pub use types::AppState;
+pub use auth::{hash_key, ApiKey, Auth, Caller, KeyConfig, KeysFile, Quota, HASH_PREFIX};
+pub use cache::{CacheStats, ParseCache};
+pub use config::{CacheConfig, LimitsConfig, ServerConfig, DEFAULT_CONFIG_FILE, USAGE};
+pub use handlers::{
+  analyze, cache_stats, check, complete, examples, format, health, hover, load_examples, run,
+  run_batch, stdlib_modules,
+};
+pub use limits::Limits;
+pub use metrics::{metrics, Metrics};
+pub use openapi::{openapi, ApiDoc};
+pub use programs::{
+  delete_program, get_program, get_program_version, list_programs, run_program, save_program,
+};
+pub use rate_limit::{RateLimit, RateLimits, Throttle};
+pub use share::{create_share, get_share, ShareStore, DEFAULT_MAX_SHARES};
+pub use store::{ProgramStore, StoreError};
+pub use stream::run_stream;
+pub use types::{AppState, SourceFiles};
 
+/// The API with any CORS origin allowed.
 pub fn create_app(state: AppState) -> Router {
-  Router::new()
-    .route("/health", get(health))
-    .route("/examples", get(examples))
-    .route("/run", post(run))
-    .route("/format", post(format))
-    .layer(CorsLayer::permissive())
+  create_app_with_origins(state, &["*".to_string()])
+}
+
+/// Every operation of the API as (method, path, handler). `/openapi.json` documents exactly
+/// these, see [`routes`].
+fn operations() -> Vec<(&'static str, &'static str, MethodRouter<AppState>)> {
+  vec![
+    ("GET", "/health", get(health)),
+    ("GET", "/examples", get(examples)),
+    ("GET", "/stdlib", get(stdlib_modules)),
+    ("POST", "/run", post(run)),
+    ("POST", "/run/batch", post(run_batch)),
+    ("POST", "/run/stream", post(run_stream)),
+    ("POST", "/format", post(format)),
+    ("POST", "/check", post(check)),
+    ("POST", "/analyze", post(analyze)),
+    ("POST", "/complete", post(complete)),
+    ("POST", "/hover", post(hover)),
+    ("GET", "/cache", get(cache_stats)),
+    ("GET", "/metrics", get(metrics)),
+    ("GET", "/openapi.json", get(openapi)),
+    ("GET", "/programs", get(list_programs)),
+    ("PUT", "/programs/:name", put(save_program)),
+    ("GET", "/programs/:name", get(get_program)),
+    ("DELETE", "/programs/:name", delete(delete_program)),
+    (
+      "GET",
+      "/programs/:name/versions/:version",
+      get(get_program_version),
+    ),
+    ("POST", "/programs/:name/run", post(run_program)),
+    ("POST", "/share", post(create_share)),
+    ("GET", "/share/:id", get(get_share)),
+  ]
+}
+
+/// Routed operations as (method, path), with path parameters as `:name`.
+pub fn routes() -> Vec<(&'static str, &'static str)> {
+  operations()
+    .into_iter()
+    .map(|(method, path, _)| (method, path))
+    .collect()
+}
+
+/// The API allowing CORS requests from `origins` (`*` for any).
+pub fn create_app_with_origins(state: AppState, origins: &[String]) -> Router {
+  // Routes of the same path are merged into one method router.
+  operations()
+    .into_iter()
+    .fold(Router::new(), |router, (_, path, handler)| {
+      router.route(path, handler)
+    })
+    .route_layer(middleware::from_fn_with_state(
+      state.clone(),
+      rate_limit::rate_limit,
+    ))
+    .route_layer(middleware::from_fn_with_state(
+      state.clone(),
+      auth::authenticate,
+    ))
+    .route_layer(middleware::from_fn_with_state(
+      state.clone(),
+      metrics::track,
+    ))
+    .layer(DefaultBodyLimit::max(state.limits.body_bytes))
+    .layer(cors(origins))
     .with_state(state)
 }
+
+fn cors(origins: &[String]) -> CorsLayer {
+  if origins.iter().any(|o| o == "*") {
+    return CorsLayer::permissive();
+  }
+  let origins: Vec<HeaderValue> = origins.iter().filter_map(|o| o.parse().ok()).collect();
+  CorsLayer::new()
+    .allow_origin(AllowOrigin::list(origins))
+    .allow_methods(Any)
+    .allow_headers(Any)
+}
@@ -0,0 +1,135 @@
+//! Machine-readable description of the API (`/openapi.json`), generated from the handlers'
+//! `utoipa::path` attributes and the request/response types.
+
+use axum::Json;
+use utoipa::openapi::security::{
+  ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme,
+};
+use utoipa::{Modify, OpenApi};
+
+use super::cache::CacheStats;
+use super::error::ErrorResponse;
+use super::types::{
+  AnalyzeRequest, BatchItem, BatchRequest, CheckRequest, CheckResponse, CursorRequest, ExampleItem,
+  FormatRequest, FormatResponse, ProgramDetail, ProgramSummary, RunOptions, RunRequest,
+  RunResponse, SavedProgram, ShareCreated, SharedResult, SharedRun, SourceFiles, StdlibModule,
+  StoredRunRequest, StoredVersion, StreamChunk, StreamDone, StreamSample, VersionInfo,
+};
+use super::{handlers, metrics, programs, share, stream};
+use crate::analysis::{Analysis, CallEdge, Diagnostic, Severity, Symbol, SymbolKind};
+use crate::ast::Span;
+use crate::editor::{Completion, CompletionKind, Completions, Hover};
+use crate::interpreter::{Profile, ProfileEntry, ProfileKind};
+
+#[derive(OpenApi)]
+#[openapi(
+  info(
+    title = "branchy",
+    description = "Run, format and store branchy programs. Behind the nginx gateway every path \
+                   is prefixed with `/api`."
+  ),
+  paths(
+    handlers::health,
+    handlers::examples,
+    handlers::stdlib_modules,
+    handlers::run,
+    handlers::run_batch,
+    stream::run_stream,
+    handlers::format,
+    handlers::check,
+    handlers::analyze,
+    handlers::complete,
+    handlers::hover,
+    handlers::cache_stats,
+    metrics::metrics,
+    openapi,
+    programs::list_programs,
+    programs::save_program,
+    programs::get_program,
+    programs::get_program_version,
+    programs::delete_program,
+    programs::run_program,
+    share::create_share,
+    share::get_share,
+  ),
+  modifiers(&Security),
+  components(schemas(
+    SourceFiles,
+    RunRequest,
+    RunOptions,
+    RunResponse,
+    BatchRequest,
+    BatchItem,
+    StreamSample,
+    StreamChunk,
+    StreamDone,
+    ExampleItem,
+    StdlibModule,
+    FormatRequest,
+    FormatResponse,
+    CheckRequest,
+    CheckResponse,
+    AnalyzeRequest,
+    Analysis,
+    Diagnostic,
+    Severity,
+    Symbol,
+    SymbolKind,
+    CallEdge,
+    CursorRequest,
+    Completions,
+    Completion,
+    CompletionKind,
+    Hover,
+    CacheStats,
+    StoredVersion,
+    VersionInfo,
+    ProgramSummary,
+    ProgramDetail,
+    SavedProgram,
+    StoredRunRequest,
+    SharedRun,
+    ShareCreated,
+    SharedResult,
+    ErrorResponse,
+    Span,
+    Profile,
+    ProfileEntry,
+    ProfileKind,
+  ))
+)]
+pub struct ApiDoc;
+
+/// API key schemes, and a security requirement that also allows no key (servers without a keys
+/// file are open).
+struct Security;
+
+impl Modify for Security {
+  fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+    let components = openapi.components.get_or_insert_with(Default::default);
+    components.add_security_scheme(
+      "bearer",
+      SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+    );
+    components.add_security_scheme(
+      "api_key",
+      SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+    );
+    openapi.security = Some(vec![
+      SecurityRequirement::default(),
+      SecurityRequirement::new("bearer", Vec::<String>::new()),
+      SecurityRequirement::new("api_key", Vec::<String>::new()),
+    ]);
+  }
+}
+
+/// This document.
+#[utoipa::path(
+  get,
+  path = "/openapi.json",
+  tag = "server",
+  responses((status = 200, description = "OpenAPI 3.1 document", body = Object))
+)]
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+  Json(ApiDoc::openapi())
+}
@@ -0,0 +1,254 @@
+//! Named programs: save versions, read them back, run them by name.
+
+use crate::resolve::normalize;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+
+use super::auth::Caller;
+use super::error::ErrorResponse;
+use super::extract::ApiJson;
+use super::handlers::{execute, load_cached, message_error, ApiError};
+use super::limits::blocking;
+use super::store::{ProgramStore, StoreError};
+use super::types::{
+  ProgramDetail, ProgramSummary, RunResponse, SavedProgram, SourceFiles, StoredRunRequest,
+};
+use super::AppState;
+use std::sync::Arc;
+
+#[allow(clippy::needless_pass_by_value)] // used as `map_err(store_error)`
+fn store_error(e: StoreError) -> ApiError {
+  let status = match e {
+    StoreError::InvalidName(_) => StatusCode::BAD_REQUEST,
+    StoreError::NotFound(_) => StatusCode::NOT_FOUND,
+    StoreError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+  };
+  message_error(status, e.to_string(), None)
+}
+
+/// Stored programs with their latest version.
+#[utoipa::path(
+  get,
+  path = "/programs",
+  tag = "programs",
+  responses(
+    (status = 200, body = [ProgramSummary]),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn list_programs(
+  State(state): State<AppState>,
+  caller: Caller,
+) -> Result<Json<Vec<ProgramSummary>>, ApiError> {
+  with_store(&state, move |store| {
+    let mut out = Vec::new();
+    for name in store.names()? {
+      if !caller.can_access(&name) {
+        continue;
+      }
+      let latest = store.latest(&name)?;
+      out.push(ProgramSummary {
+        name,
+        version: latest.version,
+        updated: latest.created,
+      });
+    }
+    Ok(out)
+  })
+  .await
+  .map(Json)
+}
+
+/// Run `f` on the blocking pool: listing and history touch a file per program or version.
+async fn with_store<T: Send + 'static>(
+  state: &AppState,
+  f: impl FnOnce(&ProgramStore) -> Result<T, StoreError> + Send + 'static,
+) -> Result<T, ApiError> {
+  let store = Arc::clone(&state.store);
+  tokio::task::spawn_blocking(move || f(&store))
+    .await
+    .map_err(|e| {
+      message_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("program store: {e}"),
+        None,
+      )
+    })?
+    .map_err(store_error)
+}
+
+/// Save a new version. The program must parse and its includes resolve.
+#[utoipa::path(
+  put,
+  path = "/programs/{name}",
+  tag = "programs",
+  request_body = SourceFiles,
+  params(
+    ("name" = String, Path, description = "Program name"),
+  ),
+  responses(
+    (status = 201, body = SavedProgram),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 403, description = "API key has no access to this program", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn save_program(
+  State(state): State<AppState>,
+  caller: Caller,
+  Path(name): Path<String>,
+  ApiJson(body): ApiJson<SourceFiles>,
+) -> Result<(StatusCode, Json<SavedProgram>), ApiError> {
+  caller.check_program(&name, true)?;
+  load_cached(&state, &body)?;
+  let version = state.store.put(&name, body).map_err(store_error)?;
+  Ok((StatusCode::CREATED, Json(SavedProgram { name, version })))
+}
+
+/// Latest version of a program with the list of all versions.
+#[utoipa::path(
+  get,
+  path = "/programs/{name}",
+  tag = "programs",
+  params(
+    ("name" = String, Path, description = "Program name"),
+  ),
+  responses(
+    (status = 200, body = ProgramDetail),
+    (status = 400, description = "Invalid program name", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 403, description = "API key has no access to this program", body = ErrorResponse),
+    (status = 404, description = "No such program or version", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn get_program(
+  State(state): State<AppState>,
+  caller: Caller,
+  Path(name): Path<String>,
+) -> Result<Json<ProgramDetail>, ApiError> {
+  caller.check_program(&name, false)?;
+  detail(&state, name, None).await
+}
+
+#[utoipa::path(
+  get,
+  path = "/programs/{name}/versions/{version}",
+  tag = "programs",
+  params(
+    ("name" = String, Path, description = "Program name"),
+    ("version" = u32, Path, description = "Version number"),
+  ),
+  responses(
+    (status = 200, body = ProgramDetail),
+    (status = 400, description = "Invalid program name", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 403, description = "API key has no access to this program", body = ErrorResponse),
+    (status = 404, description = "No such program or version", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn get_program_version(
+  State(state): State<AppState>,
+  caller: Caller,
+  Path((name, version)): Path<(String, u32)>,
+) -> Result<Json<ProgramDetail>, ApiError> {
+  caller.check_program(&name, false)?;
+  detail(&state, name, Some(version)).await
+}
+
+async fn detail(
+  state: &AppState,
+  name: String,
+  version: Option<u32>,
+) -> Result<Json<ProgramDetail>, ApiError> {
+  with_store(state, move |store| {
+    let current = store.get(&name, version)?;
+    let versions = store.history(&name)?;
+    Ok(ProgramDetail {
+      name,
+      current,
+      versions,
+    })
+  })
+  .await
+  .map(Json)
+}
+
+/// Delete a program with all its versions.
+#[utoipa::path(
+  delete,
+  path = "/programs/{name}",
+  tag = "programs",
+  params(
+    ("name" = String, Path, description = "Program name"),
+  ),
+  responses(
+    (status = 204, description = "Deleted"),
+    (status = 400, description = "Invalid program name", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 403, description = "API key has no access to this program", body = ErrorResponse),
+    (status = 404, description = "No such program or version", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn delete_program(
+  State(state): State<AppState>,
+  caller: Caller,
+  Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+  caller.check_program(&name, true)?;
+  state.store.delete(&name).map_err(store_error)?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+/// Run a stored program (latest version unless `version` is given). The resolved program goes
+/// through the parse cache, keyed by the stored sources, so a version is usually parsed once.
+#[utoipa::path(
+  post,
+  path = "/programs/{name}/run",
+  tag = "programs",
+  request_body = StoredRunRequest,
+  params(
+    ("name" = String, Path, description = "Program name"),
+  ),
+  responses(
+    (status = 200, body = RunResponse),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 403, description = "API key has no access to this program", body = ErrorResponse),
+    (status = 404, description = "No such program or version", body = ErrorResponse),
+    (status = 408, description = "Run timed out", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 422, description = "Runtime error or output too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn run_program(
+  State(state): State<AppState>,
+  caller: Caller,
+  Path(name): Path<String>,
+  ApiJson(body): ApiJson<StoredRunRequest>,
+) -> Result<Json<RunResponse>, ApiError> {
+  caller.check_program(&name, false)?;
+  let stored = state.store.get(&name, body.version).map_err(store_error)?;
+  let program = load_cached(&state, &stored.program)?;
+  let entry = stored.program.entry.as_deref().map(normalize);
+  let permit = state.throttle.run_permit()?;
+  blocking(state.limits, permit, move |deadline| {
+    execute(
+      &state,
+      &program,
+      entry.as_deref(),
+      &body.options,
+      deadline,
+      None,
+    )
+  })
+  .await
+  .map(Json)
+}
@@ -0,0 +1,273 @@
+//! Per-client rate limiting (token buckets per route) and a global cap on concurrent runs.
+//!
+//! Clients are told apart by the API key that [`auth`](super::auth) validated, else by IP
+//! address (a key header alone is not trusted); a key may have its own limits. Rejected requests get 429 with
+//! `Retry-After` (seconds).
+
+use super::auth::ApiKey;
+use super::handlers::{message_error, ApiError};
+use super::AppState;
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Buckets kept before full (idle) ones are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Token bucket of one route: `burst` requests at once, refilled at `per_second`. Loading
+/// fails unless `per_second` is a positive number and `burst` is at least 1.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "RateLimitFields")]
+pub struct RateLimit {
+  pub per_second: f64,
+  pub burst: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitFields {
+  per_second: f64,
+  burst: u32,
+}
+
+impl TryFrom<RateLimitFields> for RateLimit {
+  type Error = String;
+
+  fn try_from(f: RateLimitFields) -> Result<Self, String> {
+    if !(f.per_second.is_finite() && f.per_second > 0.0) {
+      return Err(format!(
+        "per_second must be a positive number, not {}",
+        f.per_second
+      ));
+    }
+    if f.burst == 0 {
+      return Err("burst must be at least 1".to_string());
+    }
+    Ok(Self {
+      per_second: f.per_second,
+      burst: f.burst,
+    })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+  /// Limit of routes not in `routes` (None: unlimited).
+  pub default: Option<RateLimit>,
+  /// Limits by route pattern, e.g. `/run` or `/programs/:name/run`.
+  pub routes: HashMap<String, RateLimit>,
+  /// Programs running at once over all clients.
+  pub max_concurrent_runs: usize,
+  /// Take the client IP from `X-Real-IP` / `X-Forwarded-For` (set by a reverse proxy in front)
+  /// instead of the connection's address.
+  pub trust_proxy: bool,
+}
+
+impl Default for RateLimits {
+  /// Limits on the routes that run programs or save shared runs; two runs per CPU at once.
+  fn default() -> Self {
+    let run = RateLimit {
+      per_second: 5.0,
+      burst: 20,
+    };
+    let batch = RateLimit {
+      per_second: 0.5,
+      burst: 5,
+    };
+    let routes = [
+      ("/run", run),
+      ("/programs/:name/run", run),
+      ("/share/:id", run),
+      ("/share", batch),
+      ("/run/batch", batch),
+      ("/run/stream", batch),
+    ];
+    Self {
+      default: None,
+      routes: routes
+        .into_iter()
+        .map(|(r, l)| (r.to_string(), l))
+        .collect(),
+      max_concurrent_runs: 2 * std::thread::available_parallelism().map_or(2, NonZeroUsize::get),
+      trust_proxy: false,
+    }
+  }
+}
+
+impl RateLimits {
+  fn limit(&self, route: &str) -> Option<&RateLimit> {
+    self.routes.get(route).or(self.default.as_ref())
+  }
+}
+
+struct Bucket {
+  tokens: f64,
+  updated: Instant,
+}
+
+impl Bucket {
+  /// Refilled to `burst` by `now`, i.e. the same as a new bucket.
+  fn full(&self, limit: &RateLimit, now: Instant) -> bool {
+    let elapsed = now.duration_since(self.updated).as_secs_f64();
+    elapsed.mul_add(limit.per_second, self.tokens) >= f64::from(limit.burst)
+  }
+}
+
+pub struct Throttle {
+  limits: RateLimits,
+  /// By (route, client).
+  buckets: Mutex<HashMap<(String, String), Bucket>>,
+  runs: Arc<Semaphore>,
+}
+
+impl Throttle {
+  pub fn new(limits: RateLimits) -> Self {
+    let runs = Arc::new(Semaphore::new(limits.max_concurrent_runs));
+    Self {
+      limits,
+      buckets: Mutex::new(HashMap::new()),
+      runs,
+    }
+  }
+
+  pub const fn limits(&self) -> &RateLimits {
+    &self.limits
+  }
+
+  /// Take a token for `client` on `route`; Err(time until the next token) when there is none.
+  pub fn check(&self, route: &str, client: &str) -> Result<(), Duration> {
+    self.check_limit(self.limits.limit(route), route, client)
+  }
+
+  /// [`check`](Self::check) with `limit` instead of the route's configured one (None:
+  /// unlimited).
+  pub fn check_limit(
+    &self,
+    limit: Option<&RateLimit>,
+    route: &str,
+    client: &str,
+  ) -> Result<(), Duration> {
+    let Some(limit) = limit else {
+      return Ok(());
+    };
+    let burst = f64::from(limit.burst);
+    let now = Instant::now();
+    let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+    if buckets.len() >= MAX_BUCKETS {
+      let limits = &self.limits;
+      buckets.retain(|(route, _), b| limits.limit(route).is_some_and(|l| !b.full(l, now)));
+    }
+    let bucket = buckets
+      .entry((route.to_string(), client.to_string()))
+      .or_insert(Bucket {
+        tokens: burst,
+        updated: now,
+      });
+    let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = elapsed.mul_add(limit.per_second, bucket.tokens).min(burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      return Ok(());
+    }
+    let missing = 1.0 - bucket.tokens;
+    drop(buckets);
+    if limit.per_second <= 0.0 {
+      return Err(Duration::MAX);
+    }
+    Err(Duration::try_from_secs_f64(missing / limit.per_second).unwrap_or(Duration::MAX))
+  }
+
+  /// A slot to run a program in; 429 when `max_concurrent_runs` programs are already running.
+  pub(super) fn run_permit(&self) -> Result<OwnedSemaphorePermit, ApiError> {
+    Arc::clone(&self.runs).try_acquire_owned().map_err(|_| {
+      message_error(
+        StatusCode::TOO_MANY_REQUESTS,
+        "server is busy: too many programs running, retry later".to_string(),
+        None,
+      )
+    })
+  }
+}
+
+/// IP address a request without a validated API key counts against.
+fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> String {
+  let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+  let forwarded = trust_proxy
+    .then(|| header("x-real-ip").or_else(|| header("x-forwarded-for")?.split(',').next()))
+    .flatten();
+  match (forwarded, peer) {
+    (Some(ip), _) => format!("ip:{}", ip.trim()),
+    (None, Some(addr)) => format!("ip:{}", addr.ip()),
+    (None, None) => "ip:unknown".to_string(),
+  }
+}
+
+/// Middleware: rate limit by route and client, and give every 429 a `Retry-After`.
+pub(super) async fn rate_limit(
+  State(state): State<AppState>,
+  req: Request,
+  next: Next,
+) -> Response {
+  if let Some(response) = take_token(&state, &req) {
+    return response;
+  }
+  let response = next.run(req).await;
+  if response.status() == StatusCode::TOO_MANY_REQUESTS {
+    return with_retry_after(response, Duration::from_secs(1));
+  }
+  response
+}
+
+/// Count `req` against its route's limit, by its validated API key (with the key's own limits)
+/// or else by IP; the 429 response when the client has no requests left. Also used by
+/// [`authenticate`](super::auth::authenticate) for requests it rejects.
+pub(super) fn take_token(state: &AppState, req: &Request) -> Option<Response> {
+  let route = req
+    .extensions()
+    .get::<MatchedPath>()
+    .map_or_else(|| req.uri().path().to_string(), |p| p.as_str().to_string());
+  let peer = req
+    .extensions()
+    .get::<ConnectInfo<SocketAddr>>()
+    .map(|c| c.0);
+  let throttle = &state.throttle;
+  let key = req.extensions().get::<Arc<ApiKey>>();
+  let client = key.map_or_else(
+    || client_ip(req.headers(), peer, throttle.limits.trust_proxy),
+    |k| format!("key:{}", k.config.id),
+  );
+  let limit = key
+    .and_then(|k| k.config.rate_limits.get(&route))
+    .or_else(|| throttle.limits.limit(&route));
+  if let Err(wait) = throttle.check_limit(limit, &route, &client) {
+    let error = message_error(
+      StatusCode::TOO_MANY_REQUESTS,
+      format!("rate limit exceeded for {route}, retry later"),
+      None,
+    );
+    return Some(with_retry_after(error.into_response(), wait));
+  }
+  None
+}
+
+pub(super) fn with_retry_after(mut response: Response, wait: Duration) -> Response {
+  if !response.headers().contains_key(header::RETRY_AFTER) {
+    let secs = wait
+      .as_secs()
+      .saturating_add(u64::from(wait.subsec_nanos() > 0));
+    response
+      .headers_mut()
+      .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+  }
+  response
+}
@@ -0,0 +1,236 @@
+//! Shared runs (`/share`): a program with the input, vars and seed it ran with, saved under a
+//! short id so a link reproduces the exact output.
+//!
+//! Runs are stored by content, `{dir}/{id}.json`, where the id is the start of the hex SHA-256
+//! of the run, so sharing the same run twice gives the same id. The number of stored runs is
+//! capped; past it new runs are refused (old links keep working).
+
+use crate::resolve::normalize;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use super::error::ErrorResponse;
+use super::extract::ApiJson;
+use super::handlers::{load_cached, message_error, run_item, ApiError};
+use super::limits::blocking;
+use super::types::{RunOptions, RunRequest, ShareCreated, SharedResult, SharedRun};
+use super::AppState;
+
+/// Hex digits of an id; longer only when another run already has the same prefix.
+const ID_LEN: usize = 10;
+
+/// Runs a store keeps unless [`ShareStore::with_max_runs`] says otherwise.
+pub const DEFAULT_MAX_SHARES: usize = 100_000;
+
+pub struct ShareStore {
+  dir: PathBuf,
+  max_runs: usize,
+  /// Number of stored runs.
+  stored: AtomicUsize,
+  /// Held while saving, so two runs with the same id prefix get distinct ids.
+  write: Mutex<()>,
+}
+
+impl ShareStore {
+  /// Open the store in `dir`, creating the directory if needed.
+  pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir)?;
+    let stored = fs::read_dir(&dir)?
+      .filter_map(|e| e.ok()?.file_name().into_string().ok())
+      .filter(|n| n.strip_suffix(".json").is_some_and(valid_id))
+      .count();
+    Ok(Self {
+      dir,
+      max_runs: DEFAULT_MAX_SHARES,
+      stored: AtomicUsize::new(stored),
+      write: Mutex::new(()),
+    })
+  }
+
+  /// Refuse new runs once `max_runs` are stored.
+  #[must_use]
+  pub const fn with_max_runs(mut self, max_runs: usize) -> Self {
+    self.max_runs = max_runs;
+    self
+  }
+
+  pub fn dir(&self) -> &std::path::Path {
+    &self.dir
+  }
+
+  /// Save `run`; returns its id (the existing one if the same run was saved before). Fails with
+  /// [`io::ErrorKind::StorageFull`] when the store already holds its maximum of runs.
+  pub fn put(&self, run: &SharedRun) -> io::Result<String> {
+    let json = serde_json::to_string(run)?;
+    let mut hash = String::with_capacity(64);
+    for byte in Sha256::digest(json.as_bytes()) {
+      let _ = write!(hash, "{byte:02x}");
+    }
+    let _guard = self.write.lock().unwrap_or_else(PoisonError::into_inner);
+    for len in ID_LEN..=hash.len() {
+      let id = &hash[..len];
+      match self.get(id)? {
+        Some(saved) if saved == *run => return Ok(id.to_string()),
+        Some(_) => continue,
+        None => {}
+      }
+      if self.stored.load(Ordering::Relaxed) >= self.max_runs {
+        return Err(io::Error::new(
+          io::ErrorKind::StorageFull,
+          format!("holds its maximum of {} runs", self.max_runs),
+        ));
+      }
+      // Write then rename, so readers never see a partial file.
+      let tmp = self.dir.join(format!(".{id}.json.tmp"));
+      fs::write(&tmp, json)?;
+      fs::rename(&tmp, self.dir.join(format!("{id}.json")))?;
+      self.stored.fetch_add(1, Ordering::Relaxed);
+      return Ok(id.to_string());
+    }
+    Err(io::Error::other(format!(
+      "every id of hash {hash} is taken"
+    )))
+  }
+
+  /// The run saved as `id`; None when there is none (or `id` is not a valid id).
+  pub fn get(&self, id: &str) -> io::Result<Option<SharedRun>> {
+    if !valid_id(id) {
+      return Ok(None);
+    }
+    let path = self.dir.join(format!("{id}.json"));
+    let text = match fs::read_to_string(&path) {
+      Ok(text) => text,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(e),
+    };
+    serde_json::from_str(&text).map(Some).map_err(|e| {
+      io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {e}", path.display()),
+      )
+    })
+  }
+}
+
+/// Lowercase hex of [`ID_LEN`] to 64 digits.
+fn valid_id(id: &str) -> bool {
+  (ID_LEN..=64).contains(&id.len()) && id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[allow(clippy::needless_pass_by_value)] // used as `map_err(share_error)`
+fn share_error(e: io::Error) -> ApiError {
+  let status = if e.kind() == io::ErrorKind::StorageFull {
+    StatusCode::INSUFFICIENT_STORAGE
+  } else {
+    StatusCode::INTERNAL_SERVER_ERROR
+  };
+  message_error(status, format!("share store: {e}"), None)
+}
+
+/// Save a run for sharing. The program must parse; a run without `seed` gets a random one, so
+/// the link always reproduces the same output. `profile` is ignored.
+#[utoipa::path(
+  post,
+  path = "/share",
+  tag = "share",
+  request_body = RunRequest,
+  responses(
+    (status = 201, body = ShareCreated),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+    (status = 507, description = "Share store is full", body = ErrorResponse),
+  )
+)]
+pub async fn create_share(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<RunRequest>,
+) -> Result<(StatusCode, Json<ShareCreated>), ApiError> {
+  load_cached(&state, &body.program)?;
+  let run = SharedRun {
+    program: body.program,
+    input: body.options.input,
+    // Below 2^53, so the seed survives JavaScript numbers in the frontend.
+    seed: body
+      .options
+      .seed
+      .unwrap_or_else(|| rand::random::<u64>() >> 11),
+    vars: body.options.vars.into_iter().collect(),
+  };
+  let id = state.shares.put(&run).map_err(share_error)?;
+  Ok((
+    StatusCode::CREATED,
+    Json(ShareCreated { id, seed: run.seed }),
+  ))
+}
+
+/// A shared run with its output, run again now. Errors of the run, including the timeout, are
+/// reported in the body, as in `/run/batch`.
+#[utoipa::path(
+  get,
+  path = "/share/{id}",
+  tag = "share",
+  params(("id" = String, Path, description = "Id returned by `POST /share`")),
+  responses(
+    (status = 200, body = SharedResult),
+    (status = 400, description = "Shared program no longer parses", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 404, description = "No run shared under this id", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn get_share(
+  State(state): State<AppState>,
+  Path(id): Path<String>,
+) -> Result<Json<SharedResult>, ApiError> {
+  let run = state.shares.get(&id).map_err(share_error)?.ok_or_else(|| {
+    message_error(
+      StatusCode::NOT_FOUND,
+      format!("shared run \"{id}\" not found"),
+      None,
+    )
+  })?;
+  let program = load_cached(&state, &run.program)?;
+  let entry = run.program.entry.as_deref().map(normalize);
+  let options = RunOptions {
+    input: run.input.clone(),
+    seed: Some(run.seed),
+    vars: run.vars.clone().into_iter().collect(),
+    profile: false,
+  };
+  let limits = state.limits;
+  let item = blocking(limits, state.throttle.run_permit()?, move |deadline| {
+    Ok(run_item(
+      &state,
+      &program,
+      entry.as_deref(),
+      options,
+      deadline,
+      None,
+    ))
+  });
+  let (result, error) = match item.await {
+    Ok(item) => (item.result, item.error),
+    // A run cut off by the timeout is reported in the body, as in `/run/batch`.
+    Err((status, _)) if status == StatusCode::REQUEST_TIMEOUT => {
+      (None, Some(limits.timeout_error().1 .0))
+    }
+    Err(e) => return Err(e),
+  };
+  Ok(Json(SharedResult {
+    id,
+    run,
+    result,
+    error,
+  }))
+}
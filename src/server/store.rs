@@ -0,0 +1,198 @@
+//! On-disk store of named programs with version history.
+//!
+//! Every version is a separate file, `{dir}/{name}/{version}.json`, written once and never
+//! changed. Parsed programs are not kept here: runs go through the
+//! [`ParseCache`](super::ParseCache), keyed by the stored sources.
+
+use super::types::{SourceFiles, StoredVersion, VersionInfo};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Longest program name accepted.
+const MAX_NAME: usize = 64;
+
+#[derive(Debug)]
+pub enum StoreError {
+  /// Name with characters other than letters, digits, `_` and `-`.
+  InvalidName(String),
+  NotFound(String),
+  Io(String),
+}
+
+impl fmt::Display for StoreError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::InvalidName(name) => write!(
+        f,
+        "invalid program name \"{name}\": use 1-{MAX_NAME} letters, digits, _ or -"
+      ),
+      Self::NotFound(what) => write!(f, "{what} not found"),
+      Self::Io(e) => write!(f, "program store: {e}"),
+    }
+  }
+}
+
+impl From<io::Error> for StoreError {
+  fn from(e: io::Error) -> Self {
+    Self::Io(e.to_string())
+  }
+}
+
+pub struct ProgramStore {
+  dir: PathBuf,
+  /// Held while saving or deleting, so concurrent saves get distinct versions.
+  write: Mutex<()>,
+}
+
+impl ProgramStore {
+  /// Open the store in `dir`, creating the directory if needed.
+  pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir)?;
+    Ok(Self {
+      dir,
+      write: Mutex::new(()),
+    })
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Names of stored programs, sorted.
+  pub fn names(&self) -> Result<Vec<String>, StoreError> {
+    let mut names: Vec<String> = fs::read_dir(&self.dir)?
+      .filter_map(|e| e.ok()?.file_name().into_string().ok())
+      .filter(|n| valid_name(n) && !self.versions(n).unwrap_or_default().is_empty())
+      .collect();
+    names.sort();
+    Ok(names)
+  }
+
+  /// Versions of `name`, oldest first. The files are not read: see [`version_info`].
+  pub fn history(&self, name: &str) -> Result<Vec<VersionInfo>, StoreError> {
+    let dir = self.program_dir(name)?;
+    self
+      .versions(name)?
+      .into_iter()
+      .map(|version| version_info(&dir, version))
+      .collect()
+  }
+
+  /// Latest version of `name`.
+  pub fn latest(&self, name: &str) -> Result<VersionInfo, StoreError> {
+    let version = self.version(name, None)?;
+    version_info(&self.program_dir(name)?, version)
+  }
+
+  /// `version` of `name` (latest if None).
+  pub fn get(&self, name: &str, version: Option<u32>) -> Result<StoredVersion, StoreError> {
+    let version = self.version(name, version)?;
+    let path = self.program_dir(name)?.join(format!("{version}.json"));
+    let text = fs::read_to_string(&path).map_err(|e| match e.kind() {
+      io::ErrorKind::NotFound => {
+        StoreError::NotFound(format!("program \"{name}\" version {version}"))
+      }
+      _ => e.into(),
+    })?;
+    serde_json::from_str(&text).map_err(|e| StoreError::Io(format!("{}: {e}", path.display())))
+  }
+
+  /// Save `program` as the next version of `name`; returns the new version.
+  pub fn put(&self, name: &str, program: SourceFiles) -> Result<u32, StoreError> {
+    let dir = self.program_dir(name)?;
+    let _guard = self.write.lock().unwrap_or_else(PoisonError::into_inner);
+    fs::create_dir_all(&dir)?;
+    let version = self.versions(name)?.last().map_or(1, |v| v + 1);
+    let created = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map_or(0, |d| d.as_secs());
+    let modified = UNIX_EPOCH + Duration::from_secs(created);
+    let stored = StoredVersion {
+      version,
+      created,
+      program,
+    };
+    let json = serde_json::to_string_pretty(&stored).map_err(|e| StoreError::Io(e.to_string()))?;
+    // Write then rename, so readers never see a partial file.
+    let tmp = dir.join(format!(".{version}.json.tmp"));
+    fs::write(&tmp, json)?;
+    fs::File::options()
+      .write(true)
+      .open(&tmp)?
+      .set_modified(modified)?;
+    fs::rename(&tmp, dir.join(format!("{version}.json")))?;
+    Ok(version)
+  }
+
+  /// Delete `name` with all its versions.
+  pub fn delete(&self, name: &str) -> Result<(), StoreError> {
+    let dir = self.program_dir(name)?;
+    let _guard = self.write.lock().unwrap_or_else(PoisonError::into_inner);
+    if !dir.is_dir() {
+      return Err(StoreError::NotFound(format!("program \"{name}\"")));
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+  }
+
+  fn program_dir(&self, name: &str) -> Result<PathBuf, StoreError> {
+    if valid_name(name) {
+      Ok(self.dir.join(name))
+    } else {
+      Err(StoreError::InvalidName(name.to_string()))
+    }
+  }
+
+  /// Saved version numbers of `name`, ascending.
+  fn versions(&self, name: &str) -> Result<Vec<u32>, StoreError> {
+    let dir = self.program_dir(name)?;
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+      Err(e) => return Err(e.into()),
+    };
+    let mut versions: Vec<u32> = entries
+      .filter_map(|e| {
+        let name = e.ok()?.file_name().into_string().ok()?;
+        name.strip_suffix(".json")?.parse().ok()
+      })
+      .collect();
+    versions.sort_unstable();
+    Ok(versions)
+  }
+
+  /// `version` if given, else the latest one; `NotFound` when `name` has no versions.
+  pub fn version(&self, name: &str, version: Option<u32>) -> Result<u32, StoreError> {
+    if let Some(v) = version {
+      self.program_dir(name)?;
+      return Ok(v);
+    }
+    self
+      .versions(name)?
+      .last()
+      .copied()
+      .ok_or_else(|| StoreError::NotFound(format!("program \"{name}\"")))
+  }
+}
+
+/// `version` in `dir` with its creation time. A version file is written once, with its
+/// modification time set to `created`, so the time comes from the file's metadata.
+fn version_info(dir: &Path, version: u32) -> Result<VersionInfo, StoreError> {
+  let modified = fs::metadata(dir.join(format!("{version}.json")))?.modified()?;
+  let created = modified
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_secs());
+  Ok(VersionInfo { version, created })
+}
+
+fn valid_name(name: &str) -> bool {
+  (1..=MAX_NAME).contains(&name.len())
+    && name
+      .bytes()
+      .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
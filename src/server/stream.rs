@@ -0,0 +1,242 @@
+//! `/run/stream`: results as Server-Sent Events while they are generated.
+//!
+//! Runs happen on a blocking thread that sends events into a small channel. When the client
+//! disconnects the channel closes, which stops the loop between runs and aborts the current run
+//! through an [`EvalHook`]. The stream ends at `limits.stream_timeout`, and is dropped when the
+//! client stops reading until then.
+
+use crate::interpreter::HookContext;
+use crate::resolve::normalize;
+use crate::{EvalHook, Program};
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+use super::error::ErrorResponse;
+use super::extract::ApiJson;
+use super::handlers::{batch_runs, execute, load_cached, run_item, ApiError};
+use super::limits::Deadline;
+use super::types::{BatchRequest, RunOptions, StreamChunk, StreamDone, StreamSample};
+use super::AppState;
+
+/// Largest `chunk` event of a single run's output, in bytes.
+const CHUNK_BYTES: usize = 16 * 1024;
+
+/// Stream a batch (`count` or `items`, one `sample` event per run) or a single run's output.
+///
+/// Without `count` and `items` the output comes as `chunk` events once the run has finished; a
+/// single run is not streamed while it runs. A stream cut off by its timeout gets an `error`
+/// event. Always ends with a `done` event. Load errors are returned as a plain JSON error before
+/// the stream starts.
+#[utoipa::path(
+  post,
+  path = "/run/stream",
+  tag = "run",
+  request_body = BatchRequest,
+  responses(
+    (status = 200, description = "Server-Sent Events: `sample` (StreamSample), `chunk` (StreamChunk), `error` (ErrorResponse), then `done` (StreamDone)", content_type = "text/event-stream"),
+    (status = 400, description = "Bad request or program that does not parse", body = ErrorResponse),
+    (status = 401, description = "API key missing or invalid", body = ErrorResponse),
+    (status = 413, description = "Request body too large", body = ErrorResponse),
+    (status = 429, description = "Rate limited, over quota or too many runs at once", body = ErrorResponse),
+  )
+)]
+pub async fn run_stream(
+  State(state): State<AppState>,
+  ApiJson(body): ApiJson<BatchRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+  let runs = batch_runs(&body, state.limits.stream_runs)?;
+  let program = load_cached(&state, &body.program)?;
+  let entry = body.program.entry.as_deref().map(normalize);
+  let permit = state.throttle.run_permit()?;
+  let (tx, rx) = mpsc::channel(16);
+  let runtime = Handle::current();
+  tokio::task::spawn_blocking(move || {
+    let _permit = permit;
+    let job = Job {
+      state: &state,
+      program: &program,
+      entry: entry.as_deref(),
+      tx: &tx,
+      runtime: &runtime,
+      stream: Deadline::after(state.limits.stream_timeout),
+    };
+    let start = Instant::now();
+    let done = match runs {
+      Some(runs) => job.samples(runs, body.unique),
+      None => job.single(&body.options),
+    };
+    if let Some(done) = done {
+      let elapsed_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+      job.send("done", &StreamDone { elapsed_ms, ..done });
+    }
+  });
+  let events = ReceiverStream::new(rx).map(Ok::<_, Infallible>);
+  // Tell a proxy in front (nginx) not to buffer the events.
+  let headers = [("x-accel-buffering", "no")];
+  Ok((headers, Sse::new(events).keep_alive(KeepAlive::default())))
+}
+
+struct Job<'a> {
+  state: &'a AppState,
+  program: &'a Arc<Program>,
+  entry: Option<&'a str>,
+  tx: &'a mpsc::Sender<Event>,
+  runtime: &'a Handle,
+  /// When the whole stream ends.
+  stream: Deadline,
+}
+
+impl Job<'_> {
+  /// Send an event; false when the client is gone, or did not make room for the event before
+  /// the stream's deadline.
+  fn send(&self, name: &str, data: &impl Serialize) -> bool {
+    let Ok(event) = Event::default().event(name).json_data(data) else {
+      return false;
+    };
+    let sent = self.tx.send_timeout(event, self.stream.remaining());
+    self.runtime.block_on(sent).is_ok()
+  }
+
+  /// Each run gets the request timeout, cut short by the end of the stream.
+  fn deadline(&self) -> Deadline {
+    Deadline::after(self.state.limits.timeout.min(self.stream.remaining()))
+  }
+
+  /// Send the `error` event of a stream that ran out of time.
+  fn timed_out(&self) -> bool {
+    let (_, Json(error)) = self.state.limits.stream_timeout_error();
+    self.send("error", &error)
+  }
+
+  /// One `sample` per run; None when the client disconnected.
+  fn samples(&self, runs: Vec<RunOptions>, unique: bool) -> Option<StreamDone> {
+    let mut done = StreamDone::default();
+    let mut results = HashSet::new();
+    for (index, options) in runs.into_iter().enumerate() {
+      if self.stream.expired() {
+        return self.timed_out().then_some(done);
+      }
+      let mut cancel = Cancel(self.tx);
+      let deadline = self.deadline();
+      let item = run_item(
+        self.state,
+        self.program,
+        self.entry,
+        options,
+        &deadline,
+        Some(&mut cancel),
+      );
+      if self.tx.is_closed() {
+        return None;
+      }
+      done.runs += 1;
+      if item.error.is_some() {
+        done.errors += 1;
+      } else if unique
+        && item
+          .result
+          .as_ref()
+          .is_some_and(|r| !results.insert(r.clone()))
+      {
+        done.duplicates += 1;
+        continue;
+      }
+      if !self.send("sample", &StreamSample { index, item }) {
+        return None;
+      }
+    }
+    Some(done)
+  }
+
+  /// The output of one run in `chunk` events (or an `error` event); the trace goes to `done`.
+  fn single(&self, options: &RunOptions) -> Option<StreamDone> {
+    let mut cancel = Cancel(self.tx);
+    let deadline = self.deadline();
+    let out = execute(
+      self.state,
+      self.program,
+      self.entry,
+      options,
+      &deadline,
+      Some(&mut cancel),
+    );
+    if self.tx.is_closed() {
+      return None;
+    }
+    match out {
+      Ok(response) => {
+        for text in chunks(&response.result, CHUNK_BYTES) {
+          if !self.send("chunk", &StreamChunk { text }) {
+            return None;
+          }
+        }
+        Some(StreamDone {
+          runs: 1,
+          trace: Some(response.trace),
+          profile: response.profile,
+          ..StreamDone::default()
+        })
+      }
+      Err((_, Json(error))) => {
+        let sent = if self.stream.expired() {
+          self.timed_out()
+        } else {
+          self.send("error", &error)
+        };
+        if !sent {
+          return None;
+        }
+        Some(StreamDone {
+          runs: 1,
+          errors: 1,
+          ..StreamDone::default()
+        })
+      }
+    }
+  }
+}
+
+/// Aborts a run once the client has disconnected.
+struct Cancel<'a>(&'a mpsc::Sender<Event>);
+
+impl EvalHook for Cancel<'_> {
+  fn before(&mut self, _ctx: &HookContext<'_>) -> Result<(), String> {
+    if self.0.is_closed() {
+      Err("cancelled: client disconnected".to_string())
+    } else {
+      Ok(())
+    }
+  }
+}
+
+/// Split `text` into pieces of at most `max` bytes (more only for a single wider character),
+/// on character boundaries.
+fn chunks(text: &str, max: usize) -> impl Iterator<Item = &str> {
+  let mut rest = text;
+  std::iter::from_fn(move || {
+    if rest.is_empty() {
+      return None;
+    }
+    let mut end = max.min(rest.len());
+    while !rest.is_char_boundary(end) {
+      end -= 1;
+    }
+    if end == 0 {
+      end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+    }
+    let (chunk, tail) = rest.split_at(end);
+    rest = tail;
+    Some(chunk)
+  })
+}
@@ -1,19 +1,84 @@
 //! Request/response types for API.
 
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
 
 #[derive(Clone)]
 pub struct AppState {
   pub builtins: std::sync::Arc<std::collections::HashMap<String, crate::builtins::BuiltinFn>>,
+  /// Named programs (`/programs`).
+  pub store: std::sync::Arc<super::ProgramStore>,
+  /// Parsed programs of `/run` and `/check`, keyed by their sources.
+  pub cache: std::sync::Arc<super::ParseCache>,
+  pub limits: super::Limits,
+  /// Rate limits and the concurrent run cap.
+  pub throttle: std::sync::Arc<super::Throttle>,
+  /// `/examples`, loaded at startup (see [`load_examples`](super::load_examples)).
+  pub examples: std::sync::Arc<Vec<ExampleItem>>,
+  /// Request and run metrics (`/metrics`).
+  pub metrics: std::sync::Arc<super::Metrics>,
+  /// API keys; open when none are configured.
+  pub auth: std::sync::Arc<super::Auth>,
+  /// Runs saved by `/share`.
+  pub shares: std::sync::Arc<super::ShareStore>,
 }
 
-#[derive(Deserialize)]
-pub struct RunRequest {
+impl AppState {
+  /// State with programs in `{dir}/programs`, shared runs in `{dir}/shares`, default limits and
+  /// no rate limits, examples or API keys. Set other fields with struct update syntax.
+  pub fn in_dir(dir: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+    let dir = dir.as_ref();
+    Ok(Self {
+      builtins: std::sync::Arc::new(crate::default_registry()),
+      store: std::sync::Arc::new(super::ProgramStore::open(dir.join("programs"))?),
+      cache: std::sync::Arc::default(),
+      limits: super::Limits::default(),
+      throttle: std::sync::Arc::new(super::Throttle::new(super::RateLimits {
+        routes: HashMap::new(),
+        ..super::RateLimits::default()
+      })),
+      examples: std::sync::Arc::default(),
+      metrics: std::sync::Arc::default(),
+      auth: std::sync::Arc::default(),
+      shares: std::sync::Arc::new(super::ShareStore::open(dir.join("shares"))?),
+    })
+  }
+}
+
+/// Program source: either `source` alone, or a virtual filesystem `files` (path → source) with
+/// the `entry` file to start from. Includes are resolved against `files`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Hash, ToSchema)]
+pub struct SourceFiles {
+  #[serde(default)]
   pub source: String,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub files: BTreeMap<String, String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub entry: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RunRequest {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+  #[serde(flatten)]
+  pub options: RunOptions,
+}
+
+/// How to run a program: event input, seed, initial params and profiling.
+#[derive(Deserialize, Default, Clone, ToSchema)]
+pub struct RunOptions {
   #[serde(default)]
   pub input: Option<String>,
   #[serde(default, deserialize_with = "deserialize_seed")]
   pub seed: Option<u64>,
+  /// Initial params of the main branch and events (`:name`).
+  #[serde(default)]
+  pub vars: HashMap<String, String>,
+  /// Return an evaluation profile (call counts and timings) with the result.
+  #[serde(default)]
+  pub profile: bool,
 }
 
 fn deserialize_seed<'de, D>(d: D) -> Result<Option<u64>, D::Error>
@@ -42,25 +107,210 @@ where
   d.deserialize_option(SeedVisitor)
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct RunResponse {
   pub result: String,
   pub trace: Vec<crate::ast::Span>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub profile: Option<crate::Profile>,
 }
 
-#[derive(Serialize)]
+/// Many runs of one program: `count` runs (seeds `seed`, `seed + 1`, … when `seed` is given),
+/// or one run per item of `items`.
+#[derive(Deserialize, ToSchema)]
+pub struct BatchRequest {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+  #[serde(default)]
+  pub count: Option<usize>,
+  #[serde(default)]
+  pub items: Option<Vec<RunOptions>>,
+  /// Options shared by the `count` runs.
+  #[serde(flatten)]
+  pub options: RunOptions,
+  /// Drop runs whose result repeats an earlier one.
+  #[serde(default)]
+  pub unique: bool,
+}
+
+/// One run of a batch: the result, or the error of that run.
+#[derive(Serialize, ToSchema)]
+pub struct BatchItem {
+  /// Seed the run used (pass it to `/run` to reproduce the result).
+  pub seed: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub result: Option<String>,
+  #[serde(flatten, skip_serializing_if = "Option::is_none")]
+  #[schema(schema_with = super::error::optional_error_schema)]
+  pub error: Option<super::error::ErrorResponse>,
+}
+
+/// `sample` event of `/run/stream`: one run of a batch.
+#[derive(Serialize, ToSchema)]
+pub struct StreamSample {
+  pub index: usize,
+  #[serde(flatten)]
+  pub item: BatchItem,
+}
+
+/// `chunk` event of `/run/stream`: the next piece of a single run's output.
+#[derive(Serialize, ToSchema)]
+pub struct StreamChunk<'a> {
+  pub text: &'a str,
+}
+
+/// Last event of `/run/stream`.
+#[derive(Serialize, Default, ToSchema)]
+pub struct StreamDone {
+  pub runs: usize,
+  pub errors: usize,
+  /// Runs dropped by `unique`.
+  pub duplicates: usize,
+  pub elapsed_ms: u64,
+  /// Trace of a single run.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub trace: Option<Vec<crate::ast::Span>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub profile: Option<crate::Profile>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
 pub struct ExampleItem {
   pub id: String,
   pub name: String,
   pub source: String,
 }
 
-#[derive(Deserialize)]
-pub struct FormatRequest {
+#[derive(Serialize, ToSchema)]
+pub struct StdlibModule {
+  /// Include path, e.g. `std:en/names`.
+  pub include: String,
   pub source: String,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct FormatRequest {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct FormatResponse {
+  /// The formatted `source` (or `entry` file).
   pub formatted: String,
+  /// Every file of `files`, formatted (omitted without `files`).
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  pub files: BTreeMap<String, String>,
+}
+
+/// One saved version of a named program, as stored on disk.
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct StoredVersion {
+  pub version: u32,
+  /// Unix time (seconds) the version was saved.
+  pub created: u64,
+  #[serde(flatten)]
+  pub program: SourceFiles,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionInfo {
+  pub version: u32,
+  pub created: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProgramSummary {
+  pub name: String,
+  /// Latest version.
+  pub version: u32,
+  pub updated: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProgramDetail {
+  pub name: String,
+  #[serde(flatten)]
+  pub current: StoredVersion,
+  /// Every version, oldest first.
+  pub versions: Vec<VersionInfo>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SavedProgram {
+  pub name: String,
+  pub version: u32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct StoredRunRequest {
+  /// Version to run (latest if omitted).
+  #[serde(default)]
+  pub version: Option<u32>,
+  #[serde(flatten)]
+  pub options: RunOptions,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AnalyzeRequest {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+}
+
+/// A position in the entry source, for `/complete` and `/hover`.
+#[derive(Deserialize, ToSchema)]
+pub struct CursorRequest {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+  /// 1-based line.
+  pub line: u32,
+  /// 1-based column, in bytes.
+  pub column: u32,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CheckRequest {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CheckResponse {
+  /// Functions and events after includes are resolved.
+  pub functions: usize,
+  pub events: usize,
+}
+
+/// A run saved by `/share`: the program with the input, vars and seed it ran with, enough to
+/// reproduce its output.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct SharedRun {
+  #[serde(flatten)]
+  pub program: SourceFiles,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub input: Option<String>,
+  pub seed: u64,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub vars: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShareCreated {
+  /// Id for `/share/{id}`.
+  pub id: String,
+  /// Seed of the shared run (chosen at random, below 2^53, when the request had none).
+  pub seed: u64,
+}
+
+/// A shared run with its output, computed again on every read.
+#[derive(Serialize, ToSchema)]
+pub struct SharedResult {
+  pub id: String,
+  #[serde(flatten)]
+  pub run: SharedRun,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub result: Option<String>,
+  #[serde(flatten, skip_serializing_if = "Option::is_none")]
+  #[schema(schema_with = super::error::optional_error_schema)]
+  pub error: Option<super::error::ErrorResponse>,
 }
@@ -0,0 +1,40 @@
+//! Standard library of generator modules embedded in the binary (`stdlib/*.branchy`).
+//!
+//! Modules are included as `include "std:en/names";` and resolved by `resolve_includes` before
+//! the caller's resolver is consulted, so they also work where file includes are unavailable
+//! (HTTP API). The `en/` and `ru/` variants of a module define the same function names.
+
+/// Prefix of library includes (embedded modules, then search directories).
+pub const PREFIX: &str = "std:";
+
+/// (name, source) of every embedded module, sorted by name.
+pub const MODULES: &[(&str, &str)] = &[
+  (
+    "en/adjectives",
+    include_str!("../stdlib/en/adjectives.branchy"),
+  ),
+  ("en/colors", include_str!("../stdlib/en/colors.branchy")),
+  ("en/names", include_str!("../stdlib/en/names.branchy")),
+  ("en/places", include_str!("../stdlib/en/places.branchy")),
+  ("en/surnames", include_str!("../stdlib/en/surnames.branchy")),
+  ("lorem", include_str!("../stdlib/lorem.branchy")),
+  (
+    "ru/adjectives",
+    include_str!("../stdlib/ru/adjectives.branchy"),
+  ),
+  ("ru/colors", include_str!("../stdlib/ru/colors.branchy")),
+  ("ru/names", include_str!("../stdlib/ru/names.branchy")),
+  ("ru/places", include_str!("../stdlib/ru/places.branchy")),
+  ("ru/surnames", include_str!("../stdlib/ru/surnames.branchy")),
+];
+
+/// Source of the embedded module for an include path like `std:en/names`
+/// (a trailing `.branchy` is accepted).
+pub fn lookup(include: &str) -> Option<&'static str> {
+  let name = include.strip_prefix(PREFIX)?;
+  let name = name.strip_suffix(".branchy").unwrap_or(name);
+  MODULES
+    .iter()
+    .find(|(n, _)| *n == name)
+    .map(|(_, src)| *src)
+}
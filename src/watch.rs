@@ -0,0 +1,81 @@
+//! Polling file watcher for `--watch`: detects changes to a set of files by size and mtime.
+//!
+//! Polling keeps the CLI free of platform-specific notification APIs; templates are small and
+//! few, so a stat per file every poll interval is cheap.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Size and modification time of a file (None when it is missing or unreadable).
+type Stamp = Option<(u64, SystemTime)>;
+
+pub struct Watcher {
+  files: Vec<PathBuf>,
+  stamps: Vec<Stamp>,
+  /// How often files are checked.
+  pub poll: Duration,
+  /// How long files must stay unchanged after a change before it is reported.
+  pub debounce: Duration,
+}
+
+impl Watcher {
+  /// Watch `files` (duplicates are ignored), taking their current state as the baseline.
+  pub fn new(mut files: Vec<PathBuf>) -> Self {
+    files.sort();
+    files.dedup();
+    let stamps = files.iter().map(stamp).collect();
+    Self {
+      files,
+      stamps,
+      poll: Duration::from_millis(200),
+      debounce: Duration::from_millis(300),
+    }
+  }
+
+  pub fn files(&self) -> &[PathBuf] {
+    &self.files
+  }
+
+  /// Files whose state differs from the baseline; the baseline is updated.
+  pub fn changed(&mut self) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    for (file, old) in self.files.iter().zip(self.stamps.iter_mut()) {
+      let new = stamp(file);
+      if new != *old {
+        *old = new;
+        out.push(file.clone());
+      }
+    }
+    out
+  }
+
+  /// Block until some file changes and then stays unchanged for `debounce`
+  /// (editors often write in several steps). Returns the changed files.
+  pub fn wait(&mut self) -> Vec<PathBuf> {
+    let mut changed = loop {
+      thread::sleep(self.poll);
+      let c = self.changed();
+      if !c.is_empty() {
+        break c;
+      }
+    };
+    loop {
+      thread::sleep(self.debounce);
+      let more = self.changed();
+      if more.is_empty() {
+        break;
+      }
+      changed.extend(more);
+    }
+    changed.sort();
+    changed.dedup();
+    changed
+  }
+}
+
+fn stamp(path: &PathBuf) -> Stamp {
+  let meta = fs::metadata(path).ok()?;
+  Some((meta.len(), meta.modified().ok()?))
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use branchy::analysis::{analyze, Analysis, CallEdge, Severity, SymbolKind};
+use branchy::{default_registry, parse_program, resolve_includes};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use common::run_with_seed;
+
+fn analyze_src(src: &str) -> Analysis {
+  analyze(&parse_program(src).unwrap(), &default_registry())
+}
+
+#[test]
+fn analysis_lists_symbols_calls_and_builtins() {
+  let src = r#"
+!greet(:x) = [ hi :x; bye :x; ]
+!shout(:x) = [ !upper(:x); !greet(!lower(:x)); ]
+@ev = [ e1; !shout(e); ]
+~"ab+" = [ re; ]
+[ !greet(world); !greet(you); !len(abc); ]
+"#;
+  let analysis = analyze_src(src);
+  let symbols: Vec<_> = analysis
+    .symbols
+    .iter()
+    .map(|s| (s.kind, s.name.as_str(), s.params.len(), s.outputs))
+    .collect();
+  assert_eq!(
+    symbols,
+    [
+      (SymbolKind::Function, "!greet", 1, Some(2)),
+      (SymbolKind::Function, "!shout", 1, Some(3)),
+      (SymbolKind::Event, "@ev", 0, Some(4)),
+      (SymbolKind::Event, "~\"ab+\"", 0, Some(1)),
+    ]
+  );
+  assert_eq!(analysis.symbols[0].span.unwrap().start_line, 2);
+  let edge = |from: &str, to: &str, calls| CallEdge {
+    from: from.to_string(),
+    to: to.to_string(),
+    calls,
+  };
+  assert_eq!(
+    analysis.calls,
+    [
+      edge("!shout", "!greet", 1),
+      edge("@ev", "!shout", 1),
+      edge("main", "!greet", 2),
+    ]
+  );
+  assert_eq!(analysis.builtins, ["len", "lower", "upper"]);
+  assert_eq!(analysis.outputs, Some(5));
+  assert!(
+    analysis.diagnostics.is_empty(),
+    "{:?}",
+    analysis.diagnostics
+  );
+}
+
+#[test]
+fn outputs_bound_the_distinct_results() {
+  let src = "!pick(:x) = [ :x; [ \"-\" + :x; \"+\" + :x; ]; ]\n[ !pick(x); \"y\" * 1..2; [a-c]; v <p|q> * 2; ]";
+  let analysis = analyze_src(src);
+  // pick: 3, repeat: 2, char block: 3, inline twice: 4.
+  assert_eq!(analysis.outputs, Some(12));
+  let program = parse_program(src).unwrap();
+  let seen: HashSet<String> = (0..2000)
+    .map(|seed| run_with_seed(&program, seed))
+    .collect();
+  assert_eq!(seen.len(), 12, "{seen:?}");
+}
+
+#[test]
+fn outputs_of_templates_recursion_and_large_spaces() {
+  let template = r"
+!wrap(:_) = [ a; ...:extra; ]
+[ wrap :_ { :extra = [ x; y; z; ]; }; ]
+";
+  assert_eq!(analyze_src(template).outputs, Some(4));
+  let bound = "[ hello :who { :who = [ world; human; ]; }; ]";
+  assert_eq!(analyze_src(bound).outputs, Some(2));
+  let optional = "[ say :?a :b; ]";
+  assert_eq!(analyze_src(optional).outputs, Some(2));
+  let recursive = "!f() = [ x; !f() + x; ]\n[ !f(); ]";
+  let analysis = analyze_src(recursive);
+  assert_eq!(analysis.outputs, None);
+  assert_eq!(analysis.symbols[0].outputs, None);
+  assert_eq!(analyze_src("[ [a-z:100]; ]").outputs, Some(u64::MAX));
+  assert_eq!(analyze_src("[ [a-z:2]; ]").outputs, Some(26 * 26));
+  assert_eq!(analyze_src("[ [ x; ] * :n; ]").outputs, None);
+  // A main of several branches prints one choice of each.
+  assert_eq!(analyze_src("[ [ a; b; ]; [ c; d; e; ]; ]").outputs, Some(6));
+}
+
+#[test]
+fn outputs_of_deep_template_chains_are_counted_once_per_call() {
+  // Each template calls the one below twice; counting every call again would take 2^60 steps.
+  let mut src = "!f0(:y) = [ :y; ]\n".to_string();
+  for i in 1..=60 {
+    let below = i - 1;
+    writeln!(src, "!f{i}(:y) = [ f{below} :y; f{below} :y; ]").unwrap();
+  }
+  src += "[ f60 :y { :y = [ a; b; ]; }; ]\n";
+  assert_eq!(analyze_src(&src).outputs, Some(1 << 61));
+}
+
+#[test]
+fn diagnostics_report_errors_and_warnings() {
+  let src = r#"
+!used(:x) = [ :x; ]
+!unused() = [ never; ]
+!loop() = [ !loop(); ]
+~"(" = [ broken; ]
+[ !used(a, b); !missing(); x * many; tag { :extra = [ y; ]; }; ]
+"#;
+  let analysis = analyze_src(src);
+  let diagnostics: Vec<_> = analysis
+    .diagnostics
+    .iter()
+    .map(|d| (d.severity, d.message.as_str(), d.span.map(|s| s.start_line)))
+    .collect();
+  assert_eq!(
+    diagnostics,
+    [
+      (
+        Severity::Warning,
+        "invalid regex \"(\": the event never matches",
+        Some(5)
+      ),
+      (
+        Severity::Error,
+        "function used expects 1 arguments, got 2",
+        Some(6)
+      ),
+      (Severity::Error, "unknown function: missing", Some(6)),
+      (
+        Severity::Error,
+        "repeat count must be integer or range (e.g. 1..3)",
+        Some(6)
+      ),
+      (
+        Severity::Error,
+        "call 'tag' has block parameter(s) (:extra) but no function definition; block \
+         parameters must be used in a template",
+        Some(6)
+      ),
+      (
+        Severity::Warning,
+        "function !unused is never called",
+        Some(3)
+      ),
+      (Severity::Warning, "function !loop is never called", Some(4)),
+    ]
+  );
+}
+
+#[test]
+fn included_functions_are_not_reported_unused() {
+  let program = resolve_includes(
+    parse_program("include \"std:en/names\";\n[ a; ]").unwrap(),
+    |p| Err(format!("no file {p}")),
+  )
+  .unwrap();
+  let analysis = analyze(&program, &default_registry());
+  assert!(
+    analysis.diagnostics.is_empty(),
+    "{:?}",
+    analysis.diagnostics
+  );
+  assert!(analysis.symbols.iter().all(|s| s.file.is_some()));
+}
+
+#[test]
+fn failed_analysis_has_the_load_error() {
+  let error = parse_program("[ a").unwrap_err();
+  let analysis = Analysis::failed(error.clone());
+  assert_eq!(analysis.diagnostics.len(), 1);
+  assert_eq!(analysis.diagnostics[0].severity, Severity::Error);
+  assert_eq!(analysis.diagnostics[0].message, error.message);
+  assert_eq!(analysis.diagnostics[0].span, error.span);
+  assert!(analysis.symbols.is_empty());
+}
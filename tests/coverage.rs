@@ -0,0 +1,111 @@
+mod common;
+
+use branchy::coverage::{Coverage, PointKind};
+use branchy::{parse_program, resolve_includes};
+
+use common::run_with_seed_and_input;
+
+fn collect(src: &str, runs: u64, input: Option<&str>) -> Coverage {
+  let p = parse_program(src).unwrap();
+  let mut cov = Coverage::new(&p);
+  for seed in 0..runs {
+    let (_, trace) = run_with_seed_and_input(&p, seed, input);
+    cov.record(&trace);
+  }
+  cov
+}
+
+#[test]
+fn coverage_all_alternatives_chosen() {
+  let cov = collect("[ a; b; c; ]", 200, None);
+  assert_eq!(cov.runs, 200);
+  assert_eq!(cov.branch_summary(), (3, 3));
+  assert_eq!(cov.never_chosen().count(), 0);
+  let total: u64 = cov.points.iter().map(|p| p.hits).sum();
+  assert_eq!(total, 200, "each run chooses exactly one alternative");
+}
+
+#[test]
+fn coverage_reports_unreached_event_and_function() {
+  let src = r"
+!used(:x) = [ one :x; ]
+!unused() = [ never; ]
+@ev = [ e1; e2; ]
+[ !used(1); hi <x|y>; ]
+";
+  let cov = collect(src, 100, None);
+  let never: Vec<_> = cov.never_chosen().map(|p| p.owner.as_str()).collect();
+  assert_eq!(never, ["!unused", "@ev", "@ev"]);
+  let used = cov
+    .points
+    .iter()
+    .find(|p| p.owner == "!used" && p.kind == PointKind::Function);
+  assert!(used.unwrap().hits > 0);
+  assert_eq!(cov.function_summary(), (1, 2));
+  let inline: Vec<_> = cov
+    .points
+    .iter()
+    .filter(|p| p.kind == PointKind::InlineOption)
+    .collect();
+  assert_eq!(inline.len(), 2);
+  assert!(inline.iter().all(|p| p.hits > 0));
+}
+
+#[test]
+fn coverage_lcov_and_annotate() {
+  let src = "!f() = [ x; ]\n[ !f(); ]\n";
+  let cov = collect(src, 10, None);
+  let lcov = cov.to_lcov("t.branchy");
+  assert!(lcov.starts_with("TN:\nSF:t.branchy\n"));
+  assert!(lcov.contains("FN:1,!f\n"));
+  assert!(lcov.contains("FNDA:10,!f\n"));
+  assert!(lcov.contains("BRH:2\n"));
+  assert!(lcov.ends_with("end_of_record\n"));
+  let annotated = cov.annotate(src);
+  let lines: Vec<_> = annotated.lines().collect();
+  assert_eq!(lines[0], "       10 | !f() = [ x; ]");
+  assert_eq!(lines[1], "       10 | [ !f(); ]");
+}
+
+#[test]
+fn coverage_ignores_included_files() {
+  let lib = "\n!f() =                     [ x; y; ]\n[ lib; ]\n";
+  let src = "include \"lib.branchy\";\n!unused() = [ aaaaaaaaaaaaaaaaaaaaaaaa; b; ]\n[ !f(); ]\n";
+  let p = resolve_includes(parse_program(src).unwrap(), |_| Ok(lib.to_string())).unwrap();
+  let mut cov = Coverage::new(&p);
+  for seed in 0..20 {
+    let (_, trace) = run_with_seed_and_input(&p, seed, None);
+    cov.record(&trace);
+  }
+  // Spans of the included !f lie on line 2 of lib.branchy, inside !unused's first alternative.
+  let never: Vec<_> = cov.never_chosen().map(|p| p.owner.as_str()).collect();
+  assert_eq!(never, ["!unused", "!unused"]);
+  assert!(cov.points.iter().all(|p| p.owner != "!f"));
+  let annotated = cov.annotate(src);
+  let lines: Vec<_> = annotated.lines().collect();
+  assert_eq!(lines[0], "          | include \"lib.branchy\";");
+}
+
+#[test]
+fn coverage_ignores_data_spread_records() {
+  let names = "Ann\nBob\nCid\n";
+  let src = "[ a; ...lines \"names.txt\"; ]\n";
+  let p = resolve_includes(parse_program(src).unwrap(), |_| Ok(names.to_string())).unwrap();
+  let mut cov = Coverage::new(&p);
+  let mut traced = std::collections::BTreeSet::new();
+  for seed in 0..40 {
+    let (out, trace) = run_with_seed_and_input(&p, seed, None);
+    cov.record(&trace);
+    if out != "a" {
+      traced.extend(trace.iter().map(|s| (out.clone(), s.file, s.start_line)));
+    }
+  }
+  // Records trace their own line of the data file, not the spread, and get no points.
+  let lines: Vec<_> = traced
+    .iter()
+    .map(|(_, file, line)| (*file, *line))
+    .collect();
+  assert_eq!(lines, [(1, 1), (1, 2), (1, 3)], "{traced:?}");
+  assert_eq!(cov.branch_summary(), (1, 1));
+  assert_eq!(cov.points[0].of, 4);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use branchy::{
+  deserialize_program, parse_program, resolve_includes_with, serialize_program, MemoryResolver,
+  Program,
+};
+use common::run_with_seed;
+use std::collections::BTreeSet;
+
+fn resolve(main_src: &str, files: &[(&str, &str)]) -> Result<Program, String> {
+  let p = parse_program(main_src).unwrap();
+  resolve_includes_with(p, &files.iter().copied().collect::<MemoryResolver>())
+}
+
+fn outputs(program: &Program) -> BTreeSet<String> {
+  (0..60).map(|seed| run_with_seed(program, seed)).collect()
+}
+
+#[test]
+fn lines_spread_reads_non_empty_lines() {
+  let files = [("cities.txt", "Paris\r\n\n  Oslo \nRome\n")];
+  let program = resolve("[ ...lines \"cities.txt\"; Lima; ]", &files).unwrap();
+  assert_eq!(
+    outputs(&program),
+    BTreeSet::from(["Paris", "Oslo", "Rome", "Lima"].map(String::from))
+  );
+
+  let program = resolve("[ \"to \" + ...lines \"cities.txt\"; ]", &files).unwrap();
+  assert!(outputs(&program).contains("to Oslo"));
+}
+
+#[test]
+fn csv_spread_reads_a_column() {
+  let files = [(
+    "people.csv",
+    "id,name,role\n1,Ann,admin\n2,\"Smith, Bob\",user\n3,\"Say \"\"hi\"\"\",user\n4,,user\n",
+  )];
+  let program = resolve("[ ...csv \"people.csv\" column name; ]", &files).unwrap();
+  assert_eq!(
+    outputs(&program),
+    BTreeSet::from(["Ann", "Smith, Bob", "Say \"hi\""].map(String::from))
+  );
+
+  let err = resolve("[ ...csv \"people.csv\" column email; ]", &files).unwrap_err();
+  assert_eq!(
+    err,
+    "people.csv: no column \"email\" (columns: id, name, role)"
+  );
+}
+
+#[test]
+fn json_spread_follows_a_path() {
+  let files = [
+    (
+      "data.json",
+      r#"{ "cities": [ { "name": "Kyiv" }, { "name": "Lviv" } ], "n": 3 }"#,
+    ),
+    ("list.json", r#"[ "a", 1, true ]"#),
+  ];
+  let program = resolve("[ ...json \"data.json\" cities.name; ]", &files).unwrap();
+  assert_eq!(
+    outputs(&program),
+    BTreeSet::from(["Kyiv", "Lviv"].map(String::from))
+  );
+  let program = resolve("[ ...json \"list.json\"; ]", &files).unwrap();
+  assert_eq!(
+    outputs(&program),
+    BTreeSet::from(["a", "1", "true"].map(String::from))
+  );
+
+  let err = resolve("[ ...json \"data.json\" towns; ]", &files).unwrap_err();
+  assert_eq!(err, "data.json: no key \"towns\" at \"towns\"");
+  let err = resolve("[ ...json \"data.json\" cities; ]", &files).unwrap_err();
+  assert!(err.contains("is an object"), "{err}");
+}
+
+#[test]
+fn data_spreads_are_baked_into_compiled_programs() {
+  let files = [
+    ("a/x.txt", "one\n"),
+    ("a/y.txt", "two\n"),
+    ("empty.txt", "\n"),
+  ];
+  let program = resolve("[ ...lines \"a/*.txt\"; ]", &files).unwrap();
+  let bytes = serialize_program(&program).unwrap();
+  let loaded = deserialize_program(&bytes).unwrap();
+  assert_eq!(
+    outputs(&loaded),
+    BTreeSet::from(["one", "two"].map(String::from))
+  );
+
+  let err = resolve("[ ...lines \"empty.txt\"; ]", &files).unwrap_err();
+  assert_eq!(err, "empty.txt: no records");
+}
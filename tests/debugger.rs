@@ -0,0 +1,84 @@
+use branchy::debugger::{Breakpoint, Debugger};
+use branchy::{default_registry, interpret_with_hook, parse_program};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn debug(
+  src: &str,
+  breakpoints: Vec<Breakpoint>,
+  commands: &[&str],
+) -> (Result<String, String>, String) {
+  let program = parse_program(src).unwrap();
+  let commands = commands.iter().map(|c| (*c).to_string());
+  let mut dbg = Debugger::new(commands, Vec::new(), Some(src.to_string()), breakpoints);
+  let mut rng = StdRng::seed_from_u64(0);
+  let result = interpret_with_hook(&program, &default_registry(), &mut rng, None, &mut dbg)
+    .map(|(out, _)| out)
+    .map_err(|e| e.message);
+  (result, String::from_utf8(dbg.into_output()).unwrap())
+}
+
+#[test]
+fn debugger_function_breakpoint_shows_env_and_stack() {
+  let src = "!greet(:who) = [ hello :who; ]\n[ !greet(world); ]\n";
+  let (result, out) = debug(
+    src,
+    vec![Breakpoint::parse("!greet")],
+    &["s", "s", "p", "bt", "c"],
+  );
+  assert_eq!(result.unwrap(), "hello world");
+  assert!(
+    out.starts_with("2:3 in main (depth 1): !greet(world)\n"),
+    "{out}"
+  );
+  assert!(
+    out.contains("in !greet (depth 2): [ hello :who; ]"),
+    "{out}"
+  );
+  assert!(out.contains(":who = \"world\"\n"), "{out}");
+  assert!(out.contains("main\n  !greet\n"), "{out}");
+  assert!(out.contains("=> \"hello world\""), "{out}");
+}
+
+#[test]
+fn debugger_forces_branch_choice() {
+  let src = "[ a; b; c; d; e; ]\n";
+  for i in 0..5 {
+    let choose = format!("choose {i}");
+    let (result, out) = debug(src, Vec::new(), &[&choose, "c"]);
+    assert_eq!(result.unwrap(), ["a", "b", "c", "d", "e"][i], "{out}");
+  }
+  let (_, out) = debug(src, Vec::new(), &["choose 9", "n"]);
+  assert!(out.contains("choose N: N must be in 0..5"), "{out}");
+}
+
+#[test]
+fn debugger_choose_counts_spread_block_nodes() {
+  let src = "!wrap() = [ base; ...:extra; ]\n[ wrap { :extra = [ x; y; ]; } ]\n";
+  let (result, out) = debug(
+    src,
+    vec![Breakpoint::parse("1")],
+    &["choose", "choose 2", "c"],
+  );
+  assert_eq!(result.unwrap(), "y", "{out}");
+  assert!(
+    out.contains("choose N: N must be in 0..3\n  0: base\n  1: x\n  2: y\n"),
+    "{out}"
+  );
+  assert!(out.contains("will choose option 2 of 3: y"), "{out}");
+}
+
+#[test]
+fn debugger_step_over_and_quit() {
+  let src = "!f() = [ x; ]\n[ !f(); ]\n";
+  let pauses = |out: &str| out.lines().filter(|l| l.contains("(depth ")).count();
+  let (result, out) = debug(src, Vec::new(), &["s", "n"]);
+  assert_eq!(result.unwrap(), "x");
+  assert_eq!(pauses(&out), 2, "{out}");
+  let (result, out) = debug(src, Vec::new(), &["s", "s", "q"]);
+  assert_eq!(result.unwrap_err(), "debugger: quit");
+  assert_eq!(pauses(&out), 3, "{out}");
+  let (result, out) = debug(src, vec![Breakpoint::Line(7)], &[]);
+  assert_eq!(result.unwrap(), "x");
+  assert!(out.is_empty(), "{out}");
+}
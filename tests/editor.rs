@@ -0,0 +1,202 @@
+use branchy::editor::{complete, hover, CompletionKind, Context, Prefix};
+use branchy::{default_registry, parse_program, resolve_includes, Program};
+
+/// `src` without the cursor marker `|`, and the line and column of the marker.
+fn cursor(src: &str) -> (String, u32, u32) {
+  let at = src.find('|').unwrap();
+  let line = src[..at].matches('\n').count() + 1;
+  let column = at - src[..at].rfind('\n').map_or(0, |i| i + 1) + 1;
+  (
+    src.replacen('|', "", 1),
+    u32::try_from(line).unwrap(),
+    u32::try_from(column).unwrap(),
+  )
+}
+
+/// Labels and kinds completing the marker in `src`, loaded like the server does: as it is, or
+/// else without the partial name.
+fn completions(src: &str) -> Vec<(String, CompletionKind)> {
+  let (source, line, column) = cursor(src);
+  let prefix = Prefix::at(&source, line, column).unwrap();
+  let program = parse_program(&source)
+    .or_else(|_| parse_program(&prefix.strip(&source)))
+    .ok();
+  complete(&prefix, program.as_ref(), &default_registry())
+    .items
+    .into_iter()
+    .map(|c| (c.label, c.kind))
+    .collect()
+}
+
+fn labels(src: &str) -> Vec<String> {
+  completions(src)
+    .into_iter()
+    .map(|(label, _)| label)
+    .collect()
+}
+
+fn hover_at(src: &str) -> Option<(String, Option<String>)> {
+  let (source, line, column) = cursor(src);
+  let program: Program = parse_program(&source).unwrap();
+  hover(&program, line, column, &default_registry()).map(|h| (h.signature, h.documentation))
+}
+
+#[test]
+fn prefix_finds_the_name_being_typed() {
+  let (source, line, column) = cursor("[ a;\n  b ...:ex| ]");
+  let prefix = Prefix::at(&source, line, column).unwrap();
+  assert_eq!(prefix.context, Context::Spread);
+  assert_eq!(prefix.word, "ex");
+  assert_eq!((prefix.span.start_line, prefix.span.start_column), (2, 9));
+  assert_eq!(prefix.span.end_column, 11);
+  assert_eq!(prefix.strip(&source), "[ a;\n  b  ]");
+
+  for (src, context) in [
+    ("[ !up| ]", Context::Function),
+    ("[ :?na| ]", Context::Param),
+    ("[ :| ]", Context::Param),
+    ("@| = [ a; ]", Context::Event),
+    ("[ ui.ca| ]", Context::Template),
+  ] {
+    let (source, line, column) = cursor(src);
+    assert_eq!(
+      Prefix::at(&source, line, column).unwrap().context,
+      context,
+      "{src}"
+    );
+  }
+  // Columns past the end of a line are clamped to it; lines past the end are outside.
+  assert_eq!(Prefix::at("[ !up ]", 1, 99).unwrap().word, "");
+  assert_eq!(Prefix::at("[ a; ]", 2, 1), None);
+}
+
+#[test]
+fn functions_complete_with_builtins() {
+  let src = "!greet(:x) = [ hi :x; ]\n!grow() = [ g; ]\n[ !gr| ]";
+  assert_eq!(
+    completions(src),
+    [
+      ("greet".to_string(), CompletionKind::Function),
+      ("grow".to_string(), CompletionKind::Function),
+    ]
+  );
+  let all = labels("!greet(:x) = [ hi :x; ]\n[ !| ]");
+  assert_eq!(
+    all,
+    ["greet", "concat", "join", "len", "lower", "replace", "split", "trim", "upper"]
+  );
+  // Matching ignores case; a bare name completes template calls, not builtins.
+  assert_eq!(labels("!Wrap() = [ w; ]\n[ !UP| ]"), ["upper"]);
+  assert_eq!(labels("!wrap() = [ w; ]\n[ w| ]"), ["wrap"]);
+
+  let (source, line, column) = cursor("[ !rep| ]");
+  let prefix = Prefix::at(&source, line, column).unwrap();
+  let item = &complete(&prefix, None, &default_registry()).items[0];
+  assert_eq!(item.detail, "!replace(s, from, to)");
+  assert_eq!(
+    item.documentation.as_deref(),
+    Some("`s` with every `from` replaced by `to`.")
+  );
+}
+
+#[test]
+fn params_complete_by_scope() {
+  let wrap = "!wrap(:x) = [ :x + ...:extra; :?y; ]\n";
+  // The block of a template call: the template's params, then the params its body reads.
+  assert_eq!(
+    completions(&format!("{wrap}[ wrap :a {{ :| }} ]")),
+    [
+      ("x".to_string(), CompletionKind::Param),
+      ("extra".to_string(), CompletionKind::BlockParam),
+      ("y".to_string(), CompletionKind::BlockParam),
+    ]
+  );
+  // A function body: its own params.
+  assert_eq!(
+    labels(&format!("{wrap}!f(:a, :b) = [ :| ]\n[ x; ]")),
+    ["a", "b"]
+  );
+  assert_eq!(
+    labels(&format!("{wrap}!f(:a, :b) = [ :b| ]\n[ x; ]")),
+    ["b"]
+  );
+  // A binding value is evaluated where the call is, so the caller's params apply.
+  assert_eq!(
+    labels(&format!(
+      "{wrap}!f(:a) = [ wrap :a {{ :extra = [ :| ]; }} ]\n[ x; ]"
+    )),
+    ["a"]
+  );
+  // A call without a definition binds its own params; main reads params given as vars.
+  assert_eq!(labels("[ hello :who { :| } ]"), ["who"]);
+  assert_eq!(labels("[ hi :name; :|; ]"), ["name"]);
+  assert_eq!(labels("[ a; ...:| ]"), Vec::<String>::new());
+}
+
+#[test]
+fn events_complete_by_name() {
+  let src = "@start = [ go; ]\n\"stop\" = [ halt; ]\n@status = [ ok; ]\n@st|\n[ a; ]";
+  assert_eq!(
+    completions(src),
+    [
+      ("start".to_string(), CompletionKind::Event),
+      ("status".to_string(), CompletionKind::Event),
+    ]
+  );
+}
+
+#[test]
+fn hover_shows_signatures() {
+  let src = "!wrap(:x) = [ :x + ...:extra; ]\n[ !up|per(a); ]";
+  assert_eq!(
+    hover_at(src),
+    Some((
+      "!upper(s)".to_string(),
+      Some("`s` in upper case.".to_string())
+    ))
+  );
+  let src = "!wrap(:x) = [ :x + ...:extra; ]\n[ wr|ap :a { :extra = [ e; ]; }; ]";
+  assert_eq!(
+    hover_at(src),
+    Some(("!wrap(:x) { :extra; }".to_string(), None))
+  );
+  // The innermost call under the cursor.
+  let src = "!wrap(:x) = [ :x; ]\n[ !upper(!wra|p(a)); ]";
+  assert_eq!(hover_at(src).unwrap().0, "!wrap(:x)");
+  // Not on a name, or an unknown function.
+  assert_eq!(hover_at("[ !upper(a|b); ]"), None);
+  assert_eq!(hover_at("[ |!upper(a); ]"), None);
+  assert_eq!(hover_at("[ !miss|ing(); ]"), None);
+
+  let (source, line, column) = cursor("[ a; !up|per(b); ]");
+  let program = parse_program(&source).unwrap();
+  let span = hover(&program, line, column, &default_registry())
+    .unwrap()
+    .span;
+  assert_eq!(
+    (span.start_line, span.start_column, span.end_column),
+    (1, 7, 12)
+  );
+}
+
+#[test]
+fn included_functions_are_named_with_their_file() {
+  let (source, line, column) = cursor("include \"std:en/names\";\n[ !na|me(); ]");
+  let program = resolve_includes(parse_program(&source).unwrap(), |_| {
+    Err("no files".to_string())
+  })
+  .unwrap();
+  let hovered = hover(&program, line, column, &default_registry()).unwrap();
+  assert_eq!(hovered.signature, "!name()");
+  assert!(
+    hovered
+      .documentation
+      .as_deref()
+      .is_some_and(|d| d.starts_with("Defined in std:en/names")),
+    "{hovered:?}"
+  );
+  let prefix = Prefix::at(&source, line, column).unwrap();
+  let items = complete(&prefix, Some(&program), &default_registry()).items;
+  assert_eq!(items[0].label, "name");
+  assert_eq!(items[0].kind, CompletionKind::Function);
+}
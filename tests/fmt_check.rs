@@ -42,7 +42,8 @@ fn fmt_check_unformatted_file_fails() {
   );
   let stderr = String::from_utf8_lossy(&out.stderr);
   assert!(
-    stderr.contains("not formatted") || String::from_utf8_lossy(&out.stdout).contains("not formatted"),
+    stderr.contains("not formatted")
+      || String::from_utf8_lossy(&out.stdout).contains("not formatted"),
     "expected 'not formatted' in output, got stderr: {}",
     stderr
   );
@@ -1,6 +1,10 @@
 mod common;
 
-use branchy::{parse_program, resolve_includes};
+use branchy::ast::Include;
+use branchy::{
+  parse_program, resolve_includes, resolve_includes_detailed, resolve_includes_with,
+  MemoryResolver, Node,
+};
 use common::run_with_seed;
 
 #[test]
@@ -10,7 +14,7 @@ include "lib.branchy";
 [ !greet(); ]
 "#;
   let p = parse_program(main_src).unwrap();
-  assert_eq!(p.includes, ["lib.branchy"]);
+  assert_eq!(p.includes, [Include::all("lib.branchy")]);
   let lib_src = r#"!greet() = [ hi; ]; [ unused; ]"#;
   let program = resolve_includes(p, |path| {
     if path == "lib.branchy" {
@@ -26,3 +30,199 @@ include "lib.branchy";
   let out = run_with_seed(&program, 0);
   assert_eq!(out, "hi");
 }
+
+fn resolve(main_src: &str, files: &[(&str, &str)]) -> Result<branchy::Program, String> {
+  let p = parse_program(main_src).unwrap();
+  resolve_includes(p, |path| {
+    files
+      .iter()
+      .find(|(name, _)| *name == path)
+      .map(|(_, src)| (*src).to_string())
+      .ok_or_else(|| format!("no file {path}"))
+  })
+}
+
+const UI: &str = r#"
+!title(:x) = [ !upper(!wrap(:x)) ];
+!wrap(:x) = [ "*" + :x + "*" ];
+!card(:_) = [ "[" + :_ + "]" ];
+[ unused; ]
+"#;
+const OTHER: &str = "!title(:x) = [ \"other \" + :x ];\n[ unused; ]\n";
+
+#[test]
+fn include_as_namespace_qualifies_calls() {
+  let src = r#"
+include "ui.branchy" as ui;
+include "other.branchy" as other;
+[ !ui.title(hi) + " " + !other.title(x) + " " + ui.card :_ { :_ = [ body; ]; }; ]
+"#;
+  let program = resolve(src, &[("ui.branchy", UI), ("other.branchy", OTHER)]).unwrap();
+  let mut names: Vec<_> = program.functions.iter().map(|f| f.name.as_str()).collect();
+  names.sort_unstable();
+  assert_eq!(names, ["other.title", "ui.card", "ui.title", "ui.wrap"]);
+  assert_eq!(run_with_seed(&program, 0), "*HI* other x [body]");
+}
+
+#[test]
+fn from_import_hides_helpers() {
+  let src = "from \"ui.branchy\" import title;\n!wrap(:x) = [ \"local \" + :x ];\n[ !title(a) + \" \" + !wrap(b); ]\n";
+  let program = resolve(src, &[("ui.branchy", UI)]).unwrap();
+  assert_eq!(run_with_seed(&program, 0), "*A* local b");
+  assert!(program
+    .functions
+    .iter()
+    .any(|f| f.name == "ui.branchy::wrap"));
+  assert!(!program.functions.iter().any(|f| f.name == "card"));
+
+  // One file imported through two spellings has one set of hidden names.
+  let mut files = MemoryResolver::new();
+  files.insert("lib/ui.branchy", UI);
+  let src = "from \"lib/ui.branchy\" import title;\nfrom \"./lib/x/../ui.branchy\" import card;\n[ !title(a) + !card(b); ]\n";
+  let program = resolve_includes_with(parse_program(src).unwrap(), &files).unwrap();
+  let mut names: Vec<_> = program.functions.iter().map(|f| f.name.as_str()).collect();
+  names.sort_unstable();
+  assert_eq!(
+    names,
+    [
+      "card",
+      "lib/ui.branchy::card",
+      "lib/ui.branchy::title",
+      "lib/ui.branchy::wrap",
+      "title"
+    ]
+  );
+  assert_eq!(run_with_seed(&program, 0), "*A*[b]");
+
+  let err = resolve(
+    "from \"ui.branchy\" import nope;\n[ x; ]",
+    &[("ui.branchy", UI)],
+  )
+  .unwrap_err();
+  assert_eq!(err, "\"ui.branchy\" has no function !nope to import");
+}
+
+#[test]
+fn include_reports_ambiguous_names() {
+  let files = [("ui.branchy", UI), ("other.branchy", OTHER)];
+  let err = resolve(
+    "include \"ui.branchy\";\ninclude \"other.branchy\";\n[ x; ]",
+    &files,
+  )
+  .unwrap_err();
+  assert!(
+    err.starts_with(
+      "ambiguous function !title: defined in both \"ui.branchy\" and \"other.branchy\""
+    ),
+    "{err}"
+  );
+  let err = resolve(
+    "include \"ui.branchy\" as a;\ninclude \"other.branchy\" as a;\n[ x; ]",
+    &files,
+  )
+  .unwrap_err();
+  assert!(err.starts_with("ambiguous function !a.title"), "{err}");
+  let err = resolve(
+    "from \"ui.branchy\" import title;\n!title() = [ t; ];\n[ x; ]",
+    &files,
+  )
+  .unwrap_err();
+  assert!(err.contains("defined locally"), "{err}");
+  // A plain include is still shadowed by a local definition.
+  let program = resolve(
+    "include \"other.branchy\";\n!title(:x) = [ mine; ];\n[ !title(1); ]",
+    &files,
+  )
+  .unwrap();
+  assert_eq!(run_with_seed(&program, 0), "mine");
+}
+
+#[test]
+fn include_as_detects_cycles() {
+  let a = "include \"b\" as b;\n!f() = [ a; ];\n[ x; ]";
+  let b = "include \"a\" as a;\n!g() = [ b; ];\n[ x; ]";
+  let err = resolve("include \"a\" as a;\n[ x; ]", &[("a", a), ("b", b)]).unwrap_err();
+  assert_eq!(err, "circular import: a -> b -> a");
+}
+
+fn resolve_glob(main_src: &str, files: &[(&str, &str)]) -> Result<branchy::Program, String> {
+  let p = parse_program(main_src).unwrap();
+  resolve_includes_with(p, &files.iter().copied().collect::<MemoryResolver>())
+}
+
+#[test]
+fn glob_include_merges_matches_in_sorted_order() {
+  let files = [
+    ("snippets/b.branchy", "[ two; ]"),
+    ("words/x.branchy", "!x() = [ ex; ]; [ unused; ]"),
+    ("snippets/a.branchy", "[ one; ]"),
+    ("words/y.branchy", "!y() = [ why; ]; [ unused; ]"),
+  ];
+  let program = resolve_glob(
+    "include \"words/*.branchy\";\n[ ...include \"snippets/*.branchy\"; three; ]\n",
+    &files,
+  )
+  .unwrap();
+  let names: Vec<_> = program.functions.iter().map(|f| f.name.as_str()).collect();
+  assert_eq!(names, ["x", "y"]);
+  let leaves: Vec<_> = (0..20).map(|seed| run_with_seed(&program, seed)).collect();
+  let Node::Branch { children, .. } = &program.main else {
+    panic!("expected a branch, got {:?}", program.main);
+  };
+  assert_eq!(children.len(), 3);
+  for leaf in ["one", "two", "three"] {
+    assert!(
+      leaves.iter().any(|l| l == leaf),
+      "{leaf} never chosen: {leaves:?}"
+    );
+  }
+
+  let src = "include \"words/*\";\n[ !x() + ...include \"snippets/*.branchy\"; ]\n";
+  let program = resolve_glob(src, &files).unwrap();
+  assert_eq!(run_with_seed(&program, 0).len(), "ex".len() + 3);
+}
+
+#[test]
+fn glob_include_errors_name_the_pattern() {
+  let err = resolve_glob("include \"none/*.branchy\";\n[ a; b ]\n", &[]).unwrap_err();
+  assert_eq!(err, "include \"none/*.branchy\": no files match");
+
+  let files = [("words/bad.branchy", "[ unclosed")];
+  let err = resolve_glob("include \"words/*.branchy\";\n[ a; b ]\n", &files).unwrap_err();
+  assert!(
+    err.starts_with("words/bad.branchy (matched by \"words/*.branchy\"): "),
+    "{err}"
+  );
+
+  let p = parse_program("include \"words/*.branchy\";\n[ a; b ]\n").unwrap();
+  let err = resolve_includes(p, |_| Ok(String::new())).unwrap_err();
+  assert!(err.contains("glob patterns are not supported"), "{err}");
+
+  let program = resolve_glob("include \"std:en/*\";\n[ !color(); ]\n", &[]).unwrap();
+  assert!(program.functions.iter().any(|f| f.name == "surname"));
+}
+
+#[test]
+fn parse_errors_in_included_files_name_the_file() {
+  let files = [
+    ("lib/a.branchy", "[ ok; ]\n"),
+    ("lib/b.branchy", "\n[ unclosed"),
+  ]
+  .into_iter()
+  .collect::<MemoryResolver>();
+  let src = "include \"./lib/a.branchy\";\n[ ...include \"lib/x/../b.branchy\"; ]\n";
+  let err = resolve_includes_detailed(parse_program(src).unwrap(), &files).unwrap_err();
+  let parse = err.parse.unwrap();
+  assert_eq!(parse.file.as_deref(), Some("lib/b.branchy"));
+  assert_eq!(parse.span.map(|s| s.start_line), Some(2));
+  assert!(
+    err.message.starts_with("lib/x/../b.branchy: line 2"),
+    "{}",
+    err.message
+  );
+
+  let err = resolve_includes_detailed(parse_program("include \"c\";\n[ x; ]").unwrap(), &files)
+    .unwrap_err();
+  assert_eq!(err.parse, None);
+  assert_eq!(err.message, "include \"c\": file not found");
+}
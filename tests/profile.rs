@@ -0,0 +1,72 @@
+use branchy::interpreter::ProfileKind;
+use branchy::{default_registry, interpret, interpret_profiled, parse_program, Profile};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+#[test]
+fn profile_counts_functions_builtins_and_repeats() {
+  let p = parse_program(
+    r"
+!wrap(:x) = [ !upper(:x); ]
+[ !wrap(a) * 3; ]
+",
+  )
+  .unwrap();
+  let builtins = default_registry();
+  let mut profile = Profile::new();
+  let mut rng = StdRng::seed_from_u64(0);
+  let (out, _) = interpret_profiled(&p, &builtins, &mut rng, None, &mut profile).unwrap();
+  assert_eq!(out, "AAA");
+  let find = |kind: ProfileKind, name: &str| {
+    profile
+      .entries
+      .iter()
+      .find(|e| e.kind == kind && e.name == name)
+      .unwrap_or_else(|| panic!("no {:?} {} in {:?}", kind, name, profile.entries))
+  };
+  assert_eq!(find(ProfileKind::Function, "wrap").calls, 3);
+  assert_eq!(find(ProfileKind::Builtin, "upper").calls, 3);
+  assert_eq!(find(ProfileKind::Repeat, "3:3").items, 3);
+  assert!(profile.total_ns >= profile.entries[0].total_ns);
+  assert!(profile.report().contains("wrap"));
+}
+
+#[test]
+fn profile_does_not_change_output() {
+  let p = parse_program("@ev = [ x; y; z; ]\n[ a; b; c; ]").unwrap();
+  let builtins = default_registry();
+  for seed in 0..20 {
+    let plain = interpret(&p, &builtins, &mut StdRng::seed_from_u64(seed), Some("ev")).unwrap();
+    let mut profile = Profile::new();
+    let profiled = interpret_profiled(
+      &p,
+      &builtins,
+      &mut StdRng::seed_from_u64(seed),
+      Some("ev"),
+      &mut profile,
+    )
+    .unwrap();
+    assert_eq!(plain, profiled);
+    assert_eq!(
+      profile.entries.iter().filter(|e| e.name == "@ev").count(),
+      1
+    );
+  }
+}
+
+#[test]
+fn profile_records_a_repeat_that_fails() {
+  let p = parse_program("[ !missing() * 3; ]").unwrap();
+  let builtins = default_registry();
+  let mut profile = Profile::new();
+  let mut rng = StdRng::seed_from_u64(0);
+  assert!(interpret_profiled(&p, &builtins, &mut rng, None, &mut profile).is_err());
+  assert!(
+    profile
+      .entries
+      .iter()
+      .any(|e| e.kind == ProfileKind::Repeat && e.calls == 1),
+    "{:?}",
+    profile.entries
+  );
+}
@@ -0,0 +1,51 @@
+use branchy::repl::{is_incomplete, Outcome, Session};
+
+fn print(session: &mut Session, input: &str) -> String {
+  match session.handle(input).unwrap() {
+    Outcome::Print(s) => s,
+    other => panic!("expected output for {input:?}, got {other:?}"),
+  }
+}
+
+#[test]
+fn repl_defines_evaluates_and_rerolls() {
+  let mut s = Session::new();
+  assert_eq!(print(&mut s, "!g(:x) = [ hi :x; ]"), "defined !g");
+  assert_eq!(print(&mut s, ":seed 3"), "seed: 3");
+  assert_eq!(print(&mut s, "!g(world)"), "hi world");
+  for _ in 0..5 {
+    let out = print(&mut s, "[ a; b; ]");
+    assert!(out == "a" || out == "b", "got {out}");
+    let again = print(&mut s, ":again");
+    assert!(again == "a" || again == "b", "got {again}");
+  }
+  assert_eq!(print(&mut s, "!g(:y) = [ bye :y; ]"), "defined !g");
+  assert_eq!(print(&mut s, "!g(you)"), "bye you");
+  assert_eq!(print(&mut s, ":defs"), "!g(:y)");
+  assert_eq!(s.handle(":quit").unwrap(), Outcome::Quit);
+}
+
+#[test]
+fn repl_events_and_trace() {
+  let mut s = Session::new();
+  print(&mut s, "@ev = [ one; ]");
+  assert_eq!(print(&mut s, ":event ev"), "one");
+  assert_eq!(print(&mut s, ":r"), "one");
+  assert!(s
+    .handle(":e missing")
+    .unwrap_err()
+    .contains("no event matches"));
+  print(&mut s, "x + [ yes; ]");
+  let trace = print(&mut s, ":trace");
+  assert!(trace.contains("2:3-2:4  x"), "got {trace}");
+  assert!(trace.contains("2:9-2:12  yes"), "got {trace}");
+  assert!(s.handle(":bogus").unwrap_err().contains("unknown command"));
+}
+
+#[test]
+fn repl_incomplete_input() {
+  assert!(is_incomplete("[ a;\n"));
+  assert!(is_incomplete("greet :x {\n  :x = [ w ]\n"));
+  assert!(!is_incomplete("[ \"[\"; ]\n"));
+  assert!(!is_incomplete(":load lib.branchy"));
+}
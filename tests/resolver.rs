@@ -0,0 +1,100 @@
+use branchy::{
+  parse_program, resolve_includes, resolve_includes_with, ArchiveResolver, FsResolver,
+  MemoryResolver, SearchPath, SourceResolver,
+};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{Cursor, Write};
+
+const LIB: &str = "!hi() = [ hi; ];\n\"ping\" = [ pong; ];\n[ unused; lib ]\n";
+const MAIN: &str = "include \"lib/a.branchy\";\ninclude \"./lib/../lib/a.branchy\";\n[ !hi(); ]\n";
+
+#[test]
+fn memory_resolver_includes_a_file_once() {
+  let files = MemoryResolver::from_iter([("lib/a.branchy", LIB), ("lib/b.txt", "x\n")]);
+  assert_eq!(
+    files.canonicalize("./lib/x/../a.branchy").unwrap(),
+    "lib/a.branchy"
+  );
+  assert_eq!(files.glob("lib/*.branchy").unwrap(), ["lib/a.branchy"]);
+  let err = files.canonicalize("lib/c").unwrap_err();
+  assert_eq!(err, "include \"lib/c\": file not found");
+
+  let program = resolve_includes_with(parse_program(MAIN).unwrap(), &files).unwrap();
+  assert_eq!(program.functions.len(), 1);
+  assert_eq!(program.events.len(), 1);
+}
+
+#[test]
+fn closure_resolver_sees_normalized_paths() {
+  let asked = RefCell::new(Vec::new());
+  let src = "include \"./lib/a.branchy\";\ninclude \"lib//a.branchy\";\n[ !hi(); ]\n";
+  resolve_includes(parse_program(src).unwrap(), |p| {
+    asked.borrow_mut().push(p.to_string());
+    Ok(LIB.to_string())
+  })
+  .unwrap();
+  assert_eq!(asked.into_inner(), ["lib/a.branchy"]);
+}
+
+#[test]
+fn fs_resolver_canonicalizes_paths() {
+  let root = std::env::temp_dir().join(format!("branchy-fsresolver-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&root);
+  let app = root.join("app");
+  fs::create_dir_all(app.join("lib")).unwrap();
+  fs::write(app.join("lib/a.branchy"), LIB).unwrap();
+
+  let src = "include \"lib/a.branchy\";\ninclude \"../app/lib/a.branchy\";\n[ !hi(); ]\n";
+  let resolver = FsResolver::new(&app, SearchPath::default());
+  let program = resolve_includes_with(parse_program(src).unwrap(), &resolver).unwrap();
+  assert_eq!(program.events.len(), 1);
+  let lib = fs::canonicalize(app.join("lib/a.branchy")).unwrap();
+  assert_eq!(resolver.files(), [lib]);
+
+  fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn archive_resolver_reads_zip_and_tar() {
+  let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+  zip
+    .add_directory("lib/", zip::write::SimpleFileOptions::default())
+    .unwrap();
+  zip
+    .start_file("lib/a.branchy", zip::write::SimpleFileOptions::default())
+    .unwrap();
+  zip.write_all(LIB.as_bytes()).unwrap();
+  zip
+    .start_file("img/logo.png", zip::write::SimpleFileOptions::default())
+    .unwrap();
+  zip
+    .write_all(&[0x89, b'P', b'N', b'G', 0xff, 0xfe])
+    .unwrap();
+  let bytes = zip.finish().unwrap().into_inner();
+  let archive = ArchiveResolver::from_zip(&bytes).unwrap();
+  assert_eq!(archive.paths().collect::<Vec<_>>(), ["lib/a.branchy"]);
+  let program = resolve_includes_with(parse_program(MAIN).unwrap(), &archive).unwrap();
+  assert_eq!(program.events.len(), 1);
+  // A binary file is only an error when it is included.
+  let src = "include \"img/logo.png\";\n[ x; ]\n";
+  let err = resolve_includes_with(parse_program(src).unwrap(), &archive).unwrap_err();
+  assert_eq!(err, "img/logo.png: not a UTF-8 text file");
+  assert_eq!(archive.glob("img/*").unwrap(), ["img/logo.png"]);
+
+  let mut tar = tar::Builder::new(Vec::new());
+  let mut header = tar::Header::new_gnu();
+  header.set_size(LIB.len() as u64);
+  header.set_mode(0o644);
+  header.set_cksum();
+  tar
+    .append_data(&mut header, "./lib/a.branchy", LIB.as_bytes())
+    .unwrap();
+  let bytes = tar.into_inner().unwrap();
+  let archive = ArchiveResolver::from_tar(&bytes).unwrap();
+  assert_eq!(archive.paths().collect::<Vec<_>>(), ["lib/a.branchy"]);
+  let program = resolve_includes_with(parse_program(MAIN).unwrap(), &archive).unwrap();
+  assert_eq!(program.functions.len(), 1);
+
+  assert!(ArchiveResolver::from_zip(b"not a zip").is_err());
+}
@@ -0,0 +1,123 @@
+use branchy::{parse_program, resolve_includes, SearchPath};
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("branchy-{name}-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+#[test]
+fn search_path_script_dir_then_search_dirs() {
+  let root = temp_dir("search");
+  let (app, lib) = (root.join("app"), root.join("lib"));
+  fs::create_dir_all(&app).unwrap();
+  fs::create_dir_all(&lib).unwrap();
+  fs::write(app.join("local.branchy"), "").unwrap();
+  fs::write(lib.join("local.branchy"), "").unwrap();
+  fs::write(lib.join("names.branchy"), "").unwrap();
+
+  let search = SearchPath::new(vec![lib.clone()]);
+  assert_eq!(
+    search.find(&app, "local.branchy").unwrap(),
+    app.join("local.branchy")
+  );
+  assert_eq!(
+    search.find(&app, "names.branchy").unwrap(),
+    lib.join("names.branchy")
+  );
+  assert_eq!(
+    search.find(&app, "std:names").unwrap(),
+    lib.join("names.branchy")
+  );
+  assert_eq!(
+    search.find(&lib, "std:local.branchy").unwrap(),
+    lib.join("local.branchy")
+  );
+
+  let err = search.find(&app, "missing.branchy").unwrap_err();
+  assert!(
+    err.starts_with("include \"missing.branchy\": file not found; searched: "),
+    "{err}"
+  );
+  assert!(err.contains(&app.display().to_string()) && err.contains(&lib.display().to_string()));
+  let err = SearchPath::default().find(&app, "std:names").unwrap_err();
+  assert!(err.contains("no library directories"), "{err}");
+
+  fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn search_path_reads_project_config() {
+  let root = temp_dir("config");
+  let (src, vendor) = (root.join("src").join("deep"), root.join("vendor"));
+  fs::create_dir_all(&src).unwrap();
+  fs::create_dir_all(&vendor).unwrap();
+  fs::write(
+    root.join("branchy.json"),
+    r#"{ "include_paths": ["vendor"] }"#,
+  )
+  .unwrap();
+  fs::write(
+    vendor.join("greet.branchy"),
+    "!greet() = [ hi ];\n[ unused; lib ]\n",
+  )
+  .unwrap();
+
+  let search = SearchPath::discover(&src, &[PathBuf::from("cli")]).unwrap();
+  assert_eq!(
+    search.dirs()[..2],
+    [PathBuf::from("cli"), root.join("vendor")]
+  );
+
+  let program = parse_program("include \"std:greet\";\n[ !greet() ]\n").unwrap();
+  let program = resolve_includes(program, |p| search.read(&src, p)).unwrap();
+  assert_eq!(program.functions.len(), 1);
+
+  fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn search_path_glob_lists_matches() {
+  let root = temp_dir("glob");
+  let (app, lib) = (root.join("app"), root.join("lib"));
+  fs::create_dir_all(app.join("words")).unwrap();
+  fs::create_dir_all(lib.join("en")).unwrap();
+  for f in [
+    "words/b.branchy",
+    "words/a.branchy",
+    "words/notes.txt",
+    "words/.hidden.branchy",
+  ] {
+    fs::write(app.join(f), "").unwrap();
+  }
+  fs::write(lib.join("en/names.branchy"), "").unwrap();
+
+  let search = SearchPath::new(vec![lib]);
+  let mut scanned = Vec::new();
+  assert_eq!(
+    search.glob(&app, "words/*.branchy", &mut scanned),
+    ["words/a.branchy", "words/b.branchy"]
+  );
+  assert_eq!(scanned, [app.join("words")]);
+  assert_eq!(
+    search.glob(&app, "*/n?mes.branchy", &mut Vec::new()),
+    ["en/names.branchy"]
+  );
+  assert_eq!(
+    search.glob(&app, "std:en/*", &mut Vec::new()),
+    ["std:en/names.branchy"]
+  );
+  assert!(search
+    .glob(&app, "words/*.txt.branchy", &mut Vec::new())
+    .is_empty());
+  let abs = format!("{}/*.txt", app.join("words").display());
+  assert_eq!(
+    search.glob(&app, &abs, &mut Vec::new()),
+    [app.join("words/notes.txt").display().to_string()]
+  );
+
+  fs::remove_dir_all(&root).unwrap();
+}
@@ -1,15 +1,34 @@
 use axum::body::Body;
 use axum::http::{Request, StatusCode};
-use branchy::default_registry;
-use branchy::server::{create_app, AppState};
+use branchy::parse_program;
+use branchy::server::{
+  create_app, load_examples, AppState, Limits, ParseCache, RateLimit, RateLimits, ShareStore,
+  SourceFiles, Throttle,
+};
 use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tower::util::ServiceExt;
 
 fn app() -> axum::Router {
-  create_app(AppState {
-    builtins: Arc::new(default_registry()),
-  })
+  app_with_store(&format!("app-{}", COUNTER.fetch_add(1, Ordering::Relaxed)))
+}
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// App with a program store in a fresh temporary directory named after `name`.
+fn app_with_store(name: &str) -> axum::Router {
+  create_app(state(name))
+}
+
+fn state(name: &str) -> AppState {
+  let dir = std::env::temp_dir().join(format!("branchy-store-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  AppState {
+    examples: Arc::new(load_examples("examples".as_ref())),
+    ..AppState::in_dir(&dir).unwrap()
+  }
 }
 
 #[tokio::test]
@@ -262,6 +281,49 @@ async fn run_source_with_include_returns_400() {
   assert!(out["error"].as_str().unwrap().contains("include"));
 }
 
+#[tokio::test]
+async fn run_source_with_std_include() {
+  let app = app();
+  let src = r#"include "std:en/colors"; [ !color() + "!"; ]"#;
+  let body = json!({ "source": src, "seed": 1 }).to_string();
+  let req = Request::builder()
+    .method("POST")
+    .uri("/run")
+    .header("content-type", "application/json")
+    .body(Body::from(body))
+    .unwrap();
+  let res = app.oneshot(req).await.unwrap();
+  assert_eq!(res.status(), StatusCode::OK);
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let out: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+  assert!(!out["result"].as_str().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn stdlib_lists_embedded_modules() {
+  let app = app();
+  let req = Request::builder()
+    .uri("/stdlib")
+    .body(Body::empty())
+    .unwrap();
+  let res = app.oneshot(req).await.unwrap();
+  assert_eq!(res.status(), StatusCode::OK);
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let out: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+  let names: Vec<_> = out
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|m| m["include"].as_str().unwrap())
+    .collect();
+  assert!(names.contains(&"std:ru/names"));
+  assert!(names.contains(&"std:lorem"));
+}
+
 #[tokio::test]
 async fn run_not_found_route_returns_404() {
   let app = app();
@@ -314,3 +376,899 @@ async fn format_invalid_returns_error() {
   let out: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
   assert!(out["error"].as_str().unwrap().len() > 0);
 }
+
+#[tokio::test]
+async fn run_with_profile_returns_entries() {
+  let app = app();
+  let body = json!({ "source": "!f() = [ x; ]; [ !f(); ]", "profile": true }).to_string();
+  let req = Request::builder()
+    .method("POST")
+    .uri("/run")
+    .header("content-type", "application/json")
+    .body(Body::from(body))
+    .unwrap();
+  let res = app.oneshot(req).await.unwrap();
+  assert_eq!(res.status(), StatusCode::OK);
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let out: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+  assert_eq!(out["result"].as_str().unwrap(), "x");
+  let entries = out["profile"]["entries"].as_array().unwrap();
+  assert!(entries
+    .iter()
+    .any(|e| e["kind"] == "function" && e["name"] == "f" && e["calls"] == 1));
+}
+
+async fn post_json(uri: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+  let req = Request::builder()
+    .method("POST")
+    .uri(uri)
+    .header("content-type", "application/json")
+    .body(Body::from(body.to_string()))
+    .unwrap();
+  let res = app().oneshot(req).await.unwrap();
+  let status = res.status();
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn run_resolves_includes_against_files() {
+  let files = json!({
+    "main.branchy": "include \"lib/greet.branchy\";\n[ !greet(world) + \" \" + ...include \"parts/*.branchy\"; ]\n",
+    "lib/greet.branchy": "!greet(:who) = [ \"hi \" + :who; ];\n[ unused; lib ]\n",
+    "parts/a.branchy": "[ one; ]\n",
+  });
+  let (status, out) = post_json("/run", json!({ "files": files, "entry": "main.branchy" })).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["result"], "hi world one");
+
+  let (status, out) = post_json(
+    "/check",
+    json!({ "files": files, "entry": "./main.branchy" }),
+  )
+  .await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out, json!({ "functions": 1, "events": 0 }));
+
+  let (status, out) = post_json("/run", json!({ "files": files, "entry": "nope.branchy" })).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert_eq!(out["error"], "entry \"nope.branchy\" is not in files");
+}
+
+#[tokio::test]
+async fn multi_file_errors_name_the_file() {
+  let files = json!({
+    "main.branchy": "include \"lib.branchy\";\n[ !f(); ]\n",
+    "lib.branchy": "!f() = [ :missing; ];\n!g() = [ x;\n[ unused; lib ]\n",
+  });
+  let (status, out) = post_json("/check", json!({ "files": files, "entry": "main.branchy" })).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST, "{out}");
+  assert_eq!(out["file"], "lib.branchy");
+  assert!(out["line"].as_u64().unwrap() >= 2, "{out}");
+
+  let files = json!({
+    "main.branchy": "include \"lib.branchy\";\n[ !f(); ]\n",
+    "lib.branchy": "!f() = [ :missing; ];\n[ unused; lib ]\n",
+  });
+  let (status, out) = post_json("/run", json!({ "files": files, "entry": "main.branchy" })).await;
+  assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{out}");
+  assert_eq!(out["file"], "lib.branchy");
+  assert_eq!(out["line"], 1);
+
+  let files = json!({ "main.branchy": "[ :nope; ]\n" });
+  let (status, out) = post_json("/run", json!({ "files": files, "entry": "main.branchy" })).await;
+  assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{out}");
+  assert_eq!(out["file"], "main.branchy");
+}
+
+#[tokio::test]
+async fn format_formats_every_file() {
+  let files = json!({ "main.branchy": "[a;b;]", "lib.branchy": "!f()=[x;y;];[u;v;]" });
+  let (status, out) = post_json(
+    "/format",
+    json!({ "files": files, "entry": "main.branchy" }),
+  )
+  .await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["formatted"], out["files"]["main.branchy"]);
+  assert!(
+    out["files"]["lib.branchy"]
+      .as_str()
+      .unwrap()
+      .starts_with("!f() = "),
+    "{out}"
+  );
+
+  let files = json!({ "main.branchy": "[a;b;]", "lib.branchy": "[ x" });
+  let (status, out) = post_json(
+    "/format",
+    json!({ "files": files, "entry": "main.branchy" }),
+  )
+  .await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert_eq!(out["file"], "lib.branchy");
+}
+
+#[tokio::test]
+async fn run_sets_initial_vars() {
+  let (status, out) = post_json(
+    "/run",
+    json!({ "source": "[ \"hello \" + :who; ]", "vars": { "who": "world" } }),
+  )
+  .await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["result"], "hello world");
+}
+
+async fn send(
+  app: &axum::Router,
+  method: &str,
+  uri: &str,
+  body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+  let req = Request::builder()
+    .method(method)
+    .uri(uri)
+    .header("content-type", "application/json")
+    .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+    .unwrap();
+  let res = app.clone().oneshot(req).await.unwrap();
+  let status = res.status();
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json = if bytes.is_empty() {
+    serde_json::Value::Null
+  } else {
+    serde_json::from_slice(&bytes).unwrap()
+  };
+  (status, json)
+}
+
+#[tokio::test]
+async fn programs_keep_versions_and_run_by_name() {
+  let app = app_with_store("versions");
+  let v1 = json!({ "source": "[ \"v1 \" + :who; ]" });
+  let (status, out) = send(&app, "PUT", "/programs/greeting", Some(v1)).await;
+  assert_eq!(status, StatusCode::CREATED, "{out}");
+  assert_eq!(out, json!({ "name": "greeting", "version": 1 }));
+  let v2 = json!({
+    "files": {
+      "main.branchy": "include \"tag.branchy\";\n[ !tag() + \" \" + :who; ]\n",
+      "tag.branchy": "!tag() = [ \"v2\"; ];\n[ x; ]\n",
+    },
+    "entry": "main.branchy",
+  });
+  let (status, out) = send(&app, "PUT", "/programs/greeting", Some(v2)).await;
+  assert_eq!(status, StatusCode::CREATED, "{out}");
+  assert_eq!(out["version"], 2);
+
+  let (_, out) = send(&app, "GET", "/programs", None).await;
+  assert_eq!(out[0]["name"], "greeting");
+  assert_eq!(out[0]["version"], 2);
+  let (_, out) = send(&app, "GET", "/programs/greeting", None).await;
+  assert_eq!(out["version"], 2);
+  assert_eq!(out["entry"], "main.branchy");
+  assert_eq!(out["versions"].as_array().unwrap().len(), 2);
+  assert_eq!(out["versions"][1]["created"], out["created"]);
+  let (_, out) = send(&app, "GET", "/programs/greeting/versions/1", None).await;
+  assert_eq!(out["source"], "[ \"v1 \" + :who; ]");
+
+  let run = json!({ "vars": { "who": "bob" }, "seed": 1 });
+  let (status, out) = send(&app, "POST", "/programs/greeting/run", Some(run.clone())).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["result"], "v2 bob");
+  let old = json!({ "version": 1, "vars": { "who": "bob" } });
+  let (_, out) = send(&app, "POST", "/programs/greeting/run", Some(old)).await;
+  assert_eq!(out["result"], "v1 bob");
+
+  let (status, _) = send(&app, "DELETE", "/programs/greeting", None).await;
+  assert_eq!(status, StatusCode::NO_CONTENT);
+  let (status, out) = send(&app, "POST", "/programs/greeting/run", Some(run.clone())).await;
+  assert_eq!(status, StatusCode::NOT_FOUND);
+  assert_eq!(out["error"], "program \"greeting\" not found");
+
+  // Saved again under the same name and version: runs the new sources, not the parsed old ones.
+  let new = json!({ "source": "[ \"new \" + :who; ]" });
+  let (_, out) = send(&app, "PUT", "/programs/greeting", Some(new)).await;
+  assert_eq!(out["version"], 1);
+  let (_, out) = send(&app, "POST", "/programs/greeting/run", Some(run)).await;
+  assert_eq!(out["result"], "new bob");
+}
+
+#[tokio::test]
+async fn programs_reject_bad_names_and_sources() {
+  let app = app_with_store("invalid");
+  let ok = json!({ "source": "[ a; b ]" });
+  let (status, out) = send(&app, "PUT", "/programs/..", Some(ok)).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert!(
+    out["error"]
+      .as_str()
+      .unwrap()
+      .starts_with("invalid program name"),
+    "{out}"
+  );
+  let (status, out) = send(
+    &app,
+    "PUT",
+    "/programs/broken",
+    Some(json!({ "source": "[ a" })),
+  )
+  .await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert!(out["line"].is_number(), "{out}");
+  let (status, _) = send(&app, "GET", "/programs/broken", None).await;
+  assert_eq!(status, StatusCode::NOT_FOUND);
+  let (status, _) = send(&app, "GET", "/programs/broken/versions/3", None).await;
+  assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn rerun_uses_parse_cache() {
+  let app = app_with_store("cache");
+  let body = json!({ "source": "[ a; b; c ]" });
+  for seed in 1..=3 {
+    let mut body = body.clone();
+    body["seed"] = json!(seed);
+    let (status, out) = send(&app, "POST", "/run", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{out}");
+  }
+  let (status, out) = send(&app, "POST", "/run", Some(json!({ "source": "[ a" }))).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST, "{out}");
+  let (_, out) = send(&app, "GET", "/cache", None).await;
+  assert_eq!(out["entries"], 1);
+  assert_eq!(out["hits"], 2);
+  assert_eq!(out["misses"], 2);
+}
+
+#[tokio::test]
+async fn editor_requests_do_not_fill_the_parse_cache() {
+  let app = app();
+  let source = "!greet(:x) = [ hi :x; ]\n[ !gr ]";
+  for column in 5..=6 {
+    let body = json!({ "source": source, "line": 2, "column": column });
+    let (status, out) = send(&app, "POST", "/complete", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{out}");
+  }
+  let body = json!({ "source": "[ !upper(a); ]", "line": 1, "column": 5 });
+  let (status, out) = send(&app, "POST", "/hover", Some(body)).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  let (_, out) = send(&app, "GET", "/cache", None).await;
+  assert_eq!((&out["entries"], &out["misses"]), (&json!(0), &json!(0)));
+
+  // A program that was run is used from the cache.
+  let (status, _) = send(
+    &app,
+    "POST",
+    "/run",
+    Some(json!({ "source": "[ !upper(a); ]" })),
+  )
+  .await;
+  assert_eq!(status, StatusCode::OK);
+  let body = json!({ "source": "[ !upper(a); ]", "line": 1, "column": 5 });
+  let (status, out) = send(&app, "POST", "/hover", Some(body)).await;
+  assert_eq!(status, StatusCode::OK);
+  assert_eq!(out["signature"], "!upper(s)");
+  let (_, out) = send(&app, "GET", "/cache", None).await;
+  assert_eq!((&out["entries"], &out["misses"]), (&json!(1), &json!(1)));
+}
+
+#[test]
+fn parse_cache_evicts_least_recently_used() {
+  let cache = ParseCache::new(2, 1 << 20);
+  let files = |s: &str| SourceFiles {
+    source: s.to_string(),
+    ..SourceFiles::default()
+  };
+  let load = |f: &SourceFiles| parse_program(&f.source);
+  let a = cache.get_or_load(&files("[ a; ]"), load).unwrap();
+  cache.get_or_load(&files("[ b; ]"), load).unwrap();
+  let again = cache.get_or_load(&files("[ a; ]"), load).unwrap();
+  assert!(Arc::ptr_eq(&a, &again));
+  cache.get_or_load(&files("[ c; ]"), load).unwrap();
+  // "b" was the least recently used.
+  cache.get_or_load(&files("[ b; ]"), load).unwrap();
+  let stats = cache.stats();
+  assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 4));
+
+  let small = ParseCache::new(10, 8);
+  small.get_or_load(&files("[ too big; ]"), load).unwrap();
+  assert_eq!(small.stats().entries, 0);
+}
+
+#[tokio::test]
+async fn run_batch_counts_items_and_dedups() {
+  let (status, out) = post_json(
+    "/run/batch",
+    json!({ "source": "[ a; b ]", "count": 20, "seed": 7 }),
+  )
+  .await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  let items = out.as_array().unwrap();
+  assert_eq!(items.len(), 20);
+  assert_eq!(items[0]["seed"], 7);
+  assert_eq!(items[19]["seed"], 26);
+  let (_, again) = post_json("/run", json!({ "source": "[ a; b ]", "seed": 26 })).await;
+  assert_eq!(items[19]["result"], again["result"]);
+
+  let (_, out) = post_json(
+    "/run/batch",
+    json!({ "source": "[ a; b ]", "count": 20, "seed": 7, "unique": true }),
+  )
+  .await;
+  let mut results: Vec<_> = out
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|i| i["result"].clone())
+    .collect();
+  results.sort_by_key(ToString::to_string);
+  assert_eq!(results, vec![json!("a"), json!("b")]);
+
+  let items = json!([
+    { "vars": { "x": "one" }, "seed": 1 },
+    { "seed": 2 },
+    { "input": "go" },
+  ]);
+  let source = "@go = [ went; ]; [ :x; ]";
+  let (status, out) = post_json("/run/batch", json!({ "source": source, "items": items })).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out[0], json!({ "seed": 1, "result": "one" }));
+  assert_eq!(out[1]["error"], "undefined param :x");
+  assert_eq!(out[1]["line"], 1);
+  assert_eq!(out[2]["result"], "went");
+}
+
+#[tokio::test]
+async fn run_batch_rejects_bad_sizes() {
+  let (status, out) = post_json("/run/batch", json!({ "source": "[ a; ]" })).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert_eq!(out["error"], "send either \"count\" or \"items\"");
+  let (status, out) = post_json(
+    "/run/batch",
+    json!({ "source": "[ a; ]", "count": Limits::default().batch_runs + 1 }),
+  )
+  .await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert!(
+    out["error"].as_str().unwrap().contains("exceeds the limit"),
+    "{out}"
+  );
+}
+
+/// (event, data) pairs of a finished Server-Sent Events response.
+async fn post_stream(body: serde_json::Value) -> Vec<(String, serde_json::Value)> {
+  post_stream_to(&app(), body).await
+}
+
+async fn post_stream_to(
+  app: &axum::Router,
+  body: serde_json::Value,
+) -> Vec<(String, serde_json::Value)> {
+  let req = Request::builder()
+    .method("POST")
+    .uri("/run/stream")
+    .header("content-type", "application/json")
+    .body(Body::from(body.to_string()))
+    .unwrap();
+  let res = app.clone().oneshot(req).await.unwrap();
+  assert_eq!(res.status(), StatusCode::OK);
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let text = String::from_utf8(bytes.to_vec()).unwrap();
+  text
+    .split("\n\n")
+    .filter_map(|block| {
+      let event = block.lines().find_map(|l| l.strip_prefix("event: "))?;
+      let data = block.lines().find_map(|l| l.strip_prefix("data: "))?;
+      Some((event.to_string(), serde_json::from_str(data).unwrap()))
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn run_stream_sends_samples_then_done() {
+  let events =
+    post_stream(json!({ "source": "[ :x; ]", "count": 3, "seed": 5, "vars": { "x": "v" } })).await;
+  let names: Vec<_> = events.iter().map(|(e, _)| e.as_str()).collect();
+  assert_eq!(names, ["sample", "sample", "sample", "done"]);
+  assert_eq!(events[2].1, json!({ "index": 2, "seed": 7, "result": "v" }));
+  assert_eq!(events[3].1["runs"], 3);
+  assert_eq!(events[3].1["errors"], 0);
+}
+
+#[tokio::test]
+async fn run_stream_stops_at_its_limits() {
+  let limits = Limits {
+    stream_runs: 5,
+    stream_timeout: std::time::Duration::from_millis(200),
+    ..Limits::default()
+  };
+  let app = create_app(AppState {
+    limits,
+    ..state("stream-limits")
+  });
+  let (status, out) = send(
+    &app,
+    "POST",
+    "/run/stream",
+    Some(json!({ "source": "[ a; ]", "count": 6 })),
+  )
+  .await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert_eq!(out["error"], "batch of 6 runs exceeds the limit of 5");
+
+  // The first run is cut off at the end of the stream, well before the 5 s run timeout.
+  let slow = "[ [ \"\"; \"\" ] * 1000000000; ]";
+  let events = post_stream_to(&app, json!({ "source": slow, "count": 3 })).await;
+  let names: Vec<_> = events.iter().map(|(e, _)| e.as_str()).collect();
+  assert_eq!(names, ["sample", "error", "done"]);
+  assert_eq!(events[1].1["error"], "stream timed out after 200 ms");
+  assert_eq!(events[2].1["runs"], 1);
+  let events = post_stream_to(&app, json!({ "source": slow })).await;
+  let names: Vec<_> = events.iter().map(|(e, _)| e.as_str()).collect();
+  assert_eq!(names, ["error", "done"]);
+  assert_eq!(events[0].1["error"], "stream timed out after 200 ms");
+
+  // A client that stops reading (the channel holds 16 events) is dropped once the stream's
+  // time is up.
+  let req = Request::builder()
+    .method("POST")
+    .uri("/run/stream")
+    .header("content-type", "application/json")
+    .body(Body::from(
+      json!({ "source": "[ a; ]", "count": 100 }).to_string(),
+    ))
+    .unwrap();
+  let limits = Limits {
+    stream_timeout: std::time::Duration::from_millis(200),
+    ..Limits::default()
+  };
+  let app = create_app(AppState {
+    limits,
+    ..state("stream-stalled")
+  });
+  let res = app.oneshot(req).await.unwrap();
+  tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let text = String::from_utf8(bytes.to_vec()).unwrap();
+  assert!(text.contains("event: sample"), "{text}");
+  assert!(!text.contains("event: done"), "{text}");
+}
+
+#[tokio::test]
+async fn run_stream_chunks_a_single_output() {
+  let events = post_stream(json!({ "source": "[ \"abcdefgh\" * 5000; ]" })).await;
+  let (done, chunks) = events.split_last().unwrap();
+  assert_eq!(done.0, "done");
+  assert!(done.1["trace"].is_array());
+  assert!(chunks.len() > 1);
+  let text: String = chunks
+    .iter()
+    .map(|(e, d)| {
+      assert_eq!(e, "chunk");
+      d["text"].as_str().unwrap().to_string()
+    })
+    .collect();
+  assert_eq!(text, "abcdefgh".repeat(5000));
+
+  let events = post_stream(json!({ "source": "[ :missing; ]" })).await;
+  assert_eq!(events[0].0, "error");
+  assert_eq!(events[0].1["error"], "undefined param :missing");
+  assert_eq!(events[1].1["errors"], 1);
+}
+
+#[tokio::test]
+async fn limits_reject_large_bodies_slow_runs_and_large_outputs() {
+  let limits = Limits {
+    body_bytes: 200,
+    timeout: std::time::Duration::from_millis(100),
+    output_bytes: 1000,
+    batch_runs: 10,
+    ..Limits::default()
+  };
+  let app = create_app(AppState {
+    limits,
+    ..state("limits")
+  });
+  let big = json!({ "source": format!("[ \"{}\"; ]", "x".repeat(300)) });
+  let (status, out) = send(&app, "POST", "/run", Some(big)).await;
+  assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+  assert_eq!(out["error"], "request body exceeds the limit of 200 bytes");
+
+  let slow = json!({ "source": "[ [ \"\"; \"\" ] * 1000000000; ]" });
+  let (status, out) = send(&app, "POST", "/run", Some(slow.clone())).await;
+  assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+  assert_eq!(out["error"], "run timed out after 100 ms");
+  // A batch keeps the runs that finished in time; the rest are marked as timed out.
+  let batch = json!({
+    "source": "[ [ \"\"; \"\" ] * :n + x; ]",
+    "items": [
+      { "vars": { "n": "1" }, "seed": 1 },
+      { "vars": { "n": "1000000000" }, "seed": 2 },
+      { "vars": { "n": "1" }, "seed": 3 },
+    ],
+  });
+  let (status, out) = send(&app, "POST", "/run/batch", Some(batch)).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out[0]["seed"], 1);
+  assert_eq!(out[0]["result"], "x");
+  for (item, seed) in out.as_array().unwrap()[1..].iter().zip([2, 3]) {
+    assert_eq!(item["seed"], seed);
+    assert_eq!(item["error"], "run timed out after 100 ms");
+  }
+  // So is a shared run that no longer finishes in time.
+  let (status, created) = send(&app, "POST", "/share", Some(slow.clone())).await;
+  assert_eq!(status, StatusCode::CREATED, "{created}");
+  let uri = format!("/share/{}", created["id"].as_str().unwrap());
+  let (status, out) = send(&app, "GET", &uri, None).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert!(out.get("result").is_none(), "{out}");
+  assert_eq!(out["error"], "run timed out after 100 ms");
+  let (status, out) = send(
+    &app,
+    "POST",
+    "/run/batch",
+    Some(json!({ "source": "[ a; ]", "count": 11 })),
+  )
+  .await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert_eq!(out["error"], "batch of 11 runs exceeds the limit of 10");
+
+  let (status, out) = send(
+    &app,
+    "POST",
+    "/run",
+    Some(json!({ "source": "[ x * 2000; ]" })),
+  )
+  .await;
+  assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+  assert_eq!(out["error"], "output exceeds the limit of 1000 bytes");
+  // Stops as soon as one repeat or concatenation goes over the cap, long before the timeout.
+  for source in ["[ x * 1000000000; ]", "[ x * 1000000000 + y; ]"] {
+    let (status, out) = send(&app, "POST", "/run", Some(json!({ "source": source }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{source}: {out}");
+    assert_eq!(out["error"], "output exceeds the limit of 1000 bytes");
+  }
+}
+
+#[tokio::test]
+async fn builtins_stop_at_the_output_limit() {
+  let app = app();
+  // The replace would build 10 GB from arguments well under the 1 MiB limit.
+  let parts = ["a * 100000"; 11].join(", ");
+  for source in [
+    "[ !replace(a * 100000, a, b * 100000); ]".to_string(),
+    format!("[ !join(b * 100000, {parts}); ]"),
+    format!("[ !concat({parts}); ]"),
+  ] {
+    let (status, out) = send(&app, "POST", "/run", Some(json!({ "source": source }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{source}: {out}");
+    assert_eq!(out["error"], "output exceeds the limit of 1048576 bytes");
+  }
+  let fits = json!({ "source": "[ !replace(a * 1000, a, bc); ]" });
+  let (status, out) = send(&app, "POST", "/run", Some(fits)).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["result"], "bc".repeat(1000));
+}
+
+#[tokio::test]
+async fn bad_json_bodies_use_error_shape() {
+  let req = Request::builder()
+    .method("POST")
+    .uri("/run")
+    .header("content-type", "application/json")
+    .body(Body::from("{ nope"))
+    .unwrap();
+  let res = app().oneshot(req).await.unwrap();
+  assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let out: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+  assert!(out["error"].is_string(), "{out}");
+}
+
+/// POST /run from the client identified by `header`; status and `Retry-After`.
+async fn run_as(app: &axum::Router, header: (&str, &str)) -> (StatusCode, Option<String>) {
+  let req = Request::builder()
+    .method("POST")
+    .uri("/run")
+    .header("content-type", "application/json")
+    .header(header.0, header.1)
+    .body(Body::from(json!({ "source": "[ a; ]" }).to_string()))
+    .unwrap();
+  let res = app.clone().oneshot(req).await.unwrap();
+  let retry = res
+    .headers()
+    .get("retry-after")
+    .map(|v| v.to_str().unwrap().to_string());
+  (res.status(), retry)
+}
+
+#[tokio::test]
+async fn rate_limit_is_per_client_and_route() {
+  let limit = RateLimit {
+    per_second: 0.01,
+    burst: 2,
+  };
+  let app = create_app(AppState {
+    throttle: Arc::new(Throttle::new(RateLimits {
+      routes: HashMap::from([("/run".to_string(), limit)]),
+      trust_proxy: true,
+      ..RateLimits::default()
+    })),
+    ..state("rate")
+  });
+  let alice = ("x-real-ip", "10.0.0.1");
+  assert_eq!(run_as(&app, alice).await, (StatusCode::OK, None));
+  assert_eq!(run_as(&app, alice).await, (StatusCode::OK, None));
+  let (status, retry) = run_as(&app, alice).await;
+  assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+  assert_eq!(retry.as_deref(), Some("100"));
+  assert_eq!(
+    run_as(&app, ("x-real-ip", "10.0.0.2")).await.0,
+    StatusCode::OK
+  );
+
+  // Keys that authentication did not validate don't make new clients.
+  assert_eq!(run_as(&app, ("x-api-key", "a")).await.0, StatusCode::OK);
+  assert_eq!(run_as(&app, ("x-api-key", "b")).await.0, StatusCode::OK);
+  assert_eq!(
+    run_as(&app, ("x-api-key", "c")).await.0,
+    StatusCode::TOO_MANY_REQUESTS
+  );
+  let (_, out) = send(&app, "POST", "/check", Some(json!({ "source": "[ a; ]" }))).await;
+  assert_eq!(out["functions"], 0);
+}
+
+#[tokio::test]
+async fn tiny_refill_rates_give_the_longest_retry_after() {
+  let limit = RateLimit {
+    per_second: 1e-20,
+    burst: 1,
+  };
+  let app = create_app(AppState {
+    throttle: Arc::new(Throttle::new(RateLimits {
+      routes: HashMap::from([("/run".to_string(), limit)]),
+      ..RateLimits::default()
+    })),
+    ..state("tiny-rate")
+  });
+  let client = ("x-real-ip", "10.0.0.1");
+  assert_eq!(run_as(&app, client).await.0, StatusCode::OK);
+  let (status, retry) = run_as(&app, client).await;
+  assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+  assert_eq!(retry, Some(u64::MAX.to_string()));
+}
+
+#[tokio::test]
+async fn concurrent_run_cap_returns_429() {
+  let app = create_app(AppState {
+    throttle: Arc::new(Throttle::new(RateLimits {
+      routes: HashMap::new(),
+      max_concurrent_runs: 0,
+      ..RateLimits::default()
+    })),
+    ..state("busy")
+  });
+  let (status, retry) = run_as(&app, ("x-api-key", "k")).await;
+  assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+  assert_eq!(retry.as_deref(), Some("1"));
+  let (status, _) = send(&app, "POST", "/format", Some(json!({ "source": "[ a; ]" }))).await;
+  assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn metrics_count_requests_errors_and_runs() {
+  let app = app();
+  let run = |source: &str| json!({ "source": source, "seed": 1 });
+  for source in ["[ a; ]", "[ a; ]", "[ a", "[ !missing(); ]"] {
+    send(&app, "POST", "/run", Some(run(source))).await;
+  }
+  let req = Request::builder()
+    .uri("/metrics")
+    .body(Body::empty())
+    .unwrap();
+  let res = app.oneshot(req).await.unwrap();
+  assert_eq!(res.status(), StatusCode::OK);
+  assert!(res.headers()["content-type"]
+    .to_str()
+    .unwrap()
+    .starts_with("text/plain"));
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let text = String::from_utf8(bytes.to_vec()).unwrap();
+  for line in [
+    "branchy_http_requests_total{method=\"POST\",route=\"/run\",status=\"200\"} 2",
+    "branchy_http_requests_total{method=\"POST\",route=\"/run\",status=\"400\"} 1",
+    "branchy_http_request_duration_seconds_count{method=\"POST\",route=\"/run\"} 4",
+    "branchy_errors_total{stage=\"parse\",kind=\"syntax\"} 1",
+    "branchy_runs_total 3",
+    "branchy_output_bytes_bucket{le=\"64\"} 2",
+    "branchy_eval_steps_count 3",
+    "branchy_parse_cache_hits_total 1",
+    "branchy_parse_cache_misses_total 3",
+  ] {
+    assert!(text.lines().any(|l| l == line), "missing {line} in\n{text}");
+  }
+  assert!(
+    text.contains("branchy_errors_total{stage=\"eval\",kind=\"runtime\"} 1"),
+    "{text}"
+  );
+}
+
+#[tokio::test]
+async fn shared_runs_reproduce_their_output() {
+  let app = app_with_store("share");
+  let source = "[ \"a \" + :who; \"b \" + :who; \"c \" + :who; \"d \" + :who; ]";
+  let share = json!({ "source": source, "vars": { "who": "bob" } });
+  let (status, created) = send(&app, "POST", "/share", Some(share)).await;
+  assert_eq!(status, StatusCode::CREATED, "{created}");
+  let id = created["id"].as_str().unwrap();
+  assert_eq!(id.len(), 10, "{id}");
+  let seed = created["seed"].as_u64().unwrap();
+
+  let run = json!({ "source": source, "vars": { "who": "bob" }, "seed": seed });
+  let (_, expected) = send(&app, "POST", "/run", Some(run.clone())).await;
+  let (status, out) = send(&app, "GET", &format!("/share/{id}"), None).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["id"], id);
+  assert_eq!(out["source"], source);
+  assert_eq!(out["seed"], seed);
+  assert_eq!(out["vars"], json!({ "who": "bob" }));
+  assert_eq!(out["result"], expected["result"]);
+  let (_, again) = send(&app, "POST", "/share", Some(run)).await;
+  assert_eq!(again["id"], id);
+
+  let failing = json!({ "source": "[ !missing(); ]", "seed": 1 });
+  let (status, created) = send(&app, "POST", "/share", Some(failing)).await;
+  assert_eq!(status, StatusCode::CREATED, "{created}");
+  assert_ne!(created["id"], id);
+  let uri = format!("/share/{}", created["id"].as_str().unwrap());
+  let (status, out) = send(&app, "GET", &uri, None).await;
+  assert_eq!(status, StatusCode::OK);
+  assert!(out.get("result").is_none(), "{out}");
+  assert!(out["error"].as_str().unwrap().contains("missing"), "{out}");
+
+  let (status, out) = send(&app, "POST", "/share", Some(json!({ "source": "[ a" }))).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST, "{out}");
+  for unknown in ["0123456789", "../secrets", "xyz"] {
+    let (status, out) = send(&app, "GET", &format!("/share/{unknown}"), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{unknown}: {out}");
+  }
+}
+
+#[tokio::test]
+async fn share_store_is_capped_and_rate_limited() {
+  let dir = std::env::temp_dir().join(format!("branchy-share-cap-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  let shares = ShareStore::open(&dir).unwrap().with_max_runs(2);
+  let app = create_app(AppState {
+    shares: Arc::new(shares),
+    ..state("share-cap")
+  });
+  let share = |seed: u64| json!({ "source": "[ a; ]", "seed": seed });
+  for seed in 1..=2 {
+    let (status, out) = send(&app, "POST", "/share", Some(share(seed))).await;
+    assert_eq!(status, StatusCode::CREATED, "{out}");
+  }
+  let (status, out) = send(&app, "POST", "/share", Some(share(3))).await;
+  assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE, "{out}");
+  assert_eq!(out["error"], "share store: holds its maximum of 2 runs");
+  // Runs saved before still get their id, also after reopening the store.
+  let (status, _) = send(&app, "POST", "/share", Some(share(1))).await;
+  assert_eq!(status, StatusCode::CREATED);
+  let reopened = ShareStore::open(&dir).unwrap().with_max_runs(3);
+  let app = create_app(AppState {
+    shares: Arc::new(reopened),
+    ..state("share-cap")
+  });
+  let (status, _) = send(&app, "POST", "/share", Some(share(3))).await;
+  assert_eq!(status, StatusCode::CREATED);
+  let (status, _) = send(&app, "POST", "/share", Some(share(4))).await;
+  assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+
+  assert!(RateLimits::default().routes.contains_key("/share"));
+}
+
+#[tokio::test]
+async fn analyze_reports_symbols_and_diagnostics() {
+  let app = app();
+  let source = "!greet(:x) = [ hi :x; bye :x; ]\n!unused() = [ x; ]\n[ !greet(!upper(a)); ]";
+  let (status, out) = send(&app, "POST", "/analyze", Some(json!({ "source": source }))).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["outputs"], 2);
+  assert_eq!(out["builtins"], json!(["upper"]));
+  assert_eq!(
+    out["calls"],
+    json!([{ "from": "main", "to": "!greet", "calls": 1 }])
+  );
+  assert_eq!(out["symbols"][0]["name"], "!greet");
+  assert_eq!(out["symbols"][0]["params"], json!(["x"]));
+  assert_eq!(out["symbols"][0]["span"]["start_line"], 1);
+  assert_eq!(
+    out["diagnostics"],
+    json!([{
+      "severity": "warning",
+      "message": "function !unused is never called",
+      "span": { "start_line": 2, "start_column": 13, "end_line": 2, "end_column": 19, "file": 0 },
+    }])
+  );
+
+  let (status, out) = send(&app, "POST", "/analyze", Some(json!({ "source": "[ a" }))).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["diagnostics"][0]["severity"], "error");
+  assert!(
+    out["diagnostics"][0]["span"]["start_line"].is_number(),
+    "{out}"
+  );
+  assert_eq!(out["symbols"], json!([]));
+  assert_eq!(out["outputs"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn complete_and_hover_use_the_cursor() {
+  let app = app();
+  // Half typed: loaded without `!gr` to find `!greet`.
+  let source = "!greet(:x) = [ hi :x; ]\n[ !gr ]";
+  let body = json!({ "source": source, "line": 2, "column": 6 });
+  let (status, out) = send(&app, "POST", "/complete", Some(body)).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(
+    out,
+    json!({
+      "span": { "start_line": 2, "start_column": 4, "end_line": 2, "end_column": 6, "file": 0 },
+      "items": [{ "label": "greet", "kind": "function", "detail": "!greet(:x)" }],
+    })
+  );
+  let files = json!({
+    "files": { "main.branchy": "!wrap(:x) = [ :x + ...:extra; ]\n[ wrap :a { :extra = e; : } ]" },
+    "entry": "main.branchy",
+    "line": 2,
+    "column": 26,
+  });
+  let (status, out) = send(&app, "POST", "/complete", Some(files)).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  let labels: Vec<&str> = out["items"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| c["label"].as_str().unwrap())
+    .collect();
+  assert_eq!(labels, ["x", "extra"]);
+  let (status, out) = send(
+    &app,
+    "POST",
+    "/complete",
+    Some(json!({ "source": "[ a; ]", "line": 3, "column": 1 })),
+  )
+  .await;
+  assert_eq!(status, StatusCode::BAD_REQUEST);
+  assert_eq!(out["error"], "line 3, column 1 is outside the source");
+
+  let body = json!({ "source": "[ !upper(a); ]", "line": 1, "column": 5 });
+  let (status, out) = send(&app, "POST", "/hover", Some(body)).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(
+    out,
+    json!({
+      "span": { "start_line": 1, "start_column": 4, "end_line": 1, "end_column": 9, "file": 0 },
+      "signature": "!upper(s)",
+      "documentation": "`s` in upper case.",
+    })
+  );
+  let body = json!({ "source": "[ !upper(a); ]", "line": 1, "column": 1 });
+  let (status, out) = send(&app, "POST", "/hover", Some(body)).await;
+  assert_eq!(status, StatusCode::OK);
+  assert_eq!(out, serde_json::Value::Null);
+}
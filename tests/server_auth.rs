@@ -0,0 +1,309 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use branchy::server::{
+  create_app, hash_key, AppState, Auth, KeyConfig, KeysFile, Quota, RateLimit, RateLimits,
+  ServerConfig, Throttle,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tower::util::ServiceExt;
+
+fn key(id: &str, secret: &str, programs: &[&str]) -> KeyConfig {
+  KeyConfig {
+    id: id.to_string(),
+    hash: hash_key(secret),
+    programs: programs.iter().map(|p| (*p).to_string()).collect(),
+    read_only: false,
+    rate_limits: HashMap::new(),
+    quota: None,
+  }
+}
+
+fn app(name: &str, auth: Auth) -> axum::Router {
+  app_with_limits(
+    name,
+    auth,
+    RateLimits {
+      routes: HashMap::new(),
+      ..RateLimits::default()
+    },
+  )
+}
+
+fn app_with_limits(name: &str, auth: Auth, rate_limits: RateLimits) -> axum::Router {
+  let dir = std::env::temp_dir().join(format!("branchy-auth-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  create_app(AppState {
+    throttle: Arc::new(Throttle::new(rate_limits)),
+    auth: Arc::new(auth),
+    ..AppState::in_dir(&dir).unwrap()
+  })
+}
+
+/// Send a request, with `key` as `Authorization: Bearer`.
+async fn send(
+  app: &axum::Router,
+  method: &str,
+  uri: &str,
+  key: Option<&str>,
+  body: Option<Value>,
+) -> (StatusCode, Value, axum::http::HeaderMap) {
+  let mut req = Request::builder()
+    .method(method)
+    .uri(uri)
+    .header("content-type", "application/json");
+  if let Some(key) = key {
+    req = req.header("authorization", format!("Bearer {key}"));
+  }
+  let req = req
+    .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+    .unwrap();
+  let res = app.clone().oneshot(req).await.unwrap();
+  let (status, headers) = (res.status(), res.headers().clone());
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  let json = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+  (status, json, headers)
+}
+
+fn run_body() -> Value {
+  json!({ "source": "[ a; ]" })
+}
+
+#[test]
+fn hash_key_is_hex_sha256() {
+  assert_eq!(
+    hash_key("secret"),
+    "sha256:2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+  );
+}
+
+#[test]
+fn bad_keys_are_rejected() {
+  let mut bad = key("a", "x", &[]);
+  bad.hash = "md5:abc".to_string();
+  let Err(err) = Auth::new(vec![bad]) else {
+    panic!("bad hash accepted");
+  };
+  assert!(err.contains("hash must be"), "{err}");
+  let Err(err) = Auth::new(vec![key("a", "x", &[]), key("a", "y", &[])]) else {
+    panic!("repeated id accepted");
+  };
+  assert_eq!(err, "key \"a\" is listed twice");
+  let Err(err) = Auth::new(vec![key("a", "x", &[]), key("b", "x", &[])]) else {
+    panic!("repeated key accepted");
+  };
+  assert!(err.contains("hash of another key"), "{err}");
+  let mut zero = key("a", "x", &[]);
+  zero.quota = Some(Quota {
+    requests: 5,
+    period_secs: 0,
+  });
+  let Err(err) = Auth::new(vec![zero]) else {
+    panic!("zero quota period accepted");
+  };
+  assert_eq!(err, "key \"a\": quota period_secs must be at least 1");
+
+  let file: KeysFile = serde_json::from_value(json!({
+    "keys": [{ "id": "a", "hash": hash_key("x"), "quota": { "requests": 5 } }]
+  }))
+  .unwrap();
+  assert_eq!(
+    file.keys[0].quota,
+    Some(Quota {
+      requests: 5,
+      period_secs: 86_400
+    })
+  );
+}
+
+#[test]
+fn keys_file_is_configurable() {
+  let args = ["--keys".to_string(), "keys.json".to_string()];
+  let config = ServerConfig::load(&args, |_| None).unwrap();
+  assert_eq!(config.keys_file, Some("keys.json".into()));
+  let config = ServerConfig::load(&[], |name| {
+    (name == "BRANCHY_KEYS").then(|| "env.json".to_string())
+  })
+  .unwrap();
+  assert_eq!(config.keys_file, Some("env.json".into()));
+  assert_eq!(ServerConfig::default().keys_file, None);
+}
+
+#[tokio::test]
+async fn open_without_keys() {
+  let app = app("open", Auth::open());
+  let (status, out, _) = send(&app, "POST", "/run", None, Some(run_body())).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  let (status, _, _) = send(&app, "PUT", "/programs/any", None, Some(run_body())).await;
+  assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn keys_are_required_except_on_public_routes() {
+  let app = app(
+    "required",
+    Auth::new(vec![key("team", "s3cret", &[])]).unwrap(),
+  );
+  for uri in ["/health", "/openapi.json"] {
+    let (status, _, _) = send(&app, "GET", uri, None, None).await;
+    assert_eq!(status, StatusCode::OK, "{uri}");
+  }
+  let (status, out, headers) = send(&app, "POST", "/run", None, Some(run_body())).await;
+  assert_eq!(status, StatusCode::UNAUTHORIZED);
+  assert!(out["error"]
+    .as_str()
+    .unwrap()
+    .starts_with("API key required"));
+  assert_eq!(headers["www-authenticate"], "Bearer");
+  let (status, out, _) = send(&app, "POST", "/run", Some("wrong"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::UNAUTHORIZED);
+  assert_eq!(out["error"], "invalid API key");
+  let (status, out, _) = send(&app, "POST", "/run", Some("s3cret"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+
+  let req = Request::builder()
+    .uri("/examples")
+    .header("x-api-key", "s3cret")
+    .body(Body::empty())
+    .unwrap();
+  assert_eq!(app.oneshot(req).await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn keys_see_only_their_programs() {
+  let mut reader = key("reader", "r", &["team-demo"]);
+  reader.read_only = true;
+  let auth = Auth::new(vec![key("writer", "w", &["team-*"]), reader]).unwrap();
+  let app = app("programs", auth);
+
+  for name in ["team-demo", "team-other"] {
+    let (status, out, _) = send(
+      &app,
+      "PUT",
+      &format!("/programs/{name}"),
+      Some("w"),
+      Some(run_body()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{out}");
+  }
+  let (status, out, _) = send(
+    &app,
+    "PUT",
+    "/programs/private",
+    Some("w"),
+    Some(run_body()),
+  )
+  .await;
+  assert_eq!(status, StatusCode::FORBIDDEN);
+  assert_eq!(
+    out["error"],
+    "API key \"writer\" has no access to program \"private\""
+  );
+
+  let (status, out, _) = send(&app, "GET", "/programs", Some("r"), None).await;
+  assert_eq!(status, StatusCode::OK);
+  let names: Vec<&str> = out
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|p| p["name"].as_str().unwrap())
+    .collect();
+  assert_eq!(names, ["team-demo"]);
+  let (status, _, _) = send(&app, "GET", "/programs/team-demo", Some("r"), None).await;
+  assert_eq!(status, StatusCode::OK);
+  let (status, out, _) = send(
+    &app,
+    "POST",
+    "/programs/team-demo/run",
+    Some("r"),
+    Some(json!({})),
+  )
+  .await;
+  assert_eq!(status, StatusCode::OK, "{out}");
+  assert_eq!(out["result"], "a");
+  let (status, _, _) = send(
+    &app,
+    "GET",
+    "/programs/team-other/versions/1",
+    Some("r"),
+    None,
+  )
+  .await;
+  assert_eq!(status, StatusCode::FORBIDDEN);
+  let (status, out, _) = send(&app, "DELETE", "/programs/team-demo", Some("r"), None).await;
+  assert_eq!(status, StatusCode::FORBIDDEN);
+  assert_eq!(
+    out["error"],
+    "API key \"reader\" may not change stored programs"
+  );
+  let (status, _, _) = send(&app, "DELETE", "/programs/team-demo", Some("w"), None).await;
+  assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn keys_have_quotas_and_rate_limits() {
+  let mut quota = key("quota", "q", &[]);
+  quota.quota = Some(Quota {
+    requests: 2,
+    period_secs: 3600,
+  });
+  let mut limited = key("limited", "l", &[]);
+  limited.rate_limits.insert(
+    "/run".to_string(),
+    RateLimit {
+      per_second: 0.001,
+      burst: 1,
+    },
+  );
+  let app = app("quota", Auth::new(vec![quota, limited]).unwrap());
+
+  for _ in 0..2 {
+    let (status, _, _) = send(&app, "POST", "/run", Some("q"), Some(run_body())).await;
+    assert_eq!(status, StatusCode::OK);
+  }
+  let (status, out, headers) = send(&app, "GET", "/examples", Some("q"), None).await;
+  assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+  assert_eq!(
+    out["error"],
+    "quota of 2 requests per 3600 s exceeded for API key \"quota\""
+  );
+  let retry: u64 = headers["retry-after"].to_str().unwrap().parse().unwrap();
+  assert!((3590..=3600).contains(&retry), "{retry}");
+
+  let (status, _, _) = send(&app, "POST", "/run", Some("l"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::OK);
+  let (status, out, _) = send(&app, "POST", "/run", Some("l"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{out}");
+  let (status, _, _) = send(&app, "POST", "/check", Some("l"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn failed_authentication_is_rate_limited_by_ip() {
+  let limit = RateLimit {
+    per_second: 0.001,
+    burst: 2,
+  };
+  let app = app_with_limits(
+    "failed",
+    Auth::new(vec![key("team", "s3cret", &[])]).unwrap(),
+    RateLimits {
+      routes: HashMap::from([("/run".to_string(), limit)]),
+      ..RateLimits::default()
+    },
+  );
+  for key in [None, Some("wrong")] {
+    let (status, _, _) = send(&app, "POST", "/run", key, Some(run_body())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+  }
+  let (status, out, headers) = send(&app, "POST", "/run", Some("guess"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{out}");
+  assert!(headers.contains_key("retry-after"));
+  // A valid key counts on its own.
+  let (status, _, _) = send(&app, "POST", "/run", Some("s3cret"), Some(run_body())).await;
+  assert_eq!(status, StatusCode::OK);
+}
@@ -0,0 +1,126 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use branchy::server::{create_app_with_origins, AppState, ServerConfig};
+use std::collections::HashMap;
+use std::time::Duration;
+use tower::util::ServiceExt;
+
+fn args(s: &str) -> Vec<String> {
+  s.split_whitespace().map(str::to_string).collect()
+}
+
+const fn no_env(_: &str) -> Option<String> {
+  None
+}
+
+#[test]
+fn defaults_without_config() {
+  let config = ServerConfig::load(&[], no_env).unwrap();
+  assert_eq!(config, ServerConfig::default());
+  assert_eq!(config.bind.to_string(), "0.0.0.0:3000");
+}
+
+#[test]
+fn flags_override_env_override_file() {
+  let dir = std::env::temp_dir().join(format!("branchy-server-config-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let file = dir.join("server.json");
+  std::fs::write(
+    &file,
+    r#"{
+      "bind": "127.0.0.1:4000",
+      "examples_dir": "from-file",
+      "share_dir": "shares-from-file",
+      "log": "warn",
+      "limits": { "timeout_ms": 250, "stream_runs": 10, "stream_timeout_ms": 1500 },
+      "rate_limits": { "routes": { "/run": { "per_second": 1.0, "burst": 3 } } }
+    }"#,
+  )
+  .unwrap();
+  let env: HashMap<&str, &str> = HashMap::from([
+    ("BRANCHY_EXAMPLES", "from-env"),
+    (
+      "BRANCHY_CORS_ORIGINS",
+      "https://a.example, https://b.example",
+    ),
+    ("BRANCHY_SHARES", "shares-from-env"),
+    ("RUST_LOG", "debug"),
+  ]);
+  let lookup = |name: &str| env.get(name).map(|v| (*v).to_string());
+  let config = ServerConfig::load(
+    &args(&format!(
+      "--config {} --bind 5000 --shares shares-from-flag --log error",
+      file.display()
+    )),
+    lookup,
+  )
+  .unwrap();
+  assert_eq!(config.bind.to_string(), "0.0.0.0:5000");
+  assert_eq!(config.examples_dir.to_str(), Some("from-env"));
+  assert_eq!(config.share_dir.to_str(), Some("shares-from-flag"));
+  assert_eq!(
+    config.cors_origins,
+    ["https://a.example", "https://b.example"]
+  );
+  assert_eq!(config.log, "error");
+  assert_eq!(config.limits.timeout_ms, Duration::from_millis(250));
+  assert_eq!(config.limits.body_bytes, 1 << 20);
+  assert_eq!(config.limits.stream_runs, 10);
+  assert_eq!(config.limits.stream_timeout_ms, Duration::from_millis(1500));
+  assert_eq!(config.rate_limits.routes["/run"].burst, 3);
+  assert!(!config.rate_limits.routes.contains_key("/run/batch"));
+}
+
+#[test]
+fn bad_config_is_reported() {
+  let err = ServerConfig::load(&args("--bind nowhere"), no_env).unwrap_err();
+  assert!(err.contains("invalid address \"nowhere\""), "{err}");
+  let err = ServerConfig::load(&args("--port 1"), no_env).unwrap_err();
+  assert!(err.starts_with("unexpected argument: --port"), "{err}");
+  let err = ServerConfig::load(&args("--bind"), no_env).unwrap_err();
+  assert_eq!(err, "--bind requires a value");
+  let err = ServerConfig::load(&args("--config /nonexistent/branchy.json"), no_env).unwrap_err();
+  assert!(err.starts_with("/nonexistent/branchy.json: "), "{err}");
+
+  let dir = std::env::temp_dir().join(format!("branchy-bad-limits-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let file = dir.join("server.json");
+  for (limit, error) in [
+    (
+      r#"{ "per_second": 0, "burst": 5 }"#,
+      "per_second must be a positive number, not 0",
+    ),
+    (
+      r#"{ "per_second": -1, "burst": 5 }"#,
+      "per_second must be a positive number, not -1",
+    ),
+    (
+      r#"{ "per_second": 1, "burst": 0 }"#,
+      "burst must be at least 1",
+    ),
+  ] {
+    let json = format!(r#"{{ "rate_limits": {{ "routes": {{ "/run": {limit} }} }} }}"#);
+    std::fs::write(&file, json).unwrap();
+    let flags = format!("--config {}", file.display());
+    let err = ServerConfig::load(&args(&flags), no_env).unwrap_err();
+    assert!(err.contains(error), "{limit}: {err}");
+  }
+}
+
+#[tokio::test]
+async fn cors_allows_only_configured_origins() {
+  let dir = std::env::temp_dir().join(format!("branchy-cors-{}", std::process::id()));
+  let state = AppState::in_dir(&dir).unwrap();
+  let app = create_app_with_origins(state, &["https://a.example".to_string()]);
+  for (origin, allowed) in [("https://a.example", true), ("https://evil.example", false)] {
+    let req = Request::builder()
+      .uri("/health")
+      .header("origin", origin)
+      .body(Body::empty())
+      .unwrap();
+    let res = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let header = res.headers().get("access-control-allow-origin");
+    assert_eq!(header.is_some(), allowed, "{origin}");
+  }
+}
@@ -0,0 +1,411 @@
+//! The document served at `/openapi.json` must describe what the handlers actually do: every
+//! documented route is routed, and real requests and responses match the documented schemas.
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use branchy::server::{create_app, routes, ApiDoc, AppState};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use tower::util::ServiceExt;
+use utoipa::OpenApi;
+
+fn app(name: &str) -> axum::Router {
+  let dir = std::env::temp_dir().join(format!("branchy-openapi-{name}-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&dir);
+  create_app(AppState::in_dir(&dir).unwrap())
+}
+
+fn spec() -> Value {
+  serde_json::to_value(ApiDoc::openapi()).unwrap()
+}
+
+async fn send(
+  app: &axum::Router,
+  method: &str,
+  uri: &str,
+  body: Option<&Value>,
+) -> (StatusCode, String, Vec<u8>) {
+  let req = Request::builder()
+    .method(method)
+    .uri(uri)
+    .header("content-type", "application/json")
+    .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+    .unwrap();
+  let res = app.clone().oneshot(req).await.unwrap();
+  let status = res.status();
+  let content_type = res
+    .headers()
+    .get("content-type")
+    .map(|v| v.to_str().unwrap().to_string())
+    .unwrap_or_default();
+  let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+    .await
+    .unwrap();
+  (status, content_type, bytes.to_vec())
+}
+
+/// Follow `$ref`s to `#/components/schemas/...`.
+fn resolve<'a>(spec: &'a Value, schema: &'a Value) -> &'a Value {
+  schema["$ref"].as_str().map_or(schema, |r| {
+    let name = r.strip_prefix("#/components/schemas/").unwrap();
+    resolve(spec, &spec["components"]["schemas"][name])
+  })
+}
+
+/// Property names an object schema declares, through `allOf`/`oneOf`.
+fn declared(spec: &Value, schema: &Value) -> BTreeSet<String> {
+  let schema = resolve(spec, schema);
+  let mut keys: BTreeSet<String> = schema["properties"]
+    .as_object()
+    .map(|p| p.keys().cloned().collect())
+    .unwrap_or_default();
+  for combinator in ["allOf", "oneOf", "anyOf"] {
+    for sub in schema[combinator].as_array().into_iter().flatten() {
+      keys.extend(declared(spec, sub));
+    }
+  }
+  keys
+}
+
+fn type_matches(ty: &str, value: &Value) -> bool {
+  match ty {
+    "null" => value.is_null(),
+    "boolean" => value.is_boolean(),
+    "integer" => value.is_u64() || value.is_i64(),
+    "number" => value.is_number(),
+    "string" => value.is_string(),
+    "array" => value.is_array(),
+    "object" => value.is_object(),
+    other => panic!("unknown schema type {other}"),
+  }
+}
+
+/// Errors of `value` against `schema`. With `strict`, objects may only have declared properties
+/// (parts of an `allOf` are checked together, not one by one).
+fn validate(
+  spec: &Value,
+  schema: &Value,
+  value: &Value,
+  at: &str,
+  strict: bool,
+  errors: &mut Vec<String>,
+) {
+  let schema = resolve(spec, schema);
+  match &schema["type"] {
+    Value::String(ty) if !type_matches(ty, value) => {
+      errors.push(format!("{at}: expected {ty}, got {value}"));
+      return;
+    }
+    Value::Array(types)
+      if !types
+        .iter()
+        .any(|t| type_matches(t.as_str().unwrap(), value)) =>
+    {
+      errors.push(format!("{at}: expected one of {types:?}, got {value}"));
+      return;
+    }
+    _ => {}
+  }
+  if let Some(options) = schema["enum"].as_array() {
+    if !options.contains(value) {
+      errors.push(format!("{at}: {value} is not one of {options:?}"));
+    }
+  }
+  for sub in schema["allOf"].as_array().into_iter().flatten() {
+    validate(spec, sub, value, at, false, errors);
+  }
+  if let Some(options) = schema["oneOf"].as_array() {
+    let matching = options.iter().any(|sub| {
+      let mut sub_errors = Vec::new();
+      validate(spec, sub, value, at, strict, &mut sub_errors);
+      sub_errors.is_empty()
+    });
+    if !matching {
+      errors.push(format!("{at}: {value} matches no oneOf option"));
+    }
+  }
+  let Some(object) = value.as_object() else {
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+      for (i, item) in array.iter().enumerate() {
+        validate(spec, items, item, &format!("{at}[{i}]"), true, errors);
+      }
+    }
+    return;
+  };
+  for required in schema["required"].as_array().into_iter().flatten() {
+    if !object.contains_key(required.as_str().unwrap()) {
+      errors.push(format!("{at}: missing required {required}"));
+    }
+  }
+  if let Some(properties) = schema["properties"].as_object() {
+    for (key, sub) in properties {
+      if let Some(v) = object.get(key) {
+        validate(spec, sub, v, &format!("{at}.{key}"), true, errors);
+      }
+    }
+  }
+  if let Some(values) = schema.get("additionalProperties").filter(|v| v.is_object()) {
+    for (key, v) in object {
+      validate(spec, values, v, &format!("{at}.{key}"), true, errors);
+    }
+  } else if strict && (schema.get("properties").is_some() || schema.get("allOf").is_some()) {
+    let known = declared(spec, schema);
+    for key in object.keys().filter(|k| !known.contains(*k)) {
+      errors.push(format!("{at}: undocumented property {key}"));
+    }
+  }
+}
+
+fn assert_valid(spec: &Value, schema: &Value, value: &Value, what: &str) {
+  let mut errors = Vec::new();
+  validate(spec, schema, value, "$", true, &mut errors);
+  assert!(errors.is_empty(), "{what}: {errors:#?}\nvalue: {value}");
+}
+
+/// Spec path of `uri` (`/programs/x/run` matches `/programs/{name}/run`).
+fn spec_path<'a>(spec: &'a Value, uri: &str) -> &'a str {
+  let path = uri.split('?').next().unwrap();
+  spec["paths"]
+    .as_object()
+    .unwrap()
+    .keys()
+    .find(|p| {
+      let (a, b): (Vec<_>, Vec<_>) = (p.split('/').collect(), path.split('/').collect());
+      a.len() == b.len() && a.iter().zip(&b).all(|(x, y)| x == y || x.starts_with('{'))
+    })
+    .unwrap_or_else(|| panic!("{uri} is not documented"))
+}
+
+/// Send a request and check it against the spec: the body against the request schema, the
+/// status against the documented responses and a JSON response against its schema.
+async fn call(
+  spec: &Value,
+  app: &axum::Router,
+  method: &str,
+  uri: &str,
+  body: Option<Value>,
+) -> (StatusCode, Value) {
+  let operation = &spec["paths"][spec_path(spec, uri)][method.to_lowercase()];
+  assert!(operation.is_object(), "{method} {uri} is not documented");
+  if let Some(body) = &body {
+    let schema = &operation["requestBody"]["content"]["application/json"]["schema"];
+    assert_valid(spec, schema, body, &format!("request of {method} {uri}"));
+  }
+  let (status, content_type, bytes) = send(app, method, uri, body.as_ref()).await;
+  let response = &operation["responses"][status.as_str()];
+  assert!(
+    response.is_object(),
+    "{method} {uri} answered an undocumented {status}: {}",
+    String::from_utf8_lossy(&bytes)
+  );
+  if !content_type.starts_with("application/json") {
+    return (status, Value::Null);
+  }
+  let json: Value = serde_json::from_slice(&bytes).unwrap();
+  let schema = &response["content"]["application/json"]["schema"];
+  assert!(
+    schema.is_object(),
+    "{method} {uri} {status} has no documented JSON body"
+  );
+  assert_valid(spec, schema, &json, &format!("{method} {uri} {status}"));
+  (status, json)
+}
+
+#[tokio::test]
+async fn openapi_json_serves_the_spec() {
+  let app = app("serve");
+  let (status, _, bytes) = send(&app, "GET", "/openapi.json", None).await;
+  assert_eq!(status, StatusCode::OK);
+  let served: Value = serde_json::from_slice(&bytes).unwrap();
+  assert_eq!(served, spec());
+  assert!(served["openapi"].as_str().unwrap().starts_with("3."));
+  for name in [
+    "RunRequest",
+    "RunResponse",
+    "FormatRequest",
+    "ErrorResponse",
+  ] {
+    assert!(served["components"]["schemas"][name].is_object(), "{name}");
+  }
+}
+
+/// The spec documents exactly the routed operations.
+#[test]
+fn documented_operations_are_the_routed_ones() {
+  let spec = spec();
+  let documented: BTreeSet<(String, String)> = spec["paths"]
+    .as_object()
+    .unwrap()
+    .iter()
+    .flat_map(|(path, item)| {
+      let methods = item.as_object().unwrap().keys();
+      methods.map(move |method| (method.to_uppercase(), path.clone()))
+    })
+    .collect();
+  let routed: BTreeSet<(String, String)> = routes()
+    .into_iter()
+    .map(|(method, path)| {
+      let path = path
+        .split('/')
+        .map(|part| {
+          part
+            .strip_prefix(':')
+            .map_or_else(|| part.to_string(), |param| format!("{{{param}}}"))
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+      (method.to_string(), path)
+    })
+    .collect();
+  assert_eq!(documented, routed);
+}
+
+/// Every documented operation is routed and answers with a documented status.
+#[tokio::test]
+async fn every_documented_operation_is_routed() {
+  let spec = spec();
+  let app = app("routes");
+  for (path, item) in spec["paths"].as_object().unwrap() {
+    for method in item.as_object().unwrap().keys() {
+      let uri = path
+        .replace("{name}", "probe")
+        .replace("{version}", "1")
+        .replace("{id}", "0123456789");
+      let body = item[method]["requestBody"].is_object().then(|| json!({}));
+      let (status, _, bytes) = send(&app, &method.to_uppercase(), &uri, body.as_ref()).await;
+      assert_ne!(
+        status,
+        StatusCode::METHOD_NOT_ALLOWED,
+        "{method} {path} is not routed"
+      );
+      assert!(
+        status != StatusCode::NOT_FOUND || !bytes.is_empty(),
+        "{method} {path} is not routed"
+      );
+      assert!(
+        item[method]["responses"][status.as_str()].is_object(),
+        "{method} {path} answered an undocumented {status}"
+      );
+    }
+  }
+}
+
+#[tokio::test]
+async fn responses_match_documented_schemas() {
+  let spec = spec();
+  let app = app("schemas");
+  let files = json!({
+    "files": {
+      "main.branchy": "include \"lib.branchy\";\n[ !greet(world); ]",
+      "lib.branchy": "!greet(:who) = [ \"hi \" + :who; ];\n[ lib; ]",
+    },
+    "entry": "main.branchy",
+  });
+  for (method, uri, body, expected) in [
+    ("GET", "/health", None, 200),
+    ("GET", "/examples", None, 200),
+    ("GET", "/stdlib", None, 200),
+    (
+      "POST",
+      "/run",
+      Some(json!({ "source": "[ a; b; ]", "seed": 1, "vars": { "x": "1" }, "profile": true })),
+      200,
+    ),
+    ("POST", "/run", Some(files.clone()), 200),
+    ("POST", "/run", Some(json!({ "source": "[ a" })), 400),
+    (
+      "POST",
+      "/run",
+      Some(json!({ "source": "[ !missing(); ]" })),
+      422,
+    ),
+    (
+      "POST",
+      "/run/batch",
+      Some(json!({ "items": [{ "seed": 1 }, { "seed": 2, "input": "x" }], "source": "[ a; ]" })),
+      200,
+    ),
+    (
+      "POST",
+      "/run/batch",
+      Some(json!({ "count": 2, "source": "[ !missing(); ]" })),
+      200,
+    ),
+    ("POST", "/format", Some(json!({ "source": "[a;b;]" })), 200),
+    ("POST", "/format", Some(files.clone()), 200),
+    ("POST", "/check", Some(files.clone()), 200),
+    ("POST", "/analyze", Some(files.clone()), 200),
+    (
+      "POST",
+      "/analyze",
+      Some(json!({ "source": "!f() = [ x; ]\n~\"(\" = [ !nope(); ]\n[ !len(a); ]" })),
+      200,
+    ),
+    ("POST", "/analyze", Some(json!({ "source": "[ a" })), 200),
+    ("GET", "/cache", None, 200),
+    ("PUT", "/programs/demo", Some(files.clone()), 201),
+    (
+      "PUT",
+      "/programs/demo",
+      Some(json!({ "source": "[ b; ]" })),
+      201,
+    ),
+    ("GET", "/programs", None, 200),
+    ("GET", "/programs/demo", None, 200),
+    ("GET", "/programs/demo/versions/1", None, 200),
+    ("GET", "/programs/demo/versions/9", None, 404),
+    (
+      "POST",
+      "/programs/demo/run",
+      Some(json!({ "version": 1, "seed": 3 })),
+      200,
+    ),
+    ("DELETE", "/programs/demo", None, 204),
+    ("GET", "/programs/bad%20name", None, 400),
+    (
+      "POST",
+      "/share",
+      Some(json!({ "source": "[ a; ]", "seed": 5, "vars": { "x": "1" } })),
+      201,
+    ),
+    ("POST", "/share", Some(files.clone()), 201),
+    ("POST", "/share", Some(json!({ "source": "[ a" })), 400),
+    ("GET", "/share/ffffffffff", None, 404),
+    ("GET", "/metrics", None, 200),
+    ("GET", "/openapi.json", None, 200),
+  ] {
+    let (status, out) = call(&spec, &app, method, uri, body).await;
+    assert_eq!(status.as_u16(), expected, "{method} {uri}: {out}");
+  }
+  for source in ["[ a; b; ]", "[ !missing(); ]"] {
+    let body = json!({ "source": source, "input": "x" });
+    let (_, created) = call(&spec, &app, "POST", "/share", Some(body)).await;
+    let uri = format!("/share/{}", created["id"].as_str().unwrap());
+    let (status, out) = call(&spec, &app, "GET", &uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{out}");
+  }
+}
+
+#[tokio::test]
+async fn editor_responses_match_documented_schemas() {
+  let spec = spec();
+  let app = app("editor");
+  for (uri, source, line, column, expected) in [
+    ("/complete", "!f(:x) = [ :x; ]\n[ !f ]", 2, 5, 200),
+    (
+      "/complete",
+      "!f(:x) = [ :x; ]\n[ f :y { :x = a; : } ]",
+      2,
+      19,
+      200,
+    ),
+    ("/complete", "[ a; ]", 9, 1, 400),
+    ("/hover", "[ !len(a); ]", 1, 4, 200),
+    ("/hover", "!f(:x) = [ :x; ]\n[ f :y; ]", 2, 3, 200),
+    ("/hover", "[ a; ]", 1, 1, 200),
+  ] {
+    let body = json!({ "source": source, "line": line, "column": column });
+    let (status, out) = call(&spec, &app, "POST", uri, Some(body)).await;
+    assert_eq!(status.as_u16(), expected, "{uri} {source}: {out}");
+  }
+}
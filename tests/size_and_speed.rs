@@ -32,8 +32,8 @@ fn size_and_speed_comparison() {
 
   let builtins = default_registry();
 
-  let big_blocks_src = std::fs::read_to_string("examples/big_blocks.branchy")
-    .unwrap_or_else(|_| String::new());
+  let big_blocks_src =
+    std::fs::read_to_string("examples/big_blocks.branchy").unwrap_or_else(|_| String::new());
 
   let cases: Vec<(&str, &str)> = vec![
     ("small", small_src),
@@ -54,7 +54,11 @@ fn size_and_speed_comparison() {
   }
 }
 
-fn run_one(name: &str, src: &str, builtins: &std::collections::HashMap<String, branchy::builtins::BuiltinFn>) {
+fn run_one(
+  name: &str,
+  src: &str,
+  builtins: &std::collections::HashMap<String, branchy::builtins::BuiltinFn>,
+) {
   let program = parse_program(src).unwrap();
   let bytecode = serialize_program(&program).unwrap();
 
@@ -0,0 +1,91 @@
+mod common;
+
+use branchy::stdlib::{lookup, MODULES};
+use branchy::{parse_program, resolve_includes, Literal, Node};
+
+use common::run_with_seed;
+
+fn function_names(src: &str) -> Vec<String> {
+  let mut names: Vec<_> = parse_program(src)
+    .unwrap()
+    .functions
+    .into_iter()
+    .map(|f| f.name)
+    .collect();
+  names.sort();
+  names
+}
+
+#[test]
+fn stdlib_modules_parse_and_run() {
+  for (name, src) in MODULES {
+    let program = parse_program(src).unwrap_or_else(|e| panic!("std:{name}: {e}"));
+    for seed in 0..20 {
+      assert!(!run_with_seed(&program, seed).is_empty(), "std:{name}");
+    }
+  }
+}
+
+#[test]
+fn stdlib_language_variants_define_same_functions() {
+  for (name, src) in MODULES {
+    if let Some(rest) = name.strip_prefix("en/") {
+      let ru = lookup(&format!("std:ru/{rest}")).unwrap_or_else(|| panic!("no std:ru/{rest}"));
+      assert_eq!(function_names(src), function_names(ru), "{rest}");
+    }
+  }
+}
+
+/// Alternatives of the function `name` in `src`, which must all be plain words.
+fn words(src: &str, name: &str) -> Vec<String> {
+  let program = parse_program(src).unwrap();
+  let f = program.functions.iter().find(|f| f.name == name).unwrap();
+  let Node::Branch { children, .. } = &f.body else {
+    panic!("!{name} is not a branch");
+  };
+  children
+    .iter()
+    .map(|c| match c {
+      Node::Leaf {
+        lit: Literal::Ident(s),
+        ..
+      } => s.clone(),
+      other => panic!("!{name}: {other:?}"),
+    })
+    .collect()
+}
+
+#[test]
+fn stdlib_full_name_pairs_name_and_surname_of_one_gender() {
+  let names = lookup("std:ru/names").unwrap();
+  let surnames = lookup("std:ru/surnames").unwrap();
+  let female = words(names, "female_name");
+  let female_surnames = words(surnames, "female_surname");
+  let src = "include \"std:ru/surnames\";\n[ !full_name() ]\n";
+  let program =
+    resolve_includes(parse_program(src).unwrap(), |p| Err(format!("no file {p}"))).unwrap();
+  for seed in 0..50 {
+    let out = run_with_seed(&program, seed);
+    let (name, surname) = out.split_once(' ').unwrap();
+    assert_eq!(
+      female.iter().any(|n| n == name),
+      female_surnames.iter().any(|s| s == surname),
+      "{out}"
+    );
+  }
+}
+
+#[test]
+fn stdlib_resolves_without_files() {
+  let src = "include \"std:ru/names\";\ninclude \"std:ru/surnames.branchy\";\n[ !name() + \" \" + !surname() ]\n";
+  let program = parse_program(src).unwrap();
+  let program = resolve_includes(program, |p| Err(format!("no file {p}"))).unwrap();
+  let out = run_with_seed(&program, 7);
+  assert_eq!(out.split(' ').count(), 2, "{out}");
+  let err = resolve_includes(
+    parse_program("include \"std:nope\"; [ x; ]").unwrap(),
+    |p| Err(format!("no file {p}")),
+  )
+  .unwrap_err();
+  assert_eq!(err, "no file std:nope");
+}
@@ -102,3 +102,21 @@ fn trace_concat_two_spans() {
   assert_eq!(out, "ab");
   assert!(trace.len() >= 1, "concat involves multiple nodes");
 }
+
+#[test]
+fn span_text_rejects_zero_lines_and_columns() {
+  let source = "[ a; ]\n[ bc; ]";
+  let span = |start_line, start_column, end_line, end_column| branchy::Span {
+    start_line,
+    start_column,
+    end_line,
+    end_column,
+    file: 0,
+  };
+  assert_eq!(span(2, 3, 2, 5).text(source), Some("bc"));
+  assert_eq!(span(1, 1, 2, 2).text(source), Some("[ a; ]\n["));
+  assert_eq!(span(0, 0, 0, 0).text(source), None);
+  assert_eq!(span(0, 1, 1, 2).text(source), None);
+  assert_eq!(span(1, 0, 1, 2).text(source), None);
+  assert_eq!(span(3, 1, 3, 2).text(source), None);
+}
@@ -0,0 +1,28 @@
+use branchy::watch::Watcher;
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn watcher_reports_changed_files_once() {
+  let dir = std::env::temp_dir().join(format!("branchy-watch-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let main = dir.join("main.branchy");
+  let lib = dir.join("lib.branchy");
+  fs::write(&main, "[ a; ]\n").unwrap();
+  fs::write(&lib, "!f() = [ x; ]\n").unwrap();
+
+  let mut w = Watcher::new(vec![main.clone(), lib.clone(), main.clone()]);
+  assert_eq!(w.files().len(), 2);
+  assert!(w.changed().is_empty());
+
+  fs::write(&lib, "!f() = [ x; y; ]\n").unwrap();
+  assert_eq!(w.changed(), [lib]);
+  assert!(w.changed().is_empty());
+
+  w.poll = Duration::from_millis(10);
+  w.debounce = Duration::from_millis(50);
+  fs::remove_file(&main).unwrap();
+  assert_eq!(w.wait(), [main]);
+
+  fs::remove_dir_all(&dir).unwrap();
+}